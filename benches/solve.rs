@@ -0,0 +1,39 @@
+//! Measures the throughput of the search, in explored nodes per second, on
+//! random instances of a few sizes. The runs are deterministic so that all the
+//! iterations of a size explore the same nodes.
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use osrm_client::Location;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaChaRng;
+
+use tsptools::{instance::{Instance, Unit}, resolution::{SolveOptions, solve_instance}};
+
+/// A random asymmetric instance of `n` destinations whose distances are whole
+/// metres, drawn from the given seed
+fn random_instance(n: usize, seed: u64) -> Instance {
+    let mut rng = ChaChaRng::seed_from_u64(seed);
+    let destinations = (0..n).map(|i| Location { longitude: i as f32, latitude: 0.0 }).collect();
+    let distances = (0..n)
+        .map(|i| (0..n).map(|j| if i == j { 0.0 } else { rng.gen_range(1..1000) as f32 }).collect())
+        .collect::<Vec<_>>();
+    Instance::from_distances(destinations, distances.into(), Unit::Meters).unwrap()
+}
+
+fn explored_nodes_per_second(c: &mut Criterion) {
+    let options = SolveOptions::builder().width(50).deterministic(true).timeout(0).build().unwrap();
+    let mut group = c.benchmark_group("explored nodes");
+    group.sample_size(10);
+    for n in [10, 12, 14] {
+        let instance = random_instance(n, 126);
+        let nodes = solve_instance("bench", instance.clone(), &options).unwrap().explored_nodes;
+        group.throughput(Throughput::Elements(nodes as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(n), &instance, |b, instance| {
+            b.iter(|| solve_instance("bench", instance.clone(), &options).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, explored_nodes_per_second);
+criterion_main!(benches);
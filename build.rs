@@ -0,0 +1,18 @@
+//! Compiles the C program which checks the interface declared in
+//! `include/tsptools.h` (only with the `ffi` feature). The program is linked
+//! against the static library and run by `tests/ffi.rs`.
+
+use std::env;
+
+fn main() {
+    println!("cargo:rerun-if-changed=include/tsptools.h");
+    println!("cargo:rerun-if-changed=tests/ffi/harness.c");
+    if env::var_os("CARGO_FEATURE_FFI").is_none() {
+        return;
+    }
+    let mut build = cc::Build::new();
+    build.file("tests/ffi/harness.c").include("include").warnings_into_errors(true).cargo_metadata(false);
+    let objects = build.compile_intermediates();
+    println!("cargo:rustc-env=TSPTOOLS_HARNESS_OBJECT={}", objects[0].display());
+    println!("cargo:rustc-env=TSPTOOLS_CC={}", build.get_compiler().path().display());
+}
@@ -0,0 +1,49 @@
+/* C interface of the tsptools solver (built with the `ffi` feature). */
+#ifndef TSPTOOLS_H
+#define TSPTOOLS_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define TSP_OK               0
+#define TSP_NULL_POINTER     1
+#define TSP_INVALID_INSTANCE 2
+#define TSP_INVALID_OPTIONS  3
+#define TSP_NO_SOLUTION      4
+#define TSP_BUFFER_TOO_SMALL 5
+#define TSP_PANIC            6
+
+typedef struct TspInstance TspInstance;
+
+typedef struct TspOptions {
+    uint64_t width;        /* maximum number of nodes in a layer */
+    uint64_t timeout;      /* time limit in seconds, 0 means none */
+    int32_t  maximize;     /* non zero to look for the longest tour */
+    int32_t  deterministic;/* non zero to search on a single thread */
+} TspOptions;
+
+/* Creates an instance of n destinations from its row-major n*n distance matrix
+ * (INFINITY marks unreachable pairs) and optionally the longitude and latitude
+ * of each destination (2*n entries, may be NULL). Release it with
+ * tsp_instance_free. */
+int32_t tsp_instance_new(const double *distances, size_t n, const double *coordinates, TspInstance **out);
+
+void tsp_instance_free(TspInstance *instance);
+
+int32_t tsp_options_default(TspOptions *out);
+
+/* Solves the instance. The tour lists the destinations in visit order and ends
+ * with the return to the depot 0. When the buffer is too small, tour_len still
+ * receives the length of the tour and TSP_BUFFER_TOO_SMALL is returned. */
+int32_t tsp_solve(const TspInstance *instance, const TspOptions *options, size_t *tour, size_t capacity,
+                  size_t *tour_len, double *value, int32_t *is_exact);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,87 @@
+//! This module implements the command which anonymizes an instance so that it
+//! can be published without disclosing where its destinations are.
+
+use std::f64::consts::PI;
+
+use clap::Args;
+use osrm_client::Location;
+use rand::Rng;
+
+use crate::{instance::{Anonymization, Instance, Metadata}, status::fail};
+
+/// This command writes an anonymized copy of an instance: the labels and the
+/// extra fields of the destinations and the metadata telling where and how it was generated are
+/// dropped, and the coordinates are either removed (set to 0) or moved by a
+/// secret random rotation and translation with `--jitter`. The matrices, the
+/// depot and the forbidden or forced arcs are kept, hence the instance is solved
+/// exactly like the original one. The metadata records that it was anonymized.
+#[derive(Debug, Args)]
+pub struct Anonymize {
+    /// The path to the instance file
+    #[clap(short, long)]
+    pub instance: String,
+    /// Moves the coordinates of the destinations by a random rotation and
+    /// translation instead of removing them
+    #[clap(short, long)]
+    pub jitter: bool,
+    /// Name of the file where to write the anonymized instance (stdout by default)
+    #[clap(short, long)]
+    pub output: Option<String>,
+}
+
+impl Anonymize {
+    /// Executes this command
+    pub async fn execute(&self, quiet: bool) {
+        if let Err(e) = self.anonymize(quiet) {
+            fail(e);
+        }
+    }
+
+    fn anonymize(&self, quiet: bool) -> Result<(), String> {
+        let instance = Instance::load(&self.instance)?;
+        let (destinations, anonymization) = if self.jitter {
+            (jitter(&instance.destinations), Anonymization::Jittered)
+        } else {
+            (vec![Location { longitude: 0.0, latitude: 0.0 }; instance.destinations.len()], Anonymization::Removed)
+        };
+        if !quiet && instance.metadata.anonymization.is_some() {
+            eprintln!("warning: {} was already anonymized", self.instance);
+        }
+        let anonymized = Instance {
+            destinations,
+            labels: None,
+            extras: None,
+            metadata: Metadata { unit: instance.metadata.unit, anonymization: Some(anonymization), ..Default::default() },
+            ..instance
+        };
+        anonymized.save(self.output.as_deref())
+    }
+}
+
+/// Rotates the given locations by a random angle around their centroid and moves
+/// the centroid to a random place. The shape of the set of locations is kept
+/// (roughly, since the transformation is applied to the coordinates in degrees).
+fn jitter(locations: &[Location]) -> Vec<Location> {
+    if locations.is_empty() {
+        return vec![];
+    }
+    let mut rng = rand::thread_rng();
+    let n = locations.len() as f64;
+    let centroid = (
+        locations.iter().map(|l| l.longitude as f64).sum::<f64>() / n,
+        locations.iter().map(|l| l.latitude as f64).sum::<f64>() / n,
+    );
+    let angle = rng.gen_range(0.0..2.0 * PI);
+    let target = (rng.gen_range(-180.0..180.0), rng.gen_range(-60.0..60.0));
+    locations.iter()
+        .map(|l| {
+            let (x, y) = (l.longitude as f64 - centroid.0, l.latitude as f64 - centroid.1);
+            let longitude = target.0 + x * angle.cos() - y * angle.sin();
+            let latitude = target.1 + x * angle.sin() + y * angle.cos();
+            Location {
+                longitude: ((longitude + 180.0).rem_euclid(360.0) - 180.0) as f32,
+                latitude: latitude.clamp(-90.0, 90.0) as f32,
+            }
+        })
+        .collect()
+}
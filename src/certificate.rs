@@ -0,0 +1,300 @@
+//! This module defines the certificates of the solver runs, which back the claims
+//! of their reports (ie that a tour is optimal) with the data needed to check
+//! them independently, and the command which checks them.
+//!
+//! A certificate is a json document whose schema is stable (see `Certificate`,
+//! its `version` tells the schema). It holds the best tour of the run, its cost
+//! recomputed exactly on the matrix of the instance, the final bound of the
+//! solver and the digests of the instance and of the settings. It is only
+//! signed in the sense of being self-consistent: its `checksum` covers all of
+//! its other fields, which detects the accidental edits but not the forged
+//! certificates.
+
+use clap::Args;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::{instance::{Instance, Unit, checksum}, resolution::{ObjectiveSense, SCALE, SolveOptions}, status::{ExitStatus, fail}, stdio::{input_name, read_input}, tour::normalize_tour};
+
+/// The version of the schema of the certificates written by these tools
+pub const CERTIFICATE_VERSION: u32 = 1;
+
+/// The certificate of a solver run. The cost and the bound are expressed in the
+/// unit of the matrix which was optimized (not in the unit of the reports), and
+/// the tour starts at the depot (without returning to it).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Certificate {
+    /// The version of the schema of the certificate
+    pub version: u32,
+    /// The path to the instance that was solved
+    pub instance: String,
+    /// The digest of the instance (see `Instance::digest`)
+    pub digest: String,
+    /// The settings of the solver, as in the report of the run
+    pub settings: Value,
+    /// The checksum of the settings (of their compact json)
+    pub settings_digest: String,
+    /// Whether the shortest or the longest tour was looked for
+    pub objective: ObjectiveSense,
+    /// The label of the snapshot of durations which was optimized, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub departure: Option<String>,
+    /// The destination where the tour starts and ends
+    pub depot: usize,
+    /// The longest leg allowed in the tour, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_leg: Option<f64>,
+    /// The unit of the matrix which was optimized
+    pub unit: Unit,
+    /// The best tour found by the run
+    pub tour: Vec<usize>,
+    /// The cost of the tour, recomputed on the matrix
+    pub cost: f64,
+    /// The final bound of the solver on the cost of the optimal tour (a lower
+    /// bound when minimizing, an upper bound when maximizing)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bound: Option<f64>,
+    /// The largest difference between the cost of a tour and its cost for the
+    /// solver, which works on the matrix scaled and rounded to integers
+    pub tolerance: f64,
+    /// True iff the run completed its search, the tour being claimed optimal
+    pub is_exact: bool,
+    /// The checksum of the other fields of the certificate
+    pub checksum: String,
+}
+
+/// The outcome of a successful check of a certificate
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckedCertificate {
+    /// The cost of the tour, recomputed on the matrix
+    pub cost: f64,
+    pub bound: Option<f64>,
+    /// The distance between the cost of the tour and the bound
+    pub gap: Option<f64>,
+    /// True iff the certificate proves that the tour is optimal
+    pub is_optimal: bool,
+}
+
+impl Certificate {
+    /// Certifies the given tour of the instance as it was solved (the departure,
+    /// the start and the longest leg of the settings being already applied to
+    /// it), along with the final bound of the solver (in the unit of the matrix)
+    pub fn new(name: &str, digest: &str, settings: &SolveOptions, solved: &Instance, tour: &[usize], bound: Option<f64>, is_exact: bool) -> Result<Self, String> {
+        let settings = serde_json::to_value(settings).map_err(|e| e.to_string())?;
+        let tour = normalize_tour(tour, solved.destinations.len(), solved.depot)?;
+        let mut certificate = Certificate {
+            version: CERTIFICATE_VERSION,
+            instance: name.to_string(),
+            digest: digest.to_string(),
+            settings_digest: checksum(settings.to_string().as_bytes()),
+            objective: serde_json::from_value(settings["objective"].clone()).map_err(|e| e.to_string())?,
+            departure: settings["departure"].as_str().map(str::to_string),
+            depot: solved.depot,
+            max_leg: settings["max_leg"].as_f64(),
+            unit: serde_json::from_value(settings["unit"].clone()).unwrap_or(solved.metadata.unit),
+            cost: solved.tour_length(&tour),
+            tour,
+            bound,
+            tolerance: tolerance(solved.destinations.len()),
+            is_exact,
+            settings,
+            checksum: String::new(),
+        };
+        certificate.checksum = certificate.content_checksum();
+        Ok(certificate)
+    }
+
+    /// The checksum of all the fields of the certificate but its checksum
+    fn content_checksum(&self) -> String {
+        let content = Certificate { checksum: String::new(), ..self.clone() };
+        checksum(serde_json::to_string(&content).unwrap().as_bytes())
+    }
+
+    /// Checks this certificate against the given instance: the certificate must
+    /// be intact and issued for this instance, its tour must be a valid tour of
+    /// the matrix which was optimized and have the recorded cost, and the bound
+    /// must not contradict that cost. A certificate claiming that the tour is
+    /// optimal must also have closed the gap (up to the tolerance).
+    pub fn check(&self, instance: &Instance) -> Result<CheckedCertificate, String> {
+        if self.version != CERTIFICATE_VERSION {
+            return Err(format!("the certificate has version {} but these tools check version {CERTIFICATE_VERSION}", self.version));
+        }
+        if self.checksum != self.content_checksum() {
+            return Err("the checksum of the certificate does not match its content (it was edited)".to_string());
+        }
+        if self.settings_digest != checksum(self.settings.to_string().as_bytes()) {
+            return Err("the digest of the settings does not match the settings".to_string());
+        }
+        if self.digest != instance.digest() {
+            return Err(format!("the certificate was issued for another instance (digest {} instead of {})", self.digest, instance.digest()));
+        }
+
+        let mut instance = instance.clone();
+        if let Some(departure) = self.departure.as_ref() {
+            instance.distances = instance.durations_at(departure)?.clone();
+        }
+        let n = instance.destinations.len();
+        if self.depot >= n {
+            return Err(format!("the depot {} is not a destination of the instance", self.depot));
+        }
+        let tour = normalize_tour(&self.tour, n, self.depot)?;
+        if tour != self.tour {
+            return Err(format!("the tour does not start at the depot {}", self.depot));
+        }
+        if let Some((from, to)) = instance.disallowed_leg(&tour) {
+            return Err(format!("the tour uses the leg from {from} to {to} which is forbidden or contradicts a forced arc"));
+        }
+        let legs = tour.iter().zip(tour.iter().cycle().skip(1)).filter(|(from, to)| from != to);
+        if let Some((from, to)) = legs.clone().find(|(from, to)| !instance.distances[**from][**to].is_finite()) {
+            return Err(format!("the tour uses the leg from {from} to {to} between two destinations that cannot reach one another"));
+        }
+        if let Some(max) = self.max_leg {
+            if let Some((from, to)) = legs.clone().find(|(from, to)| instance.distances[**from][**to] as f64 > max) {
+                return Err(format!("the tour uses the leg from {from} to {to} which is longer than {max}"));
+            }
+        }
+
+        let cost = instance.tour_length(&tour);
+        if (cost - self.cost).abs() > 1e-9 * cost.abs().max(1.0) {
+            return Err(format!("the tour costs {cost} but the certificate records {}", self.cost));
+        }
+        let tolerance = tolerance(n).max(self.tolerance);
+        let gap = self.bound.map(|bound| match self.objective {
+            ObjectiveSense::Min => cost - bound,
+            ObjectiveSense::Max => bound - cost,
+        });
+        match gap {
+            Some(gap) if gap < -tolerance => return Err(format!("the bound {} contradicts the cost {cost} of the tour", self.bound.unwrap())),
+            None if self.is_exact => return Err("the certificate claims that the tour is optimal but records no bound".to_string()),
+            Some(gap) if self.is_exact && gap > tolerance => return Err(format!("the certificate claims that the tour is optimal but its gap is {gap}")),
+            _ => {}
+        }
+        Ok(CheckedCertificate { cost, bound: self.bound, gap: gap.map(|gap| gap.max(0.0)), is_optimal: gap.is_some_and(|gap| gap <= tolerance) })
+    }
+}
+
+/// The largest difference between the cost of a tour of `n` destinations and its
+/// cost for the solver: each leg is rounded by less than one unit of the scaled
+/// costs
+fn tolerance(n: usize) -> f64 {
+    (n + 1) as f64 / SCALE as f64
+}
+
+/// This command checks a certificate written by `solve --certificate` against the
+/// instance it was issued for: it recomputes the cost of the tour on the matrix
+/// of the instance and checks it against the recorded cost and bound. It exits
+/// with status 1 when the certificate does not hold.
+#[derive(Debug, Args)]
+pub struct CheckCertificate {
+    /// The path to the certificate
+    #[clap(short, long)]
+    pub certificate: String,
+    /// The path to the instance file (the one recorded by the certificate by
+    /// default)
+    #[clap(short, long)]
+    pub instance: Option<String>,
+}
+
+impl CheckCertificate {
+    /// Executes this command. In quiet mode, the outcome is printed as json.
+    pub async fn execute(&self, quiet: bool) {
+        let certificate = self.load().unwrap_or_else(|e| fail(e));
+        let path = self.instance.as_deref().unwrap_or(&certificate.instance);
+        let instance = Instance::load(path).unwrap_or_else(|e| fail(e));
+        match certificate.check(&instance) {
+            Ok(checked) if quiet => println!("{}", json!({"valid": true, "outcome": checked})),
+            Ok(checked) => {
+                let symbol = serde_json::to_value(certificate.unit).ok().and_then(|v| v.as_str().map(str::to_string)).unwrap_or_default();
+                println!("valid certificate of {}", input_name(path));
+                println!("cost  {} {symbol}", checked.cost);
+                if let Some(bound) = checked.bound {
+                    println!("bound {bound} {symbol}");
+                }
+                match checked.gap {
+                    _ if checked.is_optimal => println!("the tour is proven optimal"),
+                    Some(gap)               => println!("the tour is within {gap} {symbol} of the optimum"),
+                    None                    => println!("the tour is not proven optimal"),
+                }
+            }
+            Err(e) => {
+                if quiet {
+                    println!("{}", json!({"valid": false, "error": e}));
+                }
+                eprintln!("invalid certificate: {e}");
+                ExitStatus::Mismatch.exit()
+            }
+        }
+    }
+
+    fn load(&self) -> Result<Certificate, String> {
+        let name = input_name(&self.certificate);
+        serde_json::from_str(&read_input(&self.certificate)?).map_err(|e| format!("{name}: invalid certificate: {e}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use osrm_client::Location;
+
+    use crate::{instance::{Instance, Unit}, resolution::SolveOptions};
+
+    use super::Certificate;
+
+    fn instance() -> Instance {
+        let destinations = (0..4).map(|i| Location { longitude: i as f32, latitude: 0.0 }).collect();
+        let distances = vec![
+            vec![0.0, 2.0, 9.0, 10.0],
+            vec![1.0, 0.0, 6.0, 4.0],
+            vec![15.0, 7.0, 0.0, 8.0],
+            vec![6.0, 3.0, 12.0, 0.0],
+        ];
+        Instance::from_distances(destinations, distances.into(), Unit::Kilometers).unwrap()
+    }
+
+    /// The certificate of the tour 0 1 3 2 (which costs 2 + 4 + 12 + 15 = 33)
+    /// with the given bound
+    fn certificate(instance: &Instance, bound: f64, is_exact: bool) -> Certificate {
+        Certificate::new("instance.json", &instance.digest(), &SolveOptions::default(), instance, &[1, 3, 2, 0], Some(bound), is_exact).unwrap()
+    }
+
+    #[test]
+    fn a_valid_certificate_holds() {
+        let instance = instance();
+        let checked = certificate(&instance, 33.0, true).check(&instance).unwrap();
+        assert_eq!(checked.cost, 33.0);
+        assert!(checked.is_optimal);
+    }
+
+    #[test]
+    fn an_edited_certificate_fails_its_checksum() {
+        let instance = instance();
+        let mut edited = certificate(&instance, 33.0, true);
+        edited.cost = 30.0;
+        assert!(edited.check(&instance).unwrap_err().contains("checksum"));
+    }
+
+    #[test]
+    fn a_bound_contradicting_the_cost_is_rejected() {
+        let instance = instance();
+        let mut contradicted = certificate(&instance, 33.0, false);
+        contradicted.bound = Some(35.0);
+        contradicted.checksum = contradicted.content_checksum();
+        assert!(contradicted.check(&instance).unwrap_err().contains("contradicts the cost"));
+    }
+
+    #[test]
+    fn an_exact_certificate_with_a_gap_is_rejected() {
+        let instance = instance();
+        let error = certificate(&instance, 30.0, true).check(&instance).unwrap_err();
+        assert!(error.contains("claims that the tour is optimal but its gap is"));
+    }
+
+    #[test]
+    fn a_certificate_of_another_instance_is_rejected() {
+        let instance = instance();
+        let mut other = instance.clone();
+        other.distances[2][0] = 14.0;
+        let error = certificate(&instance, 33.0, true).check(&other).unwrap_err();
+        assert!(error.contains("issued for another instance"));
+    }
+}
@@ -0,0 +1,138 @@
+//! This module implements the command which compares two tours of an instance.
+
+use clap::Args;
+use serde::Serialize;
+
+use crate::{instance::{Instance, Unit}, status::fail, tour::{read_tour_with_digest, normalize_tour, check_digest}};
+
+/// This command compares two tours over the same instance: their lengths, the
+/// edges they have in common and their longest common sub-path.
+#[derive(Debug, Args)]
+pub struct Compare {
+    /// The path to the instance file
+    #[clap(short, long)]
+    pub instance: String,
+    /// The first tour (solve report, TSPLIB tour or list of destinations)
+    #[clap(short, long)]
+    pub a: String,
+    /// The second tour (solve report, TSPLIB tour or list of destinations)
+    #[clap(short, long)]
+    pub b: String,
+    /// Print the comparison as json
+    #[clap(short, long)]
+    pub json: bool,
+    /// Overrides the unit of the distance matrix declared by the instance
+    #[clap(long, value_enum)]
+    pub unit: Option<Unit>,
+    /// Drops the destinations of the instance which have no row in its distance
+    /// matrix rather than rejecting it
+    #[clap(long)]
+    pub truncate_to_matrix: bool,
+}
+
+/// The outcome of the comparison of two tours
+#[derive(Debug, Clone, Serialize)]
+pub struct Comparison {
+    pub length_a: f64,
+    pub length_b: f64,
+    /// length_b - length_a
+    pub difference: f64,
+    /// (length_b - length_a) / length_a, none when tour a has no length
+    pub relative_difference: Option<f64>,
+    pub unit: String,
+    /// The number of (undirected) edges used by both tours
+    pub common_edges: usize,
+    /// The longest sequence of destinations visited consecutively by both tours
+    /// (in either direction)
+    pub longest_common_path: Vec<usize>,
+}
+
+impl Compare {
+    /// Executes this command
+    pub async fn execute(&self, quiet: bool) {
+        match self.compare() {
+            Ok(cmp) if self.json || quiet => println!("{}", serde_json::to_string_pretty(&cmp).unwrap()),
+            Ok(cmp) => {
+                println!("length a            {:.3} {}", cmp.length_a, cmp.unit);
+                println!("length b            {:.3} {}", cmp.length_b, cmp.unit);
+                match cmp.relative_difference {
+                    Some(relative) => println!("difference          {:.3} {} ({:.2}%)", cmp.difference, cmp.unit, 100.0 * relative),
+                    None           => println!("difference          {:.3} {}", cmp.difference, cmp.unit),
+                }
+                println!("common edges        {}", cmp.common_edges);
+                let path = cmp.longest_common_path.iter().map(|d| d.to_string()).collect::<Vec<_>>();
+                println!("longest common path {} ({} destinations)", path.join(" "), path.len());
+            }
+            Err(e) => fail(e),
+        }
+    }
+
+    fn compare(&self) -> Result<Comparison, String> {
+        let instance = Instance::load_with(&self.instance, self.truncate_to_matrix)?;
+        let n = instance.distances.len();
+        let digest = instance.digest();
+        let read = |spec: &str| -> Result<Vec<usize>, String> {
+            let (tour, tour_digest) = read_tour_with_digest(spec)?;
+            check_digest(tour_digest.as_ref(), &digest).map_err(|e| format!("{spec}: {e}"))?;
+            normalize_tour(&tour, n, instance.depot).map_err(|e| format!("{spec}: {e}"))
+        };
+        let a = read(&self.a)?;
+        let b = read(&self.b)?;
+
+        let unit = self.unit.unwrap_or(instance.metadata.unit);
+        let (length_a, symbol) = unit.report(instance.tour_length(&a));
+        let (length_b, _) = unit.report(instance.tour_length(&b));
+
+        Ok(Comparison {
+            length_a,
+            length_b,
+            difference: length_b - length_a,
+            relative_difference: Some(length_a).filter(|a| *a != 0.0).map(|a| (length_b - a) / a),
+            unit: symbol.to_string(),
+            common_edges: common_edges(&a, &b),
+            longest_common_path: longest_common_path(&a, &b),
+        })
+    }
+}
+
+/// The undirected edges of a closed tour
+fn edges(tour: &[usize]) -> Vec<(usize, usize)> {
+    let mut edges = tour.iter().zip(tour.iter().cycle().skip(1))
+        .map(|(x, y)| (*x.min(y), *x.max(y)))
+        .collect::<Vec<_>>();
+    edges.sort_unstable();
+    edges
+}
+
+/// Counts the undirected edges that are used by both tours
+fn common_edges(a: &[usize], b: &[usize]) -> usize {
+    let b = edges(b);
+    edges(a).iter().filter(|e| b.binary_search(e).is_ok()).count()
+}
+
+/// Finds the longest sequence of destinations that both closed tours visit
+/// consecutively, b being possibly traversed in the opposite direction
+fn longest_common_path(a: &[usize], b: &[usize]) -> Vec<usize> {
+    let n = a.len();
+    let reversed = b.iter().rev().copied().collect::<Vec<_>>();
+    let mut best: &[usize] = &[];
+    let aa = [a, a].concat();
+    for b in [b.to_vec(), reversed] {
+        let bb = [b.as_slice(), b.as_slice()].concat();
+        // longest common substring of the doubled sequences, capped to n
+        let mut prev = vec![0_usize; bb.len() + 1];
+        for i in 1..=aa.len() {
+            let mut cur = vec![0_usize; bb.len() + 1];
+            for j in 1..=bb.len() {
+                if aa[i - 1] == bb[j - 1] {
+                    cur[j] = (prev[j - 1] + 1).min(n);
+                    if cur[j] > best.len() {
+                        best = &aa[i - cur[j]..i];
+                    }
+                }
+            }
+            prev = cur;
+        }
+    }
+    best.to_vec()
+}
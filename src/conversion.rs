@@ -0,0 +1,61 @@
+//! This module implements the command which converts instances between the
+//! supported file formats: json, binary, TSPLIB and csv matrices.
+
+use clap::Args;
+
+use crate::{instance::{Instance, InstanceFormat, Unit}, status::fail, stdio::{input_name, is_stdio, output_name}};
+
+/// This command converts an instance file to another format. The formats are
+/// inferred from the extensions of the files (`.tspb` for the binary format,
+/// `.tsp` or `.atsp` for TSPLIB, `.csv` for a bare distance matrix and json
+/// otherwise) unless they are given with `--from-format` and `--to-format`.
+/// The files may be compressed with gzip (`.gz`), except for the binary ones.
+/// With `-`, the instance is read from stdin or written on stdout (in json
+/// unless another format is given).
+/// All the commands read all the formats. A warning tells what the target
+/// format cannot hold and is dropped by the conversion.
+#[derive(Debug, Args)]
+pub struct Convert {
+    /// The path to the instance file
+    #[clap(short, long, visible_alias = "instance", visible_short_alias = 'i')]
+    pub from: String,
+    /// The path of the converted instance
+    #[clap(short, long, visible_alias = "output", visible_short_alias = 'o')]
+    pub to: String,
+    /// The format of the instance file (inferred from its extension by default)
+    #[clap(long, value_enum)]
+    pub from_format: Option<InstanceFormat>,
+    /// The format of the converted instance (inferred from its extension by default)
+    #[clap(long, value_enum)]
+    pub to_format: Option<InstanceFormat>,
+    /// The unit of the distances of a csv matrix, which the file does not tell
+    #[clap(long, value_enum, default_value_t = Unit::Meters)]
+    pub unit: Unit,
+}
+
+impl Convert {
+    /// Executes this command
+    pub async fn execute(&self, quiet: bool) {
+        if let Err(e) = self.convert(quiet) {
+            fail(e);
+        }
+    }
+
+    fn convert(&self, quiet: bool) -> Result<(), String> {
+        let mut instance = Instance::load_as(&self.from, self.from_format, false)?;
+        if self.from_format.unwrap_or_else(|| InstanceFormat::of(&self.from)) == InstanceFormat::Csv {
+            instance.metadata.unit = self.unit;
+        }
+        let format = self.to_format.unwrap_or_else(|| InstanceFormat::of(&self.to));
+        let lost = format.lost(&instance);
+        instance.save_as(Some(&self.to), format)?;
+        if !quiet {
+            if !lost.is_empty() {
+                eprintln!("warning: the {format:?} format cannot hold {}: they are dropped", lost.join(", "));
+            }
+            let message = format!("converted {} to {format:?} in {}", input_name(&self.from), output_name(&self.to));
+            if is_stdio(&self.to) { eprintln!("{message}") } else { println!("{message}") }
+        }
+        Ok(())
+    }
+}
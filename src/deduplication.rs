@@ -0,0 +1,104 @@
+//! This module implements the command which merges the destinations of an
+//! instance that are (nearly) identical.
+
+use clap::Args;
+use serde_json::Value;
+
+use crate::{geometry::haversine, instance::Instance, status::fail};
+
+/// This command merges the destinations of an instance which are closer than a
+/// threshold into a single representative (the first one in instance order) and
+/// writes the reduced instance. The depot is never merged away.
+#[derive(Debug, Args)]
+pub struct Dedupe {
+    /// The path to the instance file
+    #[clap(short, long)]
+    pub instance: String,
+    /// Merge the destinations which are less than this many metres apart (as the crow flies)
+    #[clap(short, long)]
+    pub radius: Option<f64>,
+    /// Merge the destinations whose distances to one another (in both directions,
+    /// in the unit of the matrix) are below this threshold
+    #[clap(short, long)]
+    pub matrix_threshold: Option<f32>,
+    /// Label each representative with the labels of all the destinations merged
+    /// into it, its extra fields becoming the list of theirs
+    #[clap(long)]
+    pub merge_labels: bool,
+    /// Name of the file where to write the reduced instance (stdout by default)
+    #[clap(short, long)]
+    pub output: Option<String>,
+}
+
+impl Dedupe {
+    /// Executes this command. The merges are reported on stderr unless in quiet mode.
+    pub async fn execute(&self, quiet: bool) {
+        if let Err(e) = self.dedupe(quiet) {
+            fail(e);
+        }
+    }
+
+    fn dedupe(&self, quiet: bool) -> Result<(), String> {
+        if self.radius.is_none() && self.matrix_threshold.is_none() {
+            return Err("either a radius or a matrix threshold is required".to_string());
+        }
+        let instance = Instance::load(&self.instance)?;
+        if self.radius.is_some() && !instance.has_coordinates() {
+            return Err(format!("{} has no coordinates (it is anonymized or was built from a bare matrix), use a matrix threshold", self.instance));
+        }
+        let groups = self.groups(&instance);
+
+        let representatives = groups.iter().map(|g| g[0]).collect::<Vec<_>>();
+        let mut reduced = instance.subset(&representatives);
+        if self.merge_labels {
+            if let Some(labels) = instance.labels.as_ref() {
+                reduced.labels = Some(groups.iter()
+                    .map(|g| g.iter().map(|d| labels[*d].as_str()).collect::<Vec<_>>().join(" / "))
+                    .collect());
+            }
+            if let Some(extras) = instance.extras.as_ref() {
+                reduced.extras = Some(groups.iter()
+                    .map(|g| if g.len() > 1 { Value::Array(g.iter().map(|d| extras[*d].clone()).collect()) } else { extras[g[0]].clone() })
+                    .collect());
+            }
+        }
+
+        if !quiet {
+            let name = |d: usize| match instance.labels.as_ref() {
+                Some(labels) => format!("{d} ({})", labels[d]),
+                None => d.to_string(),
+            };
+            for (new, group) in groups.iter().enumerate().filter(|(_, g)| g.len() > 1) {
+                let merged = group[1..].iter().map(|d| name(*d)).collect::<Vec<_>>().join(", ");
+                eprintln!("merged {merged} into {} (now {new})", name(group[0]));
+            }
+            eprintln!("{} destinations merged, {} left", instance.destinations.len() - groups.len(), groups.len());
+        }
+        reduced.save(self.output.as_deref())
+    }
+
+    /// Groups the destinations: each destination joins the group of the first
+    /// representative it is close to, or becomes the representative of a new group.
+    /// Since the depot is grouped first, it is always the representative of its
+    /// group. The groups are sorted by representative.
+    fn groups(&self, instance: &Instance) -> Vec<Vec<usize>> {
+        let close = |a: usize, b: usize| {
+            let near = self.radius
+                .is_some_and(|r| haversine(instance.destinations[a], instance.destinations[b]) < r);
+            let cheap = self.matrix_threshold
+                .is_some_and(|t| instance.distances[a][b] < t && instance.distances[b][a] < t);
+            near || cheap
+        };
+
+        let mut groups: Vec<Vec<usize>> = vec![];
+        let depot = instance.depot;
+        for d in std::iter::once(depot).chain((0..instance.destinations.len()).filter(|d| *d != depot)) {
+            match groups.iter_mut().find(|g| close(g[0], d)) {
+                Some(group) => group.push(d),
+                None => groups.push(vec![d]),
+            }
+        }
+        groups.sort_unstable_by_key(|g| g[0]);
+        groups
+    }
+}
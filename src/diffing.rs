@@ -0,0 +1,248 @@
+//! This module implements the command which tells what changed between two
+//! versions of an instance (typically before and after a map update).
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use clap::Args;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{geometry::haversine, instance::{Instance, Matrix}, refresh::{MatrixChanges, matrix_changes}, status::{ExitStatus, fail}};
+
+/// This command compares two versions of an instance: the destinations which
+/// were added, removed or moved, the distance matrix entries which changed and
+/// the differences of their metadata. The destinations are matched by label
+/// when both instances have labels, by nearest coordinates when both have
+/// coordinates, and by index otherwise. No destination is reported as moved
+/// when either instance has no coordinates. With `--exit-code`, the command
+/// exits with status 1 when some difference exceeds the thresholds, which makes
+/// it usable as a guard on a set of benchmark instances.
+#[derive(Debug, Args)]
+pub struct Diff {
+    /// The path to the old instance file
+    #[clap(short, long)]
+    pub a: String,
+    /// The path to the new instance file
+    #[clap(short, long)]
+    pub b: String,
+    /// The matched destinations which are further apart than this distance (in
+    /// metres) are reported as moved
+    #[clap(short, long, default_value = "1")]
+    pub radius: f64,
+    /// When the destinations are matched by coordinates, a destination is only
+    /// matched with a destination of the other instance within this distance (in
+    /// metres)
+    #[clap(long, default_value = "100")]
+    pub match_radius: f64,
+    /// The matrix entries which changed by more than this tolerance (in the unit
+    /// of the matrix) are counted
+    #[clap(short, long, default_value = "0")]
+    pub tolerance: f32,
+    /// The metadata fields which are not compared
+    #[clap(long, value_delimiter = ',', default_value = "generated_at")]
+    pub ignore: Vec<String>,
+    /// Exit with status 1 when some difference exceeds the thresholds
+    #[clap(long)]
+    pub exit_code: bool,
+    /// Print the differences as json
+    #[clap(short, long)]
+    pub json: bool,
+}
+
+/// How the destinations of the two instances were matched
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Matching {
+    Labels,
+    Coordinates,
+    Indices,
+}
+
+/// A destination of the old instance matched with a destination of the new one
+/// at another place
+#[derive(Debug, Clone, Serialize)]
+pub struct Move {
+    /// The index of the destination in the old instance
+    pub a: usize,
+    /// The index of the destination in the new instance
+    pub b: usize,
+    /// The distance between the two places in metres
+    pub distance: f64,
+}
+
+/// A metadata field whose value differs between the two instances (null when it
+/// is missing)
+#[derive(Debug, Clone, Serialize)]
+pub struct MetadataChange {
+    pub field: String,
+    pub a: Value,
+    pub b: Value,
+}
+
+/// The differences between two versions of an instance
+#[derive(Debug, Clone, Serialize)]
+pub struct InstanceDiff {
+    pub matching: Matching,
+    pub nb_destinations_a: usize,
+    pub nb_destinations_b: usize,
+    /// The destinations of the new instance which match none of the old one
+    pub added: Vec<usize>,
+    /// The destinations of the old instance which match none of the new one
+    pub removed: Vec<usize>,
+    pub moved: Vec<Move>,
+    /// The changes of the distances between the matched destinations
+    pub matrix: MatrixChanges,
+    pub metadata: Vec<MetadataChange>,
+}
+
+impl InstanceDiff {
+    /// Returns true iff some difference exceeds the thresholds
+    pub fn is_significant(&self) -> bool {
+        !self.added.is_empty()
+            || !self.removed.is_empty()
+            || !self.moved.is_empty()
+            || self.matrix.nb_changed > 0
+            || self.matrix.nb_reachability_changes > 0
+            || !self.metadata.is_empty()
+    }
+}
+
+impl Diff {
+    /// Executes this command. In quiet mode, the differences are printed as json.
+    pub async fn execute(&self, quiet: bool) {
+        match self.diff() {
+            Ok(diff) => {
+                if self.json || quiet {
+                    println!("{}", serde_json::to_string_pretty(&diff).unwrap());
+                } else {
+                    self.print(&diff);
+                }
+                if self.exit_code && diff.is_significant() {
+                    ExitStatus::Mismatch.exit();
+                }
+            }
+            Err(e) => fail(e),
+        }
+    }
+
+    fn print(&self, diff: &InstanceDiff) {
+        let list = |indices: &[usize]| indices.iter().map(|d| d.to_string()).collect::<Vec<_>>().join(" ");
+        println!("matching          {:?}", diff.matching);
+        println!("destinations      {} -> {}", diff.nb_destinations_a, diff.nb_destinations_b);
+        println!("added             {} {}", diff.added.len(), list(&diff.added));
+        println!("removed           {} {}", diff.removed.len(), list(&diff.removed));
+        println!("moved             {} (by more than {} m)", diff.moved.len(), self.radius);
+        for m in diff.moved.iter() {
+            println!("  {} -> {} by {:.1} m", m.a, m.b, m.distance);
+        }
+        println!("changed entries   {} (by more than {})", diff.matrix.nb_changed, self.tolerance);
+        println!("max change        {}", diff.matrix.max_change);
+        println!("mean change       {:.3}", diff.matrix.mean_change);
+        println!("reachability      {} changes", diff.matrix.nb_reachability_changes);
+        println!("metadata          {} changes", diff.metadata.len());
+        for change in diff.metadata.iter() {
+            println!("  {}: {} -> {}", change.field, change.a, change.b);
+        }
+    }
+
+    fn diff(&self) -> Result<InstanceDiff, String> {
+        let a = Instance::load(&self.a)?;
+        let b = Instance::load(&self.b)?;
+        let located = a.has_coordinates() && b.has_coordinates();
+        let (matching, pairs) = match (a.labels.as_ref(), b.labels.as_ref()) {
+            (Some(la), Some(lb)) => (Matching::Labels, match_labels(la, lb)),
+            _ if located => (Matching::Coordinates, self.match_coordinates(&a, &b)),
+            _ => (Matching::Indices, (0..a.destinations.len().min(b.destinations.len())).map(|i| (i, i)).collect()),
+        };
+
+        let mut matched_a = vec![false; a.destinations.len()];
+        let mut matched_b = vec![false; b.destinations.len()];
+        for &(i, j) in pairs.iter() {
+            matched_a[i] = true;
+            matched_b[j] = true;
+        }
+        let moved = if !located {
+            vec![]
+        } else {
+            pairs.iter()
+                .map(|&(i, j)| Move { a: i, b: j, distance: haversine(a.destinations[i], b.destinations[j]) })
+                .filter(|m| m.distance > self.radius)
+                .collect()
+        };
+        let old = pairs.iter().map(|&(i, _)| pairs.iter().map(|&(k, _)| a.distances[i][k]).collect()).collect::<Matrix>();
+        let new = pairs.iter().map(|&(_, j)| pairs.iter().map(|&(_, l)| b.distances[j][l]).collect()).collect::<Matrix>();
+
+        Ok(InstanceDiff {
+            matching,
+            nb_destinations_a: a.destinations.len(),
+            nb_destinations_b: b.destinations.len(),
+            added: (0..b.destinations.len()).filter(|j| !matched_b[*j]).collect(),
+            removed: (0..a.destinations.len()).filter(|i| !matched_a[*i]).collect(),
+            moved,
+            matrix: matrix_changes(&old, &new, self.tolerance),
+            metadata: self.metadata_changes(&a, &b),
+        })
+    }
+
+    /// Matches the destinations greedily by increasing distance, each one with a
+    /// destination of the other instance within the matching radius
+    fn match_coordinates(&self, a: &Instance, b: &Instance) -> Vec<(usize, usize)> {
+        let mut candidates = vec![];
+        for (i, x) in a.destinations.iter().enumerate() {
+            for (j, y) in b.destinations.iter().enumerate() {
+                let distance = haversine(*x, *y);
+                if distance <= self.match_radius {
+                    candidates.push((distance, i, j));
+                }
+            }
+        }
+        candidates.sort_by(|x, y| x.0.total_cmp(&y.0));
+
+        let mut matched_a = vec![false; a.destinations.len()];
+        let mut matched_b = vec![false; b.destinations.len()];
+        let mut pairs = vec![];
+        for (_, i, j) in candidates {
+            if !matched_a[i] && !matched_b[j] {
+                matched_a[i] = true;
+                matched_b[j] = true;
+                pairs.push((i, j));
+            }
+        }
+        pairs.sort_unstable();
+        pairs
+    }
+
+    /// Lists the metadata fields which differ (except the ignored ones)
+    fn metadata_changes(&self, a: &Instance, b: &Instance) -> Vec<MetadataChange> {
+        let fields = |instance: &Instance| match serde_json::to_value(&instance.metadata) {
+            Ok(Value::Object(fields)) => fields.into_iter().collect::<BTreeMap<_, _>>(),
+            _ => BTreeMap::new(),
+        };
+        let (fa, fb) = (fields(a), fields(b));
+        let mut names = fa.keys().chain(fb.keys()).cloned().collect::<Vec<_>>();
+        names.sort_unstable();
+        names.dedup();
+        names.into_iter()
+            .filter(|name| !self.ignore.contains(name))
+            .filter_map(|field| {
+                let va = fa.get(&field).cloned().unwrap_or(Value::Null);
+                let vb = fb.get(&field).cloned().unwrap_or(Value::Null);
+                (va != vb).then_some(MetadataChange { field, a: va, b: vb })
+            })
+            .collect()
+    }
+}
+
+/// Matches the destinations which have the same label. Destinations sharing a
+/// label are matched in the order of their indices.
+fn match_labels(a: &[String], b: &[String]) -> Vec<(usize, usize)> {
+    let mut by_label: HashMap<&str, VecDeque<usize>> = HashMap::new();
+    for (i, label) in a.iter().enumerate() {
+        by_label.entry(label.as_str()).or_default().push_back(i);
+    }
+    let mut pairs = b.iter().enumerate()
+        .filter_map(|(j, label)| by_label.get_mut(label.as_str()).and_then(|q| q.pop_front()).map(|i| (i, j)))
+        .collect::<Vec<_>>();
+    pairs.sort_unstable();
+    pairs
+}
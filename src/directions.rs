@@ -0,0 +1,243 @@
+//! This module computes turn-by-turn directions for the legs of a tour with the
+//! route service of an OSRM server, and renders them as a document for the
+//! drivers. The routes are cached on disk and the requests are spaced by a
+//! minimum delay so as not to overload the server.
+
+use std::{collections::BTreeMap, fs::{File, read_to_string}, io::Write, path::Path, time::Duration};
+
+use osrm_client::Location;
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+use crate::{geometry::haversine, instance::Instance};
+
+/// The route followed by a leg of a tour along with its maneuvers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LegRoute {
+    /// The length of the leg in metres
+    pub distance: f64,
+    /// The travel time of the leg in seconds (unknown for a straight line)
+    pub duration: Option<f64>,
+    /// The maneuvers along the leg, consecutive steps on the same road being
+    /// merged
+    pub maneuvers: Vec<Maneuver>,
+}
+
+/// A maneuver followed by a stretch of road
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Maneuver {
+    /// A short description of the maneuver (ie "turn left onto Rue Haute")
+    pub instruction: String,
+    /// The distance travelled after the maneuver in metres
+    pub distance: f64,
+}
+
+/// The directions of one leg of a tour
+#[derive(Debug, Clone, Serialize)]
+pub struct LegDirections {
+    pub from: usize,
+    pub to: usize,
+    /// False when the leg could not be routed and is a straight line
+    pub routed: bool,
+    #[serde(flatten)]
+    pub route: LegRoute,
+}
+
+/// A client of the route service of an OSRM server, which keeps the routes in
+/// an on-disk cache
+pub struct DirectionsClient {
+    client: reqwest::Client,
+    base_url: String,
+    min_delay: Duration,
+    last_request: Option<Instant>,
+    cache_path: Option<String>,
+    cache: BTreeMap<String, LegRoute>,
+}
+
+/// The parts of the response of the OSRM route service which are used
+#[derive(Debug, Deserialize)]
+struct RouteResponse {
+    code: String,
+    #[serde(default)]
+    routes: Vec<RouteBody>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RouteBody {
+    distance: f64,
+    duration: f64,
+    #[serde(default)]
+    legs: Vec<RouteLeg>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RouteLeg {
+    #[serde(default)]
+    steps: Vec<RouteStep>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RouteStep {
+    distance: f64,
+    #[serde(default)]
+    name: String,
+    maneuver: StepManeuver,
+}
+
+#[derive(Debug, Deserialize)]
+struct StepManeuver {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    modifier: Option<String>,
+}
+
+impl DirectionsClient {
+    /// Creates a client of the OSRM server at `base_url` which waits `min_delay`
+    /// between two requests and caches the routes in the given file (if any)
+    pub fn new(base_url: &str, min_delay: Duration, cache_path: Option<&str>) -> Result<Self, String> {
+        let cache = match cache_path {
+            Some(path) if Path::new(path).exists() => {
+                let text = read_to_string(path).map_err(|e| format!("cannot read {path}: {e}"))?;
+                serde_json::from_str(&text).map_err(|e| format!("invalid route cache {path}: {e}"))?
+            }
+            _ => BTreeMap::new(),
+        };
+        Ok(DirectionsClient {
+            client: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            min_delay,
+            last_request: None,
+            cache_path: cache_path.map(str::to_string),
+            cache,
+        })
+    }
+
+    /// Computes the directions of each leg of the given closed tour (which ends
+    /// with its first destination). A leg which cannot be routed is replaced by a
+    /// straight line, which is not cached.
+    pub async fn tour_directions(&mut self, instance: &Instance, tour: &[usize]) -> Result<Vec<LegDirections>, String> {
+        let mut legs = vec![];
+        for pair in tour.windows(2) {
+            let (from, to) = (instance.destinations[pair[0]], instance.destinations[pair[1]]);
+            let (routed, route) = match self.route(from, to).await {
+                Some(route) => (true, route),
+                None => (false, LegRoute { distance: haversine(from, to), duration: None, maneuvers: vec![] }),
+            };
+            legs.push(LegDirections { from: pair[0], to: pair[1], routed, route });
+        }
+        if let Some(path) = self.cache_path.as_ref() {
+            let text = serde_json::to_string(&self.cache).unwrap();
+            File::create(path).and_then(|mut f| f.write_all(text.as_bytes()))
+                .map_err(|e| format!("cannot write {path}: {e}"))?;
+        }
+        Ok(legs)
+    }
+
+    /// Returns the route between two locations from the cache, or asks the server
+    /// (None when it cannot be routed)
+    async fn route(&mut self, from: Location, to: Location) -> Option<LegRoute> {
+        let key = format!("{:.6},{:.6};{:.6},{:.6}", from.longitude, from.latitude, to.longitude, to.latitude);
+        if let Some(route) = self.cache.get(&key) {
+            return Some(route.clone());
+        }
+        if let Some(last) = self.last_request {
+            tokio::time::sleep_until(last + self.min_delay).await;
+        }
+        self.last_request = Some(Instant::now());
+
+        let url = format!("{}/route/v1/driving/{},{};{},{}", self.base_url, from.longitude, from.latitude, to.longitude, to.latitude);
+        let response = self.client.get(url)
+            .query(&[("steps", "true"), ("overview", "false")])
+            .send().await.ok()?
+            .error_for_status().ok()?
+            .json::<RouteResponse>().await.ok()?;
+        if response.code != "Ok" {
+            return None;
+        }
+        let body = response.routes.into_iter().next()?;
+        let steps = body.legs.into_iter().flat_map(|leg| leg.steps).collect::<Vec<_>>();
+        let route = LegRoute { distance: body.distance, duration: Some(body.duration), maneuvers: maneuvers(steps) };
+        self.cache.insert(key, route.clone());
+        Some(route)
+    }
+}
+
+/// Turns the steps of a route into maneuvers, merging the consecutive steps
+/// which follow the same road
+fn maneuvers(steps: Vec<RouteStep>) -> Vec<Maneuver> {
+    let mut maneuvers: Vec<(String, Maneuver)> = vec![];
+    for step in steps {
+        if step.maneuver.kind == "arrive" {
+            continue;
+        }
+        if let Some((_, last)) = maneuvers.last_mut().filter(|(road, _)| !road.is_empty() && *road == step.name) {
+            last.distance += step.distance;
+            continue;
+        }
+        let action = match (step.maneuver.kind.as_str(), step.maneuver.modifier.as_deref()) {
+            ("depart", _)                        => "head".to_string(),
+            ("new name", _) | ("continue", None) => "continue".to_string(),
+            (kind, Some(modifier))               => format!("{kind} {modifier}"),
+            (kind, None)                         => kind.to_string(),
+        };
+        let instruction = if step.name.is_empty() { action } else { format!("{action} onto {}", step.name) };
+        maneuvers.push((step.name, Maneuver { instruction, distance: step.distance }));
+    }
+    maneuvers.into_iter().map(|(_, maneuver)| maneuver).collect()
+}
+
+/// Renders the directions of the legs of a tour as a Markdown document with one
+/// section per leg
+pub fn markdown(name: &str, instance: &Instance, legs: &[LegDirections]) -> String {
+    let destination = |d: usize| match instance.labels.as_ref() {
+        Some(labels) => format!("{} ({d})", labels[d]),
+        None => format!("destination {d}"),
+    };
+    let distance = legs.iter().map(|l| l.route.distance).sum::<f64>();
+    let duration = legs.iter().map(|l| l.route.duration.unwrap_or(0.0)).sum::<f64>();
+    let nb_straight = legs.iter().filter(|l| !l.routed).count();
+
+    let mut text = format!("# Directions for {name}\n\n");
+    text.push_str(&format!("{} legs, {} in {}", legs.len(), kilometres(distance), minutes(duration)));
+    if nb_straight > 0 {
+        text.push_str(&format!(" ({nb_straight} legs could not be routed, their straight line distance is counted and their duration is not)"));
+    }
+    text.push('\n');
+    for (i, leg) in legs.iter().enumerate() {
+        text.push_str(&format!("\n## Leg {}: {} to {}\n\n", i + 1, destination(leg.from), destination(leg.to)));
+        match leg.route.duration.filter(|_| leg.routed) {
+            Some(duration) => text.push_str(&format!("{} in {}\n", kilometres(leg.route.distance), minutes(duration))),
+            None => text.push_str(&format!("{} as the crow flies, no route was found\n", kilometres(leg.route.distance))),
+        }
+        if !leg.route.maneuvers.is_empty() {
+            text.push('\n');
+        }
+        for (j, maneuver) in leg.route.maneuvers.iter().enumerate() {
+            text.push_str(&format!("{}. {} ({})\n", j + 1, capitalize(&maneuver.instruction), metres(maneuver.distance)));
+        }
+    }
+    text
+}
+
+/// Formats a distance given in metres in kilometres
+fn kilometres(distance: f64) -> String {
+    format!("{:.1} km", distance / 1000.0)
+}
+
+/// Formats a short distance given in metres
+fn metres(distance: f64) -> String {
+    if distance < 1000.0 { format!("{distance:.0} m") } else { kilometres(distance) }
+}
+
+/// Formats a duration given in seconds in hours and minutes
+fn minutes(duration: f64) -> String {
+    let minutes = (duration / 60.0).round() as u64;
+    if minutes < 60 { format!("{minutes} min") } else { format!("{} h {:02} min", minutes / 60, minutes % 60) }
+}
+
+/// Capitalizes the first letter of a text
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    chars.next().map(|c| c.to_uppercase().chain(chars).collect()).unwrap_or_default()
+}
@@ -0,0 +1,151 @@
+//! This module implements the command which exports instances (and their tours)
+//! to the formats understood by the classical TSP solvers such as LKH and
+//! Concorde, or by the VROOM dispatcher.
+
+use std::{path::Path, time::Duration};
+
+use clap::{Args, ValueEnum};
+use serde_json::json;
+
+use crate::{directions::{self, DirectionsClient}, instance::{Instance, Rounding, tsplib, vroom}, status::fail, stdio::{STDIO, input_name, is_stdio, write_output}, tour::{check_digest, normalize_tour, read_tour_with_digest, tsplib_tour}};
+
+/// The formats an instance can be exported to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    /// A TSPLIB `.tsp` file holding the full matrix of the (integer) weights
+    Tsplib,
+    /// The turn-by-turn directions of a tour for the drivers, one section per
+    /// leg (Markdown, or json when the output is a `.json` file)
+    Directions,
+    /// A VROOM problem (json) whose jobs are the destinations, visited by one
+    /// vehicle based at the depot, with the matrices of the instance
+    Vroom,
+}
+
+/// This command exports an instance to another format. With `--tour`, it
+/// converts a tour of the instance instead (ie to a TSPLIB `.tour` file).
+///
+/// TSPLIB weights are integers: each distance is multiplied by the multiplier
+/// and rounded (to the nearest integer by default), so that `distance ~= weight / multiplier`
+/// (in the unit of the instance). The COMMENT line of the file records both the
+/// unit and the multiplier. A pair of destinations that cannot be reached (or a
+/// forbidden arc) gets a weight larger than the length of any tour avoiding such
+/// pairs.
+///
+/// The directions of a tour are computed with the route service of an OSRM
+/// server, one request per leg. The legs which cannot be routed are replaced by
+/// straight lines.
+///
+/// A VROOM problem holds integer matrices: the durations in seconds (required
+/// by VROOM) and the distances in metres. Like in TSPLIB, the unreachable pairs
+/// and the forbidden arcs get a value larger than the length of any route.
+#[derive(Debug, Args)]
+pub struct Export {
+    /// The path to the instance file
+    #[clap(short, long)]
+    pub instance: String,
+    /// The format of the exported file
+    #[clap(short, long, value_enum, default_value = "tsplib")]
+    pub format: ExportFormat,
+    /// The factor applied to the distances before they are rounded to integers
+    #[clap(short, long, default_value_t = 1.0)]
+    pub multiplier: f64,
+    /// How the multiplied distances are rounded to integers
+    #[clap(short, long, value_enum, default_value = "nearest")]
+    pub rounding: Rounding,
+    /// Also export the coordinates of the destinations (as TSPLIB display data, or
+    /// as the locations of the VROOM jobs)
+    #[clap(short, long)]
+    pub coordinates: bool,
+    /// Converts this tour of the instance (a solve report, a TSPLIB tour or a
+    /// list of destinations) rather than the instance itself
+    #[clap(short, long)]
+    pub tour: Option<String>,
+    /// The path of the exported file (printed on stdout when omitted)
+    #[clap(short, long)]
+    pub output: Option<String>,
+    /// Drops the destinations of the instance which have no row in its distance
+    /// matrix rather than rejecting it
+    #[clap(long)]
+    pub truncate_to_matrix: bool,
+    /// URL of the osrm server computing the directions
+    #[clap(short, long, default_value="https://router.project-osrm.org")]
+    pub url_osrm: String,
+    /// The minimum delay (in milliseconds) between two requests to the osrm server
+    #[clap(long, default_value="1000")]
+    pub osrm_delay: u64,
+    /// The file where the routes of the legs are cached
+    #[clap(long)]
+    pub route_cache: Option<String>,
+}
+
+impl Export {
+    /// Executes this command
+    pub async fn execute(&self, quiet: bool) {
+        if let Err(e) = self.export(quiet).await {
+            fail(e);
+        }
+    }
+
+    async fn export(&self, quiet: bool) -> Result<(), String> {
+        let instance = Instance::load_with(&self.instance, self.truncate_to_matrix)?;
+        if self.coordinates && !instance.has_coordinates() {
+            return Err(format!("{} has no coordinates to export (it is anonymized or was built from a bare matrix)", self.instance));
+        }
+        let name = Path::new(input_name(&self.instance)).file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| self.instance.clone());
+
+        let text = match (self.format, self.tour.as_ref()) {
+            (ExportFormat::Tsplib, Some(spec)) => {
+                let (tour, digest) = read_tour_with_digest(spec)?;
+                check_digest(digest.as_ref(), &instance.digest())?;
+                let tour = normalize_tour(&tour, instance.destinations.len(), instance.depot)?;
+                let (length, symbol) = instance.metadata.unit.report(instance.tour_length(&tour));
+                tsplib_tour(&name, &tour, instance.depot, Some(&format!("length {length:.3} {symbol}")))?
+            }
+            (ExportFormat::Tsplib, None) => tsplib::write(&name, &instance, self.multiplier, self.rounding, self.coordinates)?,
+            (ExportFormat::Directions, Some(spec)) => self.directions(&name, &instance, spec).await?,
+            (ExportFormat::Directions, None) => return Err("the directions are those of a tour, which must be given with --tour".to_string()),
+            (ExportFormat::Vroom, Some(_)) => return Err("a tour cannot be exported to VROOM, which solves the problem itself".to_string()),
+            (ExportFormat::Vroom, None) => vroom::write(&instance, self.coordinates)?,
+        };
+
+        let path = self.output.as_deref().unwrap_or(STDIO);
+        write_output(path, &text)?;
+        if !quiet && !is_stdio(path) {
+            println!("exported {} to {path}", input_name(&self.instance));
+        }
+        Ok(())
+    }
+}
+
+impl Export {
+    /// Renders the directions of the given tour of the instance
+    async fn directions(&self, name: &str, instance: &Instance, spec: &str) -> Result<String, String> {
+        if !instance.has_coordinates() {
+            return Err(format!("{} has no coordinates to route (it is anonymized or was built from a bare matrix)", self.instance));
+        }
+        let (tour, digest) = read_tour_with_digest(spec)?;
+        check_digest(digest.as_ref(), &instance.digest())?;
+        let mut tour = normalize_tour(&tour, instance.destinations.len(), instance.depot)?;
+        tour.push(tour[0]);
+
+        let mut client = DirectionsClient::new(&self.url_osrm, Duration::from_millis(self.osrm_delay), self.route_cache.as_deref())?;
+        let legs = client.tour_directions(instance, &tour).await?;
+        let nb_straight = legs.iter().filter(|l| !l.routed).count();
+        if nb_straight > 0 {
+            eprintln!("warning: {nb_straight} legs could not be routed, they are replaced by straight lines");
+        }
+        if self.output.as_ref().is_some_and(|path| path.ends_with(".json")) {
+            let mut document = json!({"instance": name, "digest": instance.digest(), "legs": legs});
+            if let Some(extras) = instance.extras.as_ref() {
+                // the tour is closed, its last destination is the depot again
+                document["visits"] = tour[..tour.len() - 1].iter().map(|d| json!({"destination": d, "extras": extras[*d]})).collect();
+            }
+            Ok(serde_json::to_string_pretty(&document).unwrap())
+        } else {
+            Ok(directions::markdown(name, instance, &legs))
+        }
+    }
+}
@@ -0,0 +1,158 @@
+//! This module exposes the solver to C (and C++) programs. The functions never
+//! unwind across the boundary: failures are reported through the status codes
+//! declared in `include/tsptools.h`.
+//!
+//! `cargo build --release --features ffi` builds both the static and the shared
+//! library. The C harness of `tests/ffi` checks this interface from C.
+
+use std::{panic::{catch_unwind, AssertUnwindSafe}, slice};
+
+use osrm_client::Location;
+
+use crate::{instance::{Instance, Unit}, resolution::{self, ObjectiveSense, SolveOptions}};
+
+pub const TSP_OK: i32 = 0;
+pub const TSP_NULL_POINTER: i32 = 1;
+pub const TSP_INVALID_INSTANCE: i32 = 2;
+pub const TSP_INVALID_OPTIONS: i32 = 3;
+pub const TSP_NO_SOLUTION: i32 = 4;
+pub const TSP_BUFFER_TOO_SMALL: i32 = 5;
+pub const TSP_PANIC: i32 = 6;
+
+/// An instance created by `tsp_instance_new`, opaque to C
+pub struct TspInstance(Instance);
+
+/// The options of the solver as seen from C
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct TspOptions {
+    /// The maximum number of nodes in a layer
+    pub width: u64,
+    /// The time limit in seconds (0 means none)
+    pub timeout: u64,
+    /// Non zero to look for the longest tour rather than the shortest one
+    pub maximize: i32,
+    /// Non zero to run the search on a single thread, reproducibly
+    pub deterministic: i32,
+}
+
+/// Runs the given function and turns a panic into the TSP_PANIC status
+fn guarded(f: impl FnOnce() -> i32) -> i32 {
+    catch_unwind(AssertUnwindSafe(f)).unwrap_or(TSP_PANIC)
+}
+
+/// Creates an instance of `n` destinations from its row-major distance matrix
+/// (`n * n` entries, INFINITY marks unreachable pairs) and optionally the
+/// longitude and latitude of each destination (`2 * n` entries, may be NULL).
+///
+/// # Safety
+/// `distances` must point to `n * n` doubles, `coordinates` must be NULL or point
+/// to `2 * n` doubles and `out` must be a valid pointer. The instance written to
+/// `out` must be released with `tsp_instance_free`.
+#[no_mangle]
+pub unsafe extern "C" fn tsp_instance_new(distances: *const f64, n: usize, coordinates: *const f64, out: *mut *mut TspInstance) -> i32 {
+    guarded(|| {
+        if distances.is_null() || out.is_null() {
+            return TSP_NULL_POINTER;
+        }
+        let Some(size) = n.checked_mul(n) else {
+            return TSP_INVALID_INSTANCE;
+        };
+        let flat = slice::from_raw_parts(distances, size);
+        let matrix = flat.chunks(n.max(1)).take(n)
+            .map(|row| row.iter().map(|d| *d as f32).collect())
+            .collect();
+        let destinations = if coordinates.is_null() {
+            vec![Location { longitude: 0.0, latitude: 0.0 }; n]
+        } else {
+            slice::from_raw_parts(coordinates, 2 * n).chunks(2)
+                .map(|c| Location { longitude: c[0] as f32, latitude: c[1] as f32 })
+                .collect()
+        };
+        // the solver reports the totals of kilometers as they are
+        match Instance::from_distances(destinations, matrix, Unit::Kilometers) {
+            Ok(instance) => {
+                *out = Box::into_raw(Box::new(TspInstance(instance)));
+                TSP_OK
+            }
+            Err(_) => TSP_INVALID_INSTANCE,
+        }
+    })
+}
+
+/// Releases an instance created by `tsp_instance_new`
+///
+/// # Safety
+/// `instance` must be NULL or have been created by `tsp_instance_new`, and must
+/// not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn tsp_instance_free(instance: *mut TspInstance) {
+    if !instance.is_null() {
+        drop(Box::from_raw(instance));
+    }
+}
+
+/// Writes the default options of the solver to `out`
+///
+/// # Safety
+/// `out` must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn tsp_options_default(out: *mut TspOptions) -> i32 {
+    guarded(|| {
+        if out.is_null() {
+            return TSP_NULL_POINTER;
+        }
+        let defaults = SolveOptions::default();
+        *out = TspOptions {
+            width: defaults.width as u64,
+            timeout: defaults.timeout,
+            maximize: (defaults.objective == ObjectiveSense::Max) as i32,
+            deterministic: defaults.deterministic as i32,
+        };
+        TSP_OK
+    })
+}
+
+/// Solves the instance. The best tour is written to `tour` (the destinations in
+/// visit order, ending with the return to the depot 0) and its length to
+/// `tour_len`, while `value` receives its total distance and `is_exact` whether
+/// it was proved optimal.
+///
+/// # Safety
+/// `instance` must come from `tsp_instance_new`, `tour` must point to at least
+/// `capacity` entries and the other pointers must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn tsp_solve(instance: *const TspInstance, options: *const TspOptions, tour: *mut usize, capacity: usize,
+    tour_len: *mut usize, value: *mut f64, is_exact: *mut i32) -> i32 {
+    guarded(|| {
+        if instance.is_null() || options.is_null() || tour.is_null() || tour_len.is_null() || value.is_null() || is_exact.is_null() {
+            return TSP_NULL_POINTER;
+        }
+        let settings = &*options;
+        let objective = if settings.maximize != 0 { ObjectiveSense::Max } else { ObjectiveSense::Min };
+        let options = SolveOptions::builder()
+            .width(settings.width as usize)
+            .timeout(settings.timeout)
+            .objective(objective)
+            .deterministic(settings.deterministic != 0)
+            .build();
+        let Ok(options) = options else {
+            return TSP_INVALID_OPTIONS;
+        };
+        let report = match resolution::solve_instance("ffi", (*instance).0.clone(), &options) {
+            Ok(report) => report,
+            Err(_) => return TSP_INVALID_INSTANCE,
+        };
+        *is_exact = report.is_exact as i32;
+        let (Some(best), Some(length)) = (report.tour, report.value) else {
+            return TSP_NO_SOLUTION;
+        };
+        *tour_len = best.len();
+        *value = length;
+        if best.len() > capacity {
+            return TSP_BUFFER_TOO_SMALL;
+        }
+        slice::from_raw_parts_mut(tour, best.len()).copy_from_slice(&best);
+        TSP_OK
+    })
+}
@@ -1,24 +1,27 @@
 //! This module comprises all utilities that are required to generate a random TSP instance
 //! between places in the real world.
 
-use std::{time::{SystemTime, UNIX_EPOCH}, fs::File, io::Write};
+use std::{cell::Cell, time::{SystemTime, UNIX_EPOCH, Duration}, fs::{File, read_to_string, create_dir_all}, io::{Write, IsTerminal}, path::Path};
 
 use clap::Args;
-use osrm_client::{Location, NearestRequestBuilder, TableRequestBuilder, TableAnnotationRequest, Client};
-use rand::{Rng, SeedableRng};
+use futures::{StreamExt, stream};
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use osrm_client::{Location, NearestRequestBuilder, RouteRequestBuilder, TableRequestBuilder, TableAnnotationRequest, Geometries, OverviewRequest, Client};
+use rand::{Rng, SeedableRng, seq::SliceRandom};
 use rand_chacha::ChaChaRng;
 use rand_distr::{Uniform, Normal, Distribution};
+use serde::Serialize;
 
-use crate::instance::Instance;
+use crate::{geometry::{Area, DensityGrid, Polyline, haversine}, instance::{Corridor, Instance, Matrix, Metadata, Transit, Unit}, nominatim::{AddressCache, Nominatim}, status::fail, transit::{Feed, parse_time}};
 
 
 /// TspGen is a generator for realistic TSP instances where the cities to visit are gouped in clusters.
 /// 
 /// Generate instance in Belgium:
-/// ```
+/// ```sh
 /// ./target/release/tspgen  --min-longitude=2.376776  --max-longitude=5.91469  --min-latitude=50.2840167  --max-latitude=51.034368
 /// ```
-#[derive(Debug, Args)]
+#[derive(Debug, Clone, Args)]
 pub struct GenerateInstance {
     /// An optional seed to kickstart the instance generation
     #[clap(short='s', long)]
@@ -45,101 +48,639 @@ pub struct GenerateInstance {
     /// The north most longitude allowed in this generation
     #[clap(long, default_value="51.0521")]
     pub max_latitude: f32,
-    /// Force all destinations to be routable (takes longer to generate an instance)
+    /// Force all destinations to be routable (takes longer to generate an instance):
+    /// each destination is snapped to the nearest point of the road network
     #[clap(short, long)]
     pub force_routable: bool,
+    /// The largest distance (in metres) a generated destination may be moved by when
+    /// it is snapped to the road network. Farther destinations are replaced.
+    #[clap(long)]
+    pub max_snap_distance: Option<f32>,
     /// Base the distance matrix on duration rather than distance
     #[clap(short='D', long)]
     pub duration: bool,
 
-    /// Name of the file where to generate the tsp instance
+    /// Name of the file where to generate the tsp instance (in the binary format
+    /// when its extension is .tspb)
     #[clap(short, long)]
     pub output: Option<String>,
 
     /// URL of the osrm server to use (optional)
     #[clap(short, long)]
     pub url_osrm: Option<String>,
+    /// Also fetches a snapshot of the durations matrix for this departure time, given
+    /// as `label` or as `label=url` to use the osrm server at `url` for it (e.g. one
+    /// loaded with the traffic of that time of day). Can be repeated. With --gtfs,
+    /// it is the time of day (HH:MM) the transit journeys leave at.
+    #[clap(long)]
+    pub departure: Vec<String>,
+    /// The maximum number of requests sent concurrently to the osrm server
+    #[clap(long, default_value="4")]
+    pub concurrency: usize,
+    /// Fill the matrix with the travel times (in seconds) of the earliest public
+    /// transport journeys given by the GTFS feed in this directory (an unzipped
+    /// feed), leaving at the --departure time. The matrix is asymmetric, and the
+    /// pairs which no journey connects are unreachable.
+    #[clap(long)]
+    pub gtfs: Option<String>,
+    /// Only take the trips of the GTFS services running on this date (YYYYMMDD)
+    #[clap(long, requires = "gtfs")]
+    pub gtfs_date: Option<String>,
+    /// The longest walk (in metres) of a transit journey: to its first stop, from
+    /// its last one, along a transfer, or straight to its destination
+    #[clap(long, default_value="1000")]
+    pub max_walk: f64,
+
+    /// Build the instance from the points listed in this csv file instead of
+    /// generating random destinations
+    #[clap(long)]
+    pub points: Option<String>,
+    /// Name of the csv column holding the name of each point
+    #[clap(long, default_value="name")]
+    pub name_column: String,
+    /// Name of the csv column holding the latitude of each point
+    #[clap(long, default_value="lat")]
+    pub lat_column: String,
+    /// Name of the csv column holding the longitude of each point
+    #[clap(long, default_value="lon")]
+    pub lon_column: String,
+    /// Name of the point to use as depot (the first row is used by default)
+    #[clap(long)]
+    pub depot_name: Option<String>,
+    /// Skip the csv rows whose coordinates cannot be parsed rather than aborting
+    #[clap(long)]
+    pub skip_bad_rows: bool,
+
+    /// A GeoJSON file with the (multi)polygon inside which all the destinations and
+    /// centroids must fall. Its bounding box replaces the min/max coordinates.
+    #[clap(long)]
+    pub boundary: Option<String>,
+    /// GeoJSON files with (multi)polygons where no destination nor centroid may
+    /// fall (pedestrian zones, restricted sites, ...). May be repeated.
+    #[clap(long)]
+    pub exclude: Vec<String>,
+    /// The number of sampled points that may be rejected (outside the boundary,
+    /// inside an exclusion zone, too close to another destination or snapped too
+    /// far) over the whole generation before it is abandoned
+    #[clap(long, default_value="10000")]
+    pub max_retries: usize,
+    /// The name of an administrative area (e.g. "Leuven, Belgium") whose outline is
+    /// resolved with Nominatim and used as boundary
+    #[clap(long)]
+    pub area: Option<String>,
+    /// Which of the areas matching `area` to use when the name is ambiguous
+    #[clap(long)]
+    pub area_index: Option<usize>,
+    /// URL of the Nominatim server used to resolve names
+    #[clap(long, default_value="https://nominatim.openstreetmap.org")]
+    pub nominatim_url: String,
+    /// The user agent identifying this application to Nominatim (its usage policy
+    /// requires one which identifies you)
+    #[clap(long, default_value="tspgen")]
+    pub user_agent: String,
+    /// The minimum delay (in milliseconds) between two requests to Nominatim
+    #[clap(long, default_value="1000")]
+    pub nominatim_delay: u64,
+    /// Label each generated destination with its address (reverse geocoded with
+    /// Nominatim), or with its coordinates when it has no known address
+    #[clap(long)]
+    pub label_addresses: bool,
+    /// The file where the reverse geocoded addresses are cached
+    #[clap(long, default_value="addresses.json")]
+    pub address_cache: String,
+    /// A csv grid of non negative weights covering the generation area (one row per
+    /// line, from north to south). When present, the destinations are sampled
+    /// proportionally to these weights instead of around the centroids.
+    #[clap(long)]
+    pub density: Option<String>,
+    /// The minimum distance (in metres, as the crow flies) between two destinations
+    #[clap(long)]
+    pub min_separation: Option<f32>,
+
+    /// Generate the destinations along the route starting at this place (given as
+    /// `latitude,longitude`) rather than around random centroids
+    #[clap(long, requires="to")]
+    pub from: Option<String>,
+    /// The place (given as `latitude,longitude`) where the route of the corridor ends
+    #[clap(long, requires="from")]
+    pub to: Option<String>,
+    /// The largest distance (in kilometres) between a destination and the route
+    #[clap(long, default_value="5")]
+    pub buffer_km: f64,
+    /// The standard deviation (in kilometres) of the distance between a destination
+    /// and the route. The destinations are spread uniformly over the buffer by default.
+    #[clap(long)]
+    pub lateral_std_km: Option<f64>,
+
+    /// Generates a family of instances with these numbers of cities (comma separated)
+    /// in `out_dir` rather than a single instance. The instances of a same seed are
+    /// nested: they are subsets of the largest one and share its distance matrix.
+    #[clap(long, value_delimiter=',')]
+    pub sizes: Vec<usize>,
+    /// The number of seeds used for each size of a family. The seeds are numbered
+    /// consecutively, starting from `seed` (or 0).
+    #[clap(long, default_value="1")]
+    pub seeds: usize,
+    /// The directory where to write the instances of a family and their manifest
+    #[clap(long, default_value=".")]
+    pub out_dir: String,
+    /// Only prints the instances of the family that would be generated
+    #[clap(long)]
+    pub dry_run: bool,
+
+    /// Whether the progress bars must be hidden
+    #[clap(skip)]
+    pub quiet: bool,
+}
+
+/// The list of the instances generated in a family, written as manifest.json
+#[derive(Debug, Serialize)]
+struct Manifest {
+    nb_centroids: usize,
+    std_dev: f32,
+    duration: bool,
+    force_routable: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    boundary: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    density: Option<String>,
+    instances: Vec<ManifestEntry>,
+}
+
+/// One of the instances listed in a manifest
+#[derive(Debug, Serialize)]
+struct ManifestEntry {
+    file: String,
+    nb_cities: usize,
+    seed: u128,
+    digest: String,
 }
 
 impl GenerateInstance {
-    /// Executes this command
-    pub async fn execute(&self) {
+    /// Executes this command. The progress of the routing requests is shown on
+    /// stderr (when it is a terminal) unless in quiet mode.
+    pub async fn execute(&mut self, quiet: bool) {
+        self.quiet = quiet;
         let mut client = osrm_client::Client::default();
         if let Some(url) = self.url_osrm.as_ref() {
             client = client.base_url(url.clone());
         }
 
-        let instance  = self.generate(&client).await;
-        let instance = serde_json::to_string_pretty(&instance).unwrap();
+        if !self.sizes.is_empty() {
+            self.generate_family(&client).await.unwrap_or_else(|e| fail(e));
+            return;
+        }
 
-        if let Some(output) = self.output.as_ref() {
-            File::create(output).unwrap().write_all(instance.as_bytes()).unwrap();
+        let instance = if let Some(points) = self.points.as_ref() {
+            self.generate_from_points(&client, points).await
         } else {
-            println!("{instance}");
+            self.generate(&client).await
+        };
+        let saved = instance.and_then(|instance| instance.save(self.output.as_deref()));
+        if let Err(e) = saved {
+            fail(e);
         }
     }
 
     /// This is the method you want to call in order to generate a clustered TSP instance
-    pub async fn generate(&self, client: &Client) -> Instance {
-        let mut rng = self.rng();
-        let centroids = self.generate_centroids(&mut rng);
-        let centroids = self.routable_cities(client, &centroids).await;
-        let mut destinations = self.generate_cities(&mut rng, &centroids);
+    pub async fn generate(&self, client: &Client) -> Result<Instance, String> {
+        let seed = self.seed();
+        let mut rng = self.rng(seed);
+        let mut osm_relation = None;
+        let area = match (self.boundary.as_deref(), self.area.as_deref()) {
+            (Some(_), Some(_)) => return Err("a boundary and an area cannot be used together".to_string()),
+            (Some(path), None) => Some(Area::load(path)?),
+            (None, Some(name)) => {
+                let (relation, area) = self.resolve_area(name).await?;
+                osm_relation = Some(relation);
+                Some(area)
+            }
+            (None, None) => None,
+        };
+        let exclusions = self.exclude.iter().map(|path| Area::load(path)).collect::<Result<Vec<_>, _>>()?;
+        let zone = Zone { area: area.as_ref(), exclusions: &exclusions, rejected: Cell::new(0), max_rejected: self.max_retries };
+        let density = self.density.as_deref()
+            .map(|path| DensityGrid::load(path, self.bounds(area.as_ref())))
+            .transpose()?;
+        let corridor = self.corridor()?;
+        let route = match corridor.as_ref() {
+            Some(_) if density.is_some() => return Err("a corridor and a density grid cannot be used together".to_string()),
+            Some(corridor) => Some(self.route(client, corridor).await?),
+            None => None,
+        };
+        let centroids = if density.is_some() || route.is_some() {
+            vec![]
+        } else {
+            let centroids = self.generate_centroids(&mut rng, &zone)?;
+            self.routable_cities(client, &centroids).await?
+        };
+        let spread = match (density.as_ref(), route.as_ref()) {
+            (Some(grid), _)     => Spread::Density(grid),
+            (None, Some(route)) => Spread::Corridor(route),
+            (None, None)        => Spread::Clusters(&centroids),
+        };
+        let mut destinations = self.generate_cities(&mut rng, spread, &zone)?;
+        let mut snap_distances = None;
+        if self.force_routable {
+            let (snapped, distances) = self.snap_destinations(client, &mut rng, spread, &zone, destinations).await?;
+            destinations = snapped;
+            snap_distances = Some(distances);
+        }
+
+        let (distances, durations, durations_by_time) = self.matrices(client, &destinations).await?;
+        let labels = if self.label_addresses { Some(self.addresses(&destinations).await?) } else { None };
+
+        Ok(Instance{
+            destinations,
+            distances,
+            durations,
+            durations_by_time,
+            labels,
+            extras: None,
+            depot: 0,
+            forbidden_arcs: vec![],
+            forced_arcs: vec![],
+            metadata: Metadata {
+                seed: Some(seed),
+                boundary: self.boundary.clone(),
+                area: self.area.clone(),
+                osm_relation,
+                corridor,
+                snap_distances,
+                density: self.density.clone(),
+                density_checksum: density.map(|grid| grid.checksum),
+                min_separation: self.min_separation,
+                exclusions: (!self.exclude.is_empty()).then(|| self.exclude.clone()),
+                ..self.metadata()
+            },
+        })
+    }
+
+    /// This method generates the instances of each size for each seed in `out_dir`
+    /// along with a manifest listing them. The largest instance of each seed is
+    /// generated and the smaller ones are random subsets of it (which always keep
+    /// the depot), so that a single matrix is computed per seed.
+    pub async fn generate_family(&self, client: &Client) -> Result<(), String> {
+        if self.points.is_some() {
+            return Err("a family of instances cannot be built from a list of points".to_string());
+        }
+        if self.sizes.contains(&0) {
+            return Err("the instances of a family must have at least one city".to_string());
+        }
+        let largest = self.sizes.iter().copied().max().unwrap_or(0);
+        let base = self.seed.unwrap_or(0);
+        if !self.dry_run {
+            create_dir_all(&self.out_dir).map_err(|e| format!("cannot create {}: {e}", self.out_dir))?;
+        }
+
+        let mut instances = vec![];
+        for seed in (0..self.seeds as u128).map(|k| base + k) {
+            let name = |size: usize| format!("tsp-n{size}-s{seed}.json");
+            if self.dry_run {
+                for size in self.sizes.iter().copied() {
+                    println!("{}: {size} cities, seed {seed}", Path::new(&self.out_dir).join(name(size)).display());
+                }
+                continue;
+            }
+
+            let generator = GenerateInstance { seed: Some(seed), nb_cities: largest, ..self.clone() };
+            let instance = generator.generate(client).await?;
+            let mut order = (1..largest).collect::<Vec<_>>();
+            order.shuffle(&mut generator.rng(seed));
+
+            for size in self.sizes.iter().copied() {
+                let mut indices = vec![0];
+                indices.extend_from_slice(&order[..size - 1]);
+                indices.sort_unstable();
+                let instance = instance.subset(&indices);
+
+                instance.save(Path::new(&self.out_dir).join(name(size)).to_str())?;
+                instances.push(ManifestEntry { file: name(size), nb_cities: size, seed, digest: instance.digest() });
+            }
+        }
+
+        if !self.dry_run {
+            let manifest = Manifest {
+                nb_centroids: self.nb_centroids,
+                std_dev: self.std_dev,
+                duration: self.duration,
+                force_routable: self.force_routable,
+                boundary: self.boundary.clone(),
+                density: self.density.clone(),
+                instances,
+            };
+            let path = Path::new(&self.out_dir).join("manifest.json");
+            File::create(&path).and_then(|mut f| f.write_all(serde_json::to_string_pretty(&manifest).unwrap().as_bytes()))
+                .map_err(|e| format!("cannot write {}: {e}", path.display()))?;
+        }
+        Ok(())
+    }
+
+    /// This method builds an instance whose destinations are the points listed in
+    /// the given csv file (in file order, the depot being moved to the front)
+    pub async fn generate_from_points(&self, client: &Client, path: &str) -> Result<Instance, String> {
+        if !self.exclude.is_empty() {
+            return Err("exclusion zones cannot be applied to a list of points".to_string());
+        }
+        let (mut labels, mut destinations) = self.read_points(path)?;
+        if let Some(depot) = self.depot_name.as_ref() {
+            let pos = labels.iter().position(|l| l == depot)
+                .ok_or_else(|| format!("there is no point named '{depot}' in {path}"))?;
+            let label = labels.remove(pos);
+            let location = destinations.remove(pos);
+            labels.insert(0, label);
+            destinations.insert(0, location);
+        }
+        let mut snap_distances = None;
         if self.force_routable {
-            destinations = self.routable_cities(client, &destinations).await;
+            let (snapped, distances) = self.snap_all(client, &destinations).await?.into_iter().unzip();
+            destinations = snapped;
+            snap_distances = Some(distances);
         }
 
-        let distances = self.travel_cost_matrix(client, &destinations).await;
+        let (distances, durations, durations_by_time) = self.matrices(client, &destinations).await?;
 
-        Instance{
+        Ok(Instance{
             destinations,
-            distances
+            distances,
+            durations,
+            durations_by_time,
+            labels: Some(labels),
+            extras: None,
+            depot: 0,
+            forbidden_arcs: vec![],
+            forced_arcs: vec![],
+            metadata: Metadata { snap_distances, ..self.metadata() },
+        })
+    }
+
+    /// This method parses the names and coordinates of the points listed in a csv
+    /// file. Both comma and semicolon separated files are accepted.
+    fn read_points(&self, path: &str) -> Result<(Vec<String>, Vec<Location>), String> {
+        let text = read_to_string(path).map_err(|e| format!("cannot read {path}: {e}"))?;
+        let mut lines = text.lines().enumerate().filter(|(_, l)| !l.trim().is_empty());
+        let (_, header) = lines.next().ok_or_else(|| format!("{path} is empty"))?;
+        let separator = if header.matches(';').count() > header.matches(',').count() { ';' } else { ',' };
+
+        let columns = split_row(header, separator);
+        let column = |name: &str| columns.iter().position(|c| c == name)
+            .ok_or_else(|| format!("there is no column '{name}' in {path}"));
+        let name = column(&self.name_column)?;
+        let lat = column(&self.lat_column)?;
+        let lon = column(&self.lon_column)?;
+
+        let mut labels = vec![];
+        let mut locations = vec![];
+        let mut bad_rows = vec![];
+        for (i, line) in lines {
+            let row = split_row(line, separator);
+            let coord = |c: usize| row.get(c).and_then(|x| x.parse::<f32>().ok()).filter(|x| x.is_finite());
+            match (coord(lat), coord(lon)) {
+                (Some(latitude), Some(longitude)) => {
+                    labels.push(row.get(name).cloned().unwrap_or_default());
+                    locations.push(Location { longitude, latitude });
+                }
+                _ => bad_rows.push(i + 1),
+            }
         }
+
+        if !bad_rows.is_empty() {
+            let rows = bad_rows.iter().map(|r| r.to_string()).collect::<Vec<_>>().join(", ");
+            if self.skip_bad_rows {
+                eprintln!("warning: skipped the rows with unparsable coordinates in {path} (lines {rows})");
+            } else {
+                return Err(format!("unparsable coordinates in {path} (lines {rows})"));
+            }
+        }
+        Ok((labels, locations))
     }
     
-    /// This method returns an initialized random number generator
-    fn rng(&self) -> impl Rng {
-        let init = self.seed.unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis());
+    /// This method resolves the name of an administrative area to the id of its
+    /// OpenStreetMap relation and its outline. Ambiguous names must be resolved
+    /// with `area_index`.
+    async fn resolve_area(&self, name: &str) -> Result<(u64, Area), String> {
+        let nominatim = self.nominatim()?;
+        let candidates = nominatim.search_outlines(name).await?.into_iter()
+            .filter(|place| place.osm_type == "relation")
+            .filter_map(|place| {
+                let area = place.geojson.as_ref().and_then(|g| Area::from_geojson(g).ok())?;
+                Some((place, area))
+            })
+            .collect::<Vec<_>>();
+
+        let index = match (candidates.len(), self.area_index) {
+            (0, _) => return Err(format!("no administrative area named '{name}' was found")),
+            (_, Some(i)) if i < candidates.len() => i,
+            (1, None) => 0,
+            (n, _) => {
+                let list = candidates.iter().enumerate()
+                    .map(|(i, (place, _))| format!("  {i}: {} (relation {})", place.display_name, place.osm_id))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                return Err(format!("'{name}' matches {n} areas, pick one with --area-index:\n{list}"));
+            }
+        };
+        let (place, area) = candidates.into_iter().nth(index).unwrap();
+        Ok((place.osm_id, area))
+    }
+
+    /// This method returns the address of each location (or its coordinates when
+    /// the address cannot be found)
+    async fn addresses(&self, locations: &[Location]) -> Result<Vec<String>, String> {
+        let nominatim = self.nominatim()?;
+        let mut cache = AddressCache::open(&self.address_cache)?;
+        let mut labels = vec![];
+        let mut nb_failures = 0;
+        let progress = self.progress(locations.len(), "geocoding");
+        for location in locations.iter().copied() {
+            progress.inc(1);
+            let address = cache.address(&nominatim, location).await.unwrap_or_else(|e| {
+                nb_failures += 1;
+                eprintln!("warning: {e}");
+                None
+            });
+            labels.push(address.unwrap_or_else(|| format!("{:.5}, {:.5}", location.latitude, location.longitude)));
+        }
+        progress.finish_and_clear();
+        if nb_failures > 0 {
+            eprintln!("warning: {nb_failures} destinations could not be reverse geocoded and are labelled with their coordinates");
+        }
+        cache.save()?;
+        Ok(labels)
+    }
+
+    /// This method returns a client of the Nominatim server
+    fn nominatim(&self) -> Result<Nominatim, String> {
+        Nominatim::new(&self.nominatim_url, &self.user_agent, Duration::from_millis(self.nominatim_delay))
+    }
+
+    /// This method returns the metadata describing the generated instance
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            unit: if self.duration || self.gtfs.is_some() { Unit::Seconds } else { Unit::Meters },
+            generated_at: Some(now()),
+            router: self.url_osrm.clone(),
+            transit: self.gtfs.as_ref().map(|feed| Transit {
+                feed: feed.clone(),
+                departure: self.departure.concat(),
+                date: self.gtfs_date.clone(),
+                max_walk: self.max_walk,
+            }),
+            ..Metadata::default()
+        }
+    }
+
+    /// This method returns the seed of the generation (the given one, or one derived
+    /// from the current time)
+    fn seed(&self) -> u128 {
+        self.seed.unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis())
+    }
+
+    /// This method returns a random number generator initialized with the given seed
+    fn rng(&self, init: u128) -> impl Rng {
         let mut seed = [0_u8; 32];
         seed.iter_mut().zip(init.to_be_bytes().into_iter()).for_each(|(s, i)| *s = i);
         seed.iter_mut().rev().zip(init.to_le_bytes().into_iter()).for_each(|(s, i)| *s = i);
         ChaChaRng::from_seed(seed)
     }
 
-    /// This method returns a vector of random centroids for this instance
-    fn generate_centroids(&self, rng: &mut impl Rng) -> Vec<Location> {
+    /// This method returns a vector of random centroids for this instance. When an
+    /// area is given, the centroids are sampled in its bounding box until they fall
+    /// inside of it (and outside the exclusion zones).
+    fn generate_centroids(&self, rng: &mut impl Rng, zone: &Zone) -> Result<Vec<Location>, String> {
+        let bounds = self.bounds(zone.area);
         let mut centroids = vec![];
         for _ in 0..self.nb_centroids {
-            centroids.push(self.random_centroid(rng));
+            centroids.push(self.sample_admissible(zone, &[], "centroid", || Self::random_centroid(rng, bounds))?);
+        }
+        Ok(centroids)
+    }
+
+    /// This method returns the (min longitude, max longitude, min latitude, max latitude)
+    /// bounds of the generation: the bounding box of the area if any, or the bounds
+    /// given on the command line
+    fn bounds(&self, area: Option<&Area>) -> (f32, f32, f32, f32) {
+        match area {
+            Some(area) => {
+                let (min_lon, max_lon, min_lat, max_lat) = area.bbox();
+                (min_lon as f32, max_lon as f32, min_lat as f32, max_lat as f32)
+            }
+            None => (self.min_longitude, self.max_longitude, self.min_latitude, self.max_latitude),
         }
-        centroids
     }
 
-    /// This method returns a new random centroid uniformly sampled from 0..max
-    fn random_centroid(&self, rng: &mut impl Rng) -> Location {
-        //
-        let lon_dist = Uniform::new_inclusive(self.min_longitude, self.max_longitude);
-        let lat_dist = Uniform::new_inclusive(self.min_latitude, self.max_latitude);
+    /// This method returns a new random centroid uniformly sampled within the given
+    /// (min longitude, max longitude, min latitude, max latitude) bounds
+    fn random_centroid(rng: &mut impl Rng, (min_lon, max_lon, min_lat, max_lat): (f32, f32, f32, f32)) -> Location {
+        let lon_dist = Uniform::new_inclusive(min_lon, max_lon);
+        let lat_dist = Uniform::new_inclusive(min_lat, max_lat);
         let longitude = lon_dist.sample(rng);
         let latitude = lat_dist.sample(rng);
         Location { longitude, latitude }
     }
 
-    /// This method returns a vector of random cities close to the centroids
-    fn generate_cities(&self, rng: &mut impl Rng, centroids: &[Location]) -> Vec<Location> {
+    /// This method returns a vector of random cities spread as requested (and inside the
+    /// given zone)
+    fn generate_cities(&self, rng: &mut impl Rng, spread: Spread<'_>, zone: &Zone) -> Result<Vec<Location>, String> {
+        let mut cities = vec![];
+        if !matches!(spread, Spread::Clusters(_)) {
+            for i in 0..self.nb_cities {
+                cities.push(self.random_city(rng, spread, i, zone, &cities)?);
+            }
+            return Ok(cities);
+        }
+
         let mut cities_per_centroids = vec![self.nb_cities / self.nb_centroids; self.nb_centroids];
         for i in 0..(self.nb_cities % self.nb_centroids) {
             cities_per_centroids[i] += 1;
         }
 
-        let mut cities = vec![];
-        for (i, centroid) in centroids.iter().copied().enumerate() {
-            let n = cities_per_centroids[i];
+        for (i, n) in cities_per_centroids.iter().copied().enumerate() {
             for _ in 0..n {
-                cities.push(self.random_pos_close_to(rng, centroid));
+                cities.push(self.random_city(rng, spread, i, zone, &cities)?);
             }
         }
-        cities
+        Ok(cities)
+    }
+
+    /// This method returns a new admissible city (see `sample_admissible`): close to the
+    /// i-th centroid (modulo), drawn from the density grid or along the route of the corridor
+    fn random_city(&self, rng: &mut impl Rng, spread: Spread<'_>, i: usize, zone: &Zone, accepted: &[Location]) -> Result<Location, String> {
+        match spread {
+            Spread::Clusters(centroids) => {
+                let centroid = centroids[i % centroids.len()];
+                self.sample_admissible(zone, accepted, "destination", || self.random_pos_close_to(rng, centroid))
+            }
+            Spread::Density(grid) => self.sample_admissible(zone, accepted, "destination", || grid.sample(rng)),
+            Spread::Corridor(route) => self.sample_admissible(zone, accepted, "destination", || self.random_pos_along(rng, route)),
+        }
+    }
+
+    /// This method returns the corridor given by the `from`, `to`, `buffer_km` and
+    /// `lateral_std_km` flags, if any
+    fn corridor(&self) -> Result<Option<Corridor>, String> {
+        let (Some(from), Some(to)) = (self.from.as_deref(), self.to.as_deref()) else {
+            return Ok(None);
+        };
+        if self.buffer_km.is_nan() || self.buffer_km <= 0.0 {
+            return Err(format!("the buffer of the corridor must be positive, got {} km", self.buffer_km));
+        }
+        if self.lateral_std_km.is_some_and(|std| std.is_nan() || std <= 0.0) {
+            return Err("the lateral standard deviation must be positive".to_string());
+        }
+        Ok(Some(Corridor {
+            from: parse_lat_lon(from)?,
+            to: parse_lat_lon(to)?,
+            buffer_km: self.buffer_km,
+            lateral_std_km: self.lateral_std_km,
+        }))
+    }
+
+    /// This method fetches the geometry of the route between the ends of the corridor
+    async fn route(&self, client: &Client, corridor: &Corridor) -> Result<Polyline, String> {
+        let location = |(latitude, longitude): (f32, f32)| Location { longitude, latitude };
+        let response = RouteRequestBuilder::default()
+            .coordinates(osrm_client::Coordinates::Multi(vec![location(corridor.from), location(corridor.to)]))
+            .geometries(Geometries::GeoJson)
+            .overview(OverviewRequest::Full)
+            .build()
+            .unwrap()
+            .send(client).await
+            .map_err(|_| "cannot fetch the route of the corridor".to_string())?;
+        let route = response.routes.first().ok_or("there is no route between the ends of the corridor")?;
+
+        let geometry = serde_json::to_value(&route.geometry).unwrap();
+        let vertices = geometry["coordinates"].as_array().ok_or("the route of the corridor has no geometry")?
+            .iter()
+            .map(|pos| match (pos[0].as_f64(), pos[1].as_f64()) {
+                (Some(lon), Some(lat)) => Ok(Location { longitude: lon as f32, latitude: lat as f32 }),
+                _ => Err("invalid position in the geometry of the route".to_string()),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Polyline::new(&vertices).map_err(|e| format!("the route of the corridor is degenerate: {e}"))
+    }
+
+    /// This method returns a new city within the buffer of the route. Its position
+    /// along the route is uniform, and its offset from the route is either uniform
+    /// over the buffer or normal (redrawn until it falls in the buffer). The city is
+    /// moved perpendicularly to the segment of the route it is drawn on, which keeps
+    /// it inside the buffer around the curves of the route.
+    fn random_pos_along(&self, rng: &mut impl Rng, route: &Polyline) -> Location {
+        let buffer = self.buffer_km * 1000.0;
+        let along = Uniform::new_inclusive(0.0, route.length()).sample(rng);
+        let offset = match self.lateral_std_km {
+            None => Uniform::new_inclusive(-buffer, buffer).sample(rng),
+            Some(std) => {
+                let dist = Normal::new(0.0, std * 1000.0).expect("cannot create normal dist");
+                loop {
+                    let offset: f64 = dist.sample(rng);
+                    if offset.abs() <= buffer {
+                        break offset;
+                    }
+                }
+            }
+        };
+        route.point_at(along, offset)
     }
 
     /// This method returns a new city close to the given centroid
@@ -150,48 +691,262 @@ impl GenerateInstance {
         let lat = dist_y.sample(rng);
         Location { longitude: lon, latitude: lat }
     }
+
+    /// This method draws points with the given sampler until one of them is admissible:
+    /// it falls in the zone and lies at least `min_separation` away from the `accepted`
+    /// locations. It fails when the rejection budget of the zone is exhausted.
+    fn sample_admissible(&self, zone: &Zone, accepted: &[Location], what: &str, mut sample: impl FnMut() -> Location) -> Result<Location, String> {
+        loop {
+            let location = sample();
+            if zone.contains(location) && self.is_separated(accepted, location) {
+                return Ok(location);
+            }
+            zone.reject().map_err(|rejected| {
+                let mut constraints = vec![];
+                if zone.area.is_some() {
+                    constraints.push("inside the boundary".to_string());
+                }
+                if !zone.exclusions.is_empty() {
+                    constraints.push("outside the exclusion zones".to_string());
+                }
+                if let Some(separation) = self.min_separation {
+                    constraints.push(format!("at least {separation} m away from the {} already placed", accepted.len()));
+                }
+                format!("could not sample a {what} {} after rejecting {rejected} points", constraints.join(" and "))
+            })?;
+        }
+    }
+
+    /// This method returns true iff the given location lies at least `min_separation`
+    /// metres away from all the accepted locations
+    fn is_separated(&self, accepted: &[Location], location: Location) -> bool {
+        match self.min_separation {
+            Some(separation) => accepted.iter().all(|other| haversine(*other, location) >= separation as f64),
+            None => true,
+        }
+    }
     
     /// This method maps a set of location to the nearset routable point on the map.
-    async fn routable_cities(&self, client: &Client, locations: &[Location]) -> Vec<Location> {
-        let mut out = vec![];
-        for loc in locations {
-            let rsp = NearestRequestBuilder::default()
-                .coordinates(osrm_client::Coordinates::Single(*loc))
-                .build()
-                .unwrap()
-                .send(&client)
-                .await
-                .unwrap();
-            
-            let wp = &rsp.waypoints.unwrap()[0];
-            out.push(wp.location);
-        }
-        out
-    }
-
-    /// This method computes the travel cost matrix between all the given locations. Depending
-    /// on the 'duration' flag, this method will either return a matrix of durations (in seconds) 
-    /// to reach each location from each other; or it will return the actual distance that is going 
-    /// to be travelled (in metres).
-    async fn travel_cost_matrix(&self, client: &Client, locations: &[Location]) -> Vec<Vec<f32>>{
-        let matrix = TableRequestBuilder::default()
-            .coordinates(osrm_client::Coordinates::Multi(Vec::from_iter(locations.iter().copied())))
-            .annotations(TableAnnotationRequest::Both)
-            .build().unwrap()
-            .send(&client)
-            .await
-            .unwrap();
+    async fn routable_cities(&self, client: &Client, locations: &[Location]) -> Result<Vec<Location>, String> {
+        Ok(self.snap_all(client, locations).await?.into_iter().map(|(location, _)| location).collect())
+    }
 
-        let mut result = vec![];
-        if self.duration {
-            for line in matrix.durations.unwrap().iter() {
-                result.push(line.iter().map(|x| x.unwrap()).collect());
+    /// This method snaps all the given locations (see `snap`), sending at most
+    /// `concurrency` requests at once
+    async fn snap_all(&self, client: &Client, locations: &[Location]) -> Result<Vec<(Location, f32)>, String> {
+        let progress = self.progress(locations.len(), "snapping");
+        let snapped = stream::iter(locations.iter().copied())
+            .map(|location| self.snap(client, location))
+            .buffered(self.concurrency.max(1))
+            .inspect(|_| progress.inc(1))
+            .collect::<Vec<_>>()
+            .await;
+        progress.finish_and_clear();
+        snapped.into_iter().collect()
+    }
+
+    /// This method computes the matrices of the instance: the cost matrix along with
+    /// the durations and their snapshots fetched from the osrm server, or the
+    /// transit travel times alone when the instance is built from a GTFS feed
+    async fn matrices(&self, client: &Client, locations: &[Location]) -> Result<(Matrix, Option<Matrix>, Option<Vec<(String, Matrix)>>), String> {
+        let Some(path) = self.gtfs.as_deref() else {
+            let (distances, durations) = self.travel_matrices(client, locations).await?;
+            return Ok((distances, durations, self.durations_by_time(client, locations).await?));
+        };
+        let [departure] = self.departure.as_slice() else {
+            return Err("a transit instance needs exactly one --departure time".to_string());
+        };
+        let departure_time = parse_time(departure)?;
+        let feed = Feed::load(path, self.gtfs_date.as_deref())?;
+        if feed.size().1 == 0 {
+            return Err(format!("no trip of the GTFS feed in {path} runs on the given date"));
+        }
+        Ok((feed.travel_times(locations, departure_time, self.max_walk), None, None))
+    }
+
+    /// This method computes the cost matrix of the instance (durations when the
+    /// 'duration' flag is set, distances otherwise) and, when the costs are
+    /// distances, the durations matrix that goes with it
+    async fn travel_matrices(&self, client: &Client, locations: &[Location]) -> Result<(Matrix, Option<Matrix>), String> {
+        let (distances, durations) = travel_matrices(client, locations).await?;
+        Ok(if self.duration { (durations, None) } else { (distances, Some(durations)) })
+    }
+
+    /// This method fetches one snapshot of the durations matrix per departure time
+    async fn durations_by_time(&self, client: &Client, locations: &[Location]) -> Result<Option<Vec<(String, Matrix)>>, String> {
+        if self.departure.is_empty() {
+            return Ok(None);
+        }
+        let mut snapshots = vec![];
+        for departure in self.departure.iter() {
+            let (label, durations) = match departure.split_once('=') {
+                Some((label, url)) => {
+                    let client = osrm_client::Client::default().base_url(url.to_string());
+                    (label, travel_matrices(&client, locations).await?.1)
+                }
+                None => (departure.as_str(), travel_matrices(client, locations).await?.1),
+            };
+            if snapshots.iter().any(|(l, _)| l == label) {
+                return Err(format!("the departure '{label}' is given more than once"));
             }
-        } else {
-            for line in matrix.distances.unwrap().iter() {
-                result.push(line.iter().map(|x| x.unwrap()).collect());
+            snapshots.push((label.to_string(), durations));
+        }
+        Ok(Some(snapshots))
+    }
+
+    /// This method returns a progress bar for a task made of `len` requests. The
+    /// bar is hidden in quiet mode or when stderr is not a terminal.
+    fn progress(&self, len: usize, task: &str) -> ProgressBar {
+        if self.quiet || !std::io::stderr().is_terminal() {
+            return ProgressBar::hidden();
+        }
+        let progress = ProgressBar::with_draw_target(Some(len as u64), ProgressDrawTarget::stderr());
+        progress.set_style(ProgressStyle::with_template("{msg:>10} [{bar:40}] {pos}/{len} (eta {eta})").unwrap());
+        progress.set_message(task.to_string());
+        progress
+    }
+
+    /// This method returns the nearest routable point of the given location along
+    /// with the distance (in metres) separating them
+    async fn snap(&self, client: &Client, location: Location) -> Result<(Location, f32), String> {
+        let rsp = NearestRequestBuilder::default()
+            .coordinates(osrm_client::Coordinates::Single(location))
+            .build()
+            .unwrap()
+            .send(client)
+            .await
+            .map_err(|_| format!("cannot snap {},{} to the road network", location.latitude, location.longitude))?;
+
+        let wp = rsp.waypoints.as_ref().and_then(|waypoints| waypoints.first())
+            .ok_or_else(|| format!("there is no routable point near {},{}", location.latitude, location.longitude))?;
+        Ok((wp.location, wp.distance))
+    }
+
+    /// This method snaps the generated destinations to the road network. The ones
+    /// that are moved farther than `max_snap_distance` or that collapse onto (or too
+    /// close to) an already snapped destination are replaced by new random cities. It returns the
+    /// snapped destinations and their snap distances.
+    async fn snap_destinations(&self, client: &Client, rng: &mut impl Rng, spread: Spread<'_>, zone: &Zone<'_>, destinations: Vec<Location>)
+        -> Result<(Vec<Location>, Vec<f32>), String> 
+    {
+        let mut out: Vec<Location> = vec![];
+        let mut snap_distances = vec![];
+        let mut too_far = 0;
+        let mut duplicates = 0;
+        let mut excluded = 0;
+        let mut candidates = self.snap_all(client, &destinations).await?.into_iter();
+        let mut resampled = 0;
+        while out.len() < self.nb_cities {
+            let (location, distance) = match candidates.next() {
+                Some(candidate) => candidate,
+                None => {
+                    resampled += 1;
+                    let candidate = self.random_city(rng, spread, resampled, zone, &out)?;
+                    self.snap(client, candidate).await?
+                }
+            };
+
+            if self.max_snap_distance.is_some_and(|max| distance > max) {
+                too_far += 1;
+            } else if !zone.contains(location) {
+                excluded += 1;
+            } else if out.iter().any(|l| l.longitude == location.longitude && l.latitude == location.latitude)
+                || !self.is_separated(&out, location) {
+                duplicates += 1;
+            } else {
+                out.push(location);
+                snap_distances.push(distance);
+                continue;
             }
+            zone.reject().map_err(|rejected| format!(
+                "could only snap {} destinations to the road network after rejecting {rejected} points", out.len()))?;
+        }
+
+        if too_far + duplicates + excluded > 0 {
+            eprintln!("warning: replaced {too_far} destinations too far from the road network, {excluded} destinations snapped outside the allowed zone and {duplicates} destinations snapped onto (or too close to) another one");
         }
-        result
+        Ok((out, snap_distances))
     }
 }
+
+/// The part of the map where the generated points may fall: inside the area (if
+/// any) and outside all the exclusion zones. All the points rejected during a
+/// generation, whatever the reason, draw from the same retry budget.
+struct Zone<'a> {
+    area: Option<&'a Area>,
+    exclusions: &'a [Area],
+    /// The number of points rejected so far
+    rejected: Cell<usize>,
+    max_rejected: usize,
+}
+
+impl Zone<'_> {
+    /// Returns true iff the given location may be used
+    fn contains(&self, location: Location) -> bool {
+        self.area.is_none_or(|area| area.contains(location)) && !self.exclusions.iter().any(|zone| zone.contains(location))
+    }
+
+    /// Records a rejected point, fails with the number of rejected points when
+    /// the budget is exhausted
+    fn reject(&self) -> Result<(), usize> {
+        let rejected = self.rejected.get() + 1;
+        self.rejected.set(rejected);
+        if rejected > self.max_rejected { Err(rejected) } else { Ok(()) }
+    }
+}
+
+/// Computes both the distance matrix (in metres) and the durations matrix (in
+/// seconds) between all the given locations with a single request. Pairs that
+/// cannot be routed are marked with an infinite cost.
+pub async fn travel_matrices(client: &Client, locations: &[Location]) -> Result<(Matrix, Matrix), String> {
+    let matrix = TableRequestBuilder::default()
+        .coordinates(osrm_client::Coordinates::Multi(Vec::from_iter(locations.iter().copied())))
+        .annotations(TableAnnotationRequest::Both)
+        .build().unwrap()
+        .send(client)
+        .await
+        .map_err(|_| "cannot fetch the travel matrices from the router".to_string())?;
+
+    // the router returns null for the pairs it cannot route between
+    let unreachable = |x: &Option<f32>| x.unwrap_or(f32::INFINITY);
+    let convert = |matrix: Vec<Vec<Option<f32>>>| matrix.iter()
+        .map(|line| line.iter().map(unreachable).collect())
+        .collect::<Matrix>();
+    let missing = |name: &str| format!("the router did not return the {name} matrix");
+    Ok((convert(matrix.distances.ok_or_else(|| missing("distance"))?), convert(matrix.durations.ok_or_else(|| missing("duration"))?)))
+}
+
+/// The current time, in seconds since the unix epoch
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+/// How the generated destinations are spread over the map
+#[derive(Debug, Clone, Copy)]
+enum Spread<'a> {
+    /// Around the centroids
+    Clusters(&'a [Location]),
+    /// Proportionally to the weights of a density grid
+    Density(&'a DensityGrid),
+    /// Within the buffer of the route of a corridor
+    Corridor(&'a Polyline),
+}
+
+/// Parses a place given as `latitude,longitude`
+fn parse_lat_lon(text: &str) -> Result<(f32, f32), String> {
+    let invalid = || format!("invalid place '{text}', expected latitude,longitude");
+    let (lat, lon) = text.split_once(',').ok_or_else(invalid)?;
+    let lat = lat.trim().parse::<f32>().map_err(|_| invalid())?;
+    let lon = lon.trim().parse::<f32>().map_err(|_| invalid())?;
+    if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lon) {
+        return Err(invalid());
+    }
+    Ok((lat, lon))
+}
+
+/// Splits one row of a csv file and trims the quotes around each field
+fn split_row(line: &str, separator: char) -> Vec<String> {
+    line.split(separator)
+        .map(|field| field.trim().trim_matches('"').to_string())
+        .collect()
+}
@@ -0,0 +1,269 @@
+//! This module provides the few geometric primitives that are needed to
+//! constrain the generation of destinations to a given area of the map, or
+//! to the surroundings of a route.
+
+use std::fs::read_to_string;
+
+use osrm_client::Location;
+use rand::Rng;
+use rand_distr::{Distribution, Uniform};
+use serde_json::Value;
+
+use crate::instance::checksum;
+
+/// A polygon described by its outer ring followed by its holes (if any). Each
+/// ring is a sequence of (longitude, latitude) pairs.
+#[derive(Debug, Clone)]
+pub struct Polygon {
+    pub rings: Vec<Vec<(f64, f64)>>,
+}
+
+impl Polygon {
+    /// Returns true iff the given point lies inside the outer ring of this
+    /// polygon and outside all of its holes
+    pub fn contains(&self, lon: f64, lat: f64) -> bool {
+        let mut rings = self.rings.iter();
+        match rings.next() {
+            Some(outer) => ring_contains(outer, lon, lat) && rings.all(|hole| !ring_contains(hole, lon, lat)),
+            None => false,
+        }
+    }
+}
+
+/// An area of the map made of one or several polygons
+#[derive(Debug, Clone)]
+pub struct Area {
+    pub polygons: Vec<Polygon>,
+}
+
+impl Area {
+    /// Reads an area from a GeoJSON file. The file may contain a Polygon or a
+    /// MultiPolygon geometry, or a Feature/FeatureCollection holding some.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = read_to_string(path).map_err(|e| format!("cannot read {path}: {e}"))?;
+        let json: Value = serde_json::from_str(&text).map_err(|e| format!("cannot parse {path}: {e}"))?;
+        Self::from_geojson(&json).map_err(|e| format!("{path}: {e}"))
+    }
+
+    /// Extracts an area from a GeoJSON value
+    pub fn from_geojson(json: &Value) -> Result<Self, String> {
+        let mut polygons = vec![];
+        collect_polygons(json, &mut polygons)?;
+        if polygons.is_empty() {
+            return Err("the GeoJSON does not contain any polygon".to_string());
+        }
+        Ok(Area { polygons })
+    }
+
+    /// Returns true iff the given location lies inside one of the polygons
+    pub fn contains(&self, location: Location) -> bool {
+        let (lon, lat) = (location.longitude as f64, location.latitude as f64);
+        self.polygons.iter().any(|p| p.contains(lon, lat))
+    }
+
+    /// Returns the bounding box of the area as (min_lon, max_lon, min_lat, max_lat)
+    pub fn bbox(&self) -> (f64, f64, f64, f64) {
+        let points = self.polygons.iter().flat_map(|p| p.rings.iter().flatten());
+        points.fold((f64::INFINITY, f64::NEG_INFINITY, f64::INFINITY, f64::NEG_INFINITY),
+            |(min_lon, max_lon, min_lat, max_lat), (lon, lat)|
+            (min_lon.min(*lon), max_lon.max(*lon), min_lat.min(*lat), max_lat.max(*lat)))
+    }
+}
+
+/// A grid of non negative weights covering a rectangle of the map, used to sample
+/// locations proportionally to a density surface. The grid is read from a csv
+/// file with one row of weights per line: the first row is the north most one
+/// and the first column the west most one.
+#[derive(Debug, Clone)]
+pub struct DensityGrid {
+    /// The cumulated weights of the cells, stored row by row
+    cumulated: Vec<f64>,
+    nb_cols: usize,
+    /// The (min longitude, max longitude, min latitude, max latitude) covered
+    bounds: (f32, f32, f32, f32),
+    /// The checksum of the file the grid was read from
+    pub checksum: String,
+}
+
+impl DensityGrid {
+    /// Reads the grid stored in the given csv file and stretches it over the
+    /// given (min longitude, max longitude, min latitude, max latitude) bounds
+    pub fn load(path: &str, bounds: (f32, f32, f32, f32)) -> Result<Self, String> {
+        let text = read_to_string(path).map_err(|e| format!("cannot read {path}: {e}"))?;
+        let separator = if text.matches(';').count() > text.matches(',').count() { ';' } else { ',' };
+
+        let mut nb_cols = 0;
+        let mut cumulated = vec![];
+        let mut total = 0.0;
+        for (i, line) in text.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+            let row = line.split(separator)
+                .map(|w| w.trim().parse::<f64>().ok().filter(|w| w.is_finite() && *w >= 0.0))
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(|| format!("{path}: line {} holds an invalid weight", i + 1))?;
+            if nb_cols != 0 && row.len() != nb_cols {
+                return Err(format!("{path}: line {} has {} cells instead of {nb_cols}", i + 1, row.len()));
+            }
+            nb_cols = row.len();
+            for w in row {
+                total += w;
+                cumulated.push(total);
+            }
+        }
+        if total <= 0.0 {
+            return Err(format!("{path}: the density grid has no positive weight"));
+        }
+        Ok(DensityGrid { cumulated, nb_cols, bounds, checksum: checksum(text.as_bytes()) })
+    }
+
+    /// Samples a location: a cell is drawn proportionally to its weight and the
+    /// location is drawn uniformly within that cell
+    pub fn sample(&self, rng: &mut impl Rng) -> Location {
+        let total = self.cumulated[self.cumulated.len() - 1];
+        let u = Uniform::new(0.0, total).sample(rng);
+        let cell = self.cumulated.partition_point(|c| *c <= u).min(self.cumulated.len() - 1);
+        let (row, col) = (cell / self.nb_cols, cell % self.nb_cols);
+
+        let nb_rows = self.cumulated.len() / self.nb_cols;
+        let (min_lon, max_lon, min_lat, max_lat) = self.bounds;
+        let width = (max_lon - min_lon) / self.nb_cols as f32;
+        let height = (max_lat - min_lat) / nb_rows as f32;
+        let within = Uniform::new(0.0_f32, 1.0);
+        Location {
+            longitude: min_lon + (col as f32 + within.sample(rng)) * width,
+            latitude: max_lat - (row as f32 + within.sample(rng)) * height,
+        }
+    }
+}
+
+/// The mean radius of the earth (in metres)
+const EARTH_RADIUS: f64 = 6_371_008.8;
+
+/// Returns the great circle distance (in metres) between two locations
+pub fn haversine(a: Location, b: Location) -> f64 {
+    let (lat_a, lat_b) = ((a.latitude as f64).to_radians(), (b.latitude as f64).to_radians());
+    let d_lat = lat_b - lat_a;
+    let d_lon = (b.longitude as f64 - a.longitude as f64).to_radians();
+    let h = (d_lat / 2.0).sin().powi(2) + lat_a.cos() * lat_b.cos() * (d_lon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS * h.sqrt().asin()
+}
+
+/// The number of metres in a degree of latitude
+const METRES_PER_DEGREE: f64 = EARTH_RADIUS * std::f64::consts::PI / 180.0;
+
+/// A path on the map (ie the geometry of a route) along which points can be
+/// placed. Each segment is measured in a projection tangent to the map at its
+/// middle, which is accurate over the length of a road segment.
+#[derive(Debug, Clone)]
+pub struct Polyline {
+    /// The (longitude, latitude) of the vertices
+    vertices: Vec<(f64, f64)>,
+    /// The distance (in metres) from the start of the path to each vertex
+    lengths: Vec<f64>,
+}
+
+impl Polyline {
+    /// Creates a polyline through the given locations. The repeated vertices
+    /// are ignored but at least two distinct vertices are needed.
+    pub fn new(locations: &[Location]) -> Result<Self, String> {
+        let mut vertices: Vec<(f64, f64)> = vec![];
+        let mut lengths = vec![];
+        for location in locations {
+            let vertex = (location.longitude as f64, location.latitude as f64);
+            match vertices.last() {
+                None => lengths.push(0.0),
+                Some(last) if *last == vertex => continue,
+                Some(last) => {
+                    let (dx, dy) = metric_delta(*last, vertex);
+                    lengths.push(lengths.last().unwrap() + dx.hypot(dy));
+                }
+            }
+            vertices.push(vertex);
+        }
+        if vertices.len() < 2 {
+            return Err("a polyline needs at least two distinct vertices".to_string());
+        }
+        Ok(Polyline { vertices, lengths })
+    }
+
+    /// Returns the length of the polyline in metres
+    pub fn length(&self) -> f64 {
+        *self.lengths.last().unwrap()
+    }
+
+    /// Returns the point lying `along` metres from the start of the polyline,
+    /// moved by `offset` metres perpendicularly to the segment it lies on (to
+    /// the left of the path when the offset is positive). The moved point is
+    /// never farther than |offset| from the polyline, even around sharp curves.
+    pub fn point_at(&self, along: f64, offset: f64) -> Location {
+        let along = along.clamp(0.0, self.length());
+        let i = self.lengths.partition_point(|l| *l <= along).clamp(1, self.vertices.len() - 1) - 1;
+        let (a, b) = (self.vertices[i], self.vertices[i + 1]);
+        let t = (along - self.lengths[i]) / (self.lengths[i + 1] - self.lengths[i]);
+        let (lon, lat) = (a.0 + t * (b.0 - a.0), a.1 + t * (b.1 - a.1));
+
+        let (dx, dy) = metric_delta(a, b);
+        let norm = dx.hypot(dy);
+        let (nx, ny) = (-dy / norm * offset, dx / norm * offset);
+        Location {
+            longitude: (lon + nx / (METRES_PER_DEGREE * lat.to_radians().cos())) as f32,
+            latitude: (lat + ny / METRES_PER_DEGREE) as f32,
+        }
+    }
+}
+
+/// Returns the displacement (in metres, towards the east and the north) from
+/// one (longitude, latitude) to another close one
+fn metric_delta(from: (f64, f64), to: (f64, f64)) -> (f64, f64) {
+    let cos = ((from.1 + to.1) / 2.0).to_radians().cos();
+    ((to.0 - from.0) * cos * METRES_PER_DEGREE, (to.1 - from.1) * METRES_PER_DEGREE)
+}
+
+/// Even-odd ray casting test of a point against a closed ring
+fn ring_contains(ring: &[(f64, f64)], lon: f64, lat: f64) -> bool {
+    let mut inside = false;
+    let mut j = ring.len().wrapping_sub(1);
+    for i in 0..ring.len() {
+        let (xi, yi) = ring[i];
+        let (xj, yj) = ring[j];
+        if (yi > lat) != (yj > lat) && lon < (xj - xi) * (lat - yi) / (yj - yi) + xi {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+fn collect_polygons(json: &Value, out: &mut Vec<Polygon>) -> Result<(), String> {
+    match json["type"].as_str() {
+        Some("FeatureCollection") => {
+            for feature in json["features"].as_array().ok_or("a FeatureCollection must have features")? {
+                collect_polygons(feature, out)?;
+            }
+        }
+        Some("Feature") => collect_polygons(&json["geometry"], out)?,
+        Some("Polygon") => out.push(parse_polygon(&json["coordinates"])?),
+        Some("MultiPolygon") => {
+            for polygon in json["coordinates"].as_array().ok_or("a MultiPolygon must have coordinates")? {
+                out.push(parse_polygon(polygon)?);
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn parse_polygon(coordinates: &Value) -> Result<Polygon, String> {
+    let rings = coordinates.as_array().ok_or("a Polygon must have coordinates")?;
+    let rings = rings.iter()
+        .map(|ring| {
+            ring.as_array().ok_or("a polygon ring must be an array of positions")?
+                .iter()
+                .map(|pos| match (pos[0].as_f64(), pos[1].as_f64()) {
+                    (Some(lon), Some(lat)) => Ok((lon, lat)),
+                    _ => Err("a position must be a pair of numbers".to_string()),
+                })
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Polygon { rings })
+}
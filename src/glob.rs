@@ -0,0 +1,139 @@
+//! This module expands the glob patterns in the paths given to the commands,
+//! which the shell does not do when the pattern is quoted (nor on Windows).
+
+use std::path::{Path, PathBuf};
+
+/// Returns true iff the given path holds a wildcard
+pub fn is_pattern(path: &str) -> bool {
+    path.contains(['*', '?'])
+}
+
+/// Expands the given paths: a path holding wildcards (`*` for any sequence of
+/// characters and `?` for any single character, in any component of the path)
+/// is replaced by the sorted paths of the files it matches, the other paths are
+/// kept as they are. Fails when a pattern matches no file.
+pub fn expand(paths: &[String]) -> Result<Vec<String>, String> {
+    let mut expanded = vec![];
+    for path in paths {
+        if !is_pattern(path) {
+            expanded.push(path.clone());
+            continue;
+        }
+        let mut matches = matching_files(path);
+        if matches.is_empty() {
+            return Err(format!("no file matches {path}"));
+        }
+        matches.sort();
+        expanded.extend(matches);
+    }
+    Ok(expanded)
+}
+
+/// Lists the files matched by the given pattern, component by component. Like
+/// in the shells, a wildcard does not match the leading dot of a hidden file.
+fn matching_files(pattern: &str) -> Vec<String> {
+    let mut candidates = vec![PathBuf::new()];
+    for component in Path::new(pattern).components() {
+        let part = component.as_os_str().to_string_lossy();
+        if !is_pattern(&part) {
+            candidates.iter_mut().for_each(|path| path.push(component));
+            continue;
+        }
+        candidates = candidates.into_iter()
+            .flat_map(|dir| {
+                let entries = std::fs::read_dir(if dir.as_os_str().is_empty() { Path::new(".") } else { &dir })
+                    .into_iter().flatten().flatten()
+                    .map(|entry| entry.file_name().to_string_lossy().to_string())
+                    .filter(|name| !name.starts_with('.') || part.starts_with('.'))
+                    .filter(|name| matches(&part, name))
+                    .collect::<Vec<_>>();
+                entries.into_iter().map(move |name| dir.join(name))
+            })
+            .collect();
+    }
+    candidates.into_iter()
+        .filter(|path| path.is_file())
+        .map(|path| path.to_string_lossy().to_string())
+        .collect()
+}
+
+/// Returns true iff the given name matches the given pattern, which holds no
+/// path separator
+fn matches(pattern: &str, name: &str) -> bool {
+    let (pattern, name) = (pattern.chars().collect::<Vec<_>>(), name.chars().collect::<Vec<_>>());
+    let (mut i, mut j) = (0, 0);
+    // the position of the last star in the pattern and the position in the
+    // name from which it is retried when the rest of the pattern fails
+    let mut star = None;
+    while j < name.len() {
+        if i < pattern.len() && (pattern[i] == '?' || pattern[i] == name[j]) {
+            i += 1;
+            j += 1;
+        } else if i < pattern.len() && pattern[i] == '*' {
+            star = Some((i, j));
+            i += 1;
+        } else if let Some((s, k)) = star {
+            star = Some((s, k + 1));
+            i = s + 1;
+            j = k + 1;
+        } else {
+            return false;
+        }
+    }
+    pattern[i..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir, write};
+
+    use tempfile::TempDir;
+
+    use super::{expand, matches};
+
+    #[test]
+    fn a_star_matches_any_sequence_of_characters() {
+        assert!(matches("*.json", "berlin.json"));
+        assert!(matches("*.json", ".json"));
+        assert!(matches("b*n*.json", "berlin.json"));
+        assert!(matches("*", ""));
+        assert!(!matches("*.json", "berlin.tsp"));
+        assert!(!matches("b*z", "berlin"));
+    }
+
+    #[test]
+    fn a_question_mark_matches_one_character() {
+        assert!(matches("city-?.json", "city-1.json"));
+        assert!(!matches("city-?.json", "city-12.json"));
+        assert!(!matches("city-?.json", "city-.json"));
+        assert!(matches("??*", "ab"));
+        assert!(!matches("??*", "a"));
+    }
+
+    #[test]
+    fn a_literal_pattern_only_matches_itself() {
+        assert!(matches("berlin.json", "berlin.json"));
+        assert!(!matches("berlin.json", "berlin.jsonl"));
+        assert!(!matches("berlin.json", "Berlin.json"));
+        let paths = vec!["no/such/file.json".to_string()];
+        assert_eq!(expand(&paths).unwrap(), paths);
+    }
+
+    #[test]
+    fn a_pattern_crosses_the_matching_directories() {
+        let dir = TempDir::new().unwrap();
+        for city in ["berlin", "brussels", "paris"] {
+            create_dir(dir.path().join(city)).unwrap();
+            write(dir.path().join(city).join("instance.json"), "{}").unwrap();
+            write(dir.path().join(city).join("notes.txt"), "").unwrap();
+        }
+        create_dir(dir.path().join(".hidden")).unwrap();
+        write(dir.path().join(".hidden").join("instance.json"), "{}").unwrap();
+
+        let root = dir.path().to_string_lossy().to_string();
+        let expanded = expand(&[format!("{root}/b*/*.json")]).unwrap();
+        assert_eq!(expanded, vec![format!("{root}/berlin/instance.json"), format!("{root}/brussels/instance.json")]);
+        assert_eq!(expand(&[format!("{root}/*/instance.json")]).unwrap().len(), 3);
+        assert!(expand(&[format!("{root}/*/*.tsp")]).unwrap_err().starts_with("no file matches"));
+    }
+}
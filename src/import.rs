@@ -0,0 +1,94 @@
+//! This module implements the command which imports the VROOM problems (and the
+//! openrouteservice optimization requests) as instances.
+
+use clap::Args;
+
+use crate::{generation::{now, travel_matrices}, instance::{Instance, Metadata, Unit, vroom}, status::fail, stdio::{STDIO, input_name, is_stdio, output_name, read_input}};
+
+/// This command builds an instance from a VROOM problem (or an openrouteservice
+/// optimization request, which has the same format), so that the exact solver
+/// can verify the plans of a heuristic dispatcher. The depot is the start of the
+/// vehicle and the destinations are the jobs, labelled with their descriptions.
+/// The custom matrices of the problem are used when it has some (the distances
+/// being the costs when both are given), otherwise the matrices are fetched from
+/// the osrm server for the locations of the jobs.
+///
+/// Only the problems with a single vehicle whose route returns to its start can
+/// be imported. The demands, capacities, time windows and service times of the
+/// problem have no counterpart in an instance: a warning tells when they are
+/// dropped.
+#[derive(Debug, Args)]
+pub struct Import {
+    /// The path to the VROOM problem (`-` for stdin)
+    #[clap(short, long)]
+    pub problem: String,
+    /// Name of the file where to write the instance (stdout by default)
+    #[clap(short, long)]
+    pub output: Option<String>,
+    /// URL of the osrm server to use when the problem has no custom matrix (optional)
+    #[clap(short, long)]
+    pub url_osrm: Option<String>,
+    /// Base the distance matrix on durations rather than distances
+    #[clap(short='D', long)]
+    pub duration: bool,
+}
+
+impl Import {
+    /// Executes this command
+    pub async fn execute(&self, quiet: bool) {
+        if let Err(e) = self.import(quiet).await {
+            fail(e);
+        }
+    }
+
+    async fn import(&self, quiet: bool) -> Result<(), String> {
+        let name = input_name(&self.problem);
+        let problem = vroom::read(&read_input(&self.problem)?).map_err(|e| format!("{name}: {e}"))?;
+        if !problem.dropped.is_empty() {
+            eprintln!("warning: an instance cannot hold {} of the problem: they are dropped", problem.dropped.join(", "));
+        }
+
+        let mut metadata = Metadata::default();
+        let (distances, durations) = match (problem.distances, problem.durations) {
+            (None, None) => {
+                let mut client = osrm_client::Client::default();
+                if let Some(url) = self.url_osrm.as_ref() {
+                    client = client.base_url(url.clone());
+                }
+                metadata.generated_at = Some(now());
+                metadata.router = self.url_osrm.clone();
+                let (distances, durations) = travel_matrices(&client, &problem.places).await?;
+                (Some(distances), Some(durations))
+            }
+            matrices => matrices,
+        };
+        let (costs, durations) = match (distances, durations) {
+            (Some(distances), durations) if !self.duration => (distances, durations),
+            (_, Some(durations)) => {
+                metadata.unit = Unit::Seconds;
+                (durations, None)
+            }
+            _ => return Err(format!("{name} has no custom durations to base the distance matrix on")),
+        };
+
+        let instance = Instance {
+            destinations: problem.places,
+            distances: costs,
+            durations,
+            durations_by_time: None,
+            labels: Some(problem.labels),
+            extras: None,
+            depot: 0,
+            forbidden_arcs: vec![],
+            forced_arcs: vec![],
+            metadata,
+        };
+        instance.validate().map_err(|e| format!("{name}: {e}"))?;
+        let path = self.output.as_deref().unwrap_or(STDIO);
+        instance.save(Some(path))?;
+        if !quiet && !is_stdio(path) {
+            println!("imported {name} ({} jobs) to {}", instance.destinations.len() - 1, output_name(path));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,71 @@
+//! This module implements a debugging command which evaluates the bound of a
+//! single state of the decision diagrams.
+
+use std::{fs::read_to_string, path::Path};
+
+use clap::Args;
+use ddo::Relaxation;
+use serde_json::json;
+
+use crate::{instance::{Instance, Unit}, resolution::{MergePolicy, ObjectiveSense, RelaxVariant, TspModel, TspRelax, TspState}, status::fail};
+
+/// This command reads a state of the decision diagrams (as json, ie
+/// `{"depth": 2, "current": [3], "must_visit": [0, 1, 4], "might_visit": []}`)
+/// and prints the fast upper bound computed for it on the given instance.
+#[derive(Debug, Args)]
+pub struct InspectState {
+    /// The path to the instance file
+    #[clap(short, long)]
+    pub instance: String,
+    /// The state to inspect (path to a json file or inline json)
+    #[clap(short, long)]
+    pub state: String,
+    /// Whether the shortest (min) or the longest (max) tour is looked for
+    #[clap(long, value_enum, default_value="min")]
+    pub objective_sense: ObjectiveSense,
+    /// Overrides the unit of the distance matrix declared by the instance
+    #[clap(long, value_enum)]
+    pub unit: Option<Unit>,
+}
+
+impl InspectState {
+    /// Executes this command. In quiet mode, the outcome is printed as json.
+    pub async fn execute(&self, quiet: bool) {
+        if let Err(e) = self.inspect(quiet) {
+            fail(e);
+        }
+    }
+
+    fn inspect(&self, quiet: bool) -> Result<(), String> {
+        let instance = Instance::load(&self.instance)?;
+        let unit = self.unit.unwrap_or(instance.metadata.unit);
+        let model = TspModel::new(instance, self.objective_sense)?;
+        let n = model.instance.destinations.len();
+
+        let text = if Path::new(&self.state).is_file() {
+            read_to_string(&self.state).map_err(|e| format!("cannot read {}: {e}", self.state))?
+        } else {
+            self.state.clone()
+        };
+        let state: TspState = serde_json::from_str(&text).map_err(|e| format!("invalid state: {e}"))?;
+        let sets = [state.current(), state.must_visit(), state.might_visit()];
+        if let Some(v) = sets.iter().flat_map(|s| s.iter()).find(|v| *v as usize >= n) {
+            return Err(format!("the state refers to destination {v} but the instance only has {n} destinations"));
+        }
+        if state.depth() > n {
+            return Err(format!("the depth of the state ({}) exceeds the number of destinations ({n})", state.depth()));
+        }
+
+        let relaxation = TspRelax{model: &model, variant: RelaxVariant::Plain, merge_policy: MergePolicy::UnionCurrent};
+        let bound = relaxation.fast_upper_bound(&state);
+        let (length, symbol) = unit.report(self.objective_sense.tour_length(bound));
+        if quiet {
+            println!("{}", json!({"state": state, "fast_upper_bound": bound, "remaining_length": length, "unit": symbol}));
+        } else {
+            println!("state {state}");
+            println!("fast upper bound {bound}");
+            println!("bound on the remaining length {length:.3} {symbol}");
+        }
+        Ok(())
+    }
+}
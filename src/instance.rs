@@ -3,7 +3,22 @@
 //! each location from each other.
 
 use osrm_client::{Location, GeoJsonGeometry, GeoJsonPoint};
+use std::{fs::File, io::{BufRead, BufReader, Read, Write}, path::Path};
+
+use crate::stdio::{STDIO, input_name, is_stdio, open_input, open_output, write_output};
+
+use clap::ValueEnum;
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
 use serde::{Serialize, Deserialize};
+use serde_json::Value;
+
+pub mod binary;
+pub mod csv;
+mod matrix;
+pub mod tsplib;
+pub mod vroom;
+
+pub use self::matrix::Matrix;
 
 /// A TSP instance that knows the gps coordinates of the destinations that must
 /// be visited along with the distances to travel from one city to the other.
@@ -11,11 +26,634 @@ use serde::{Serialize, Deserialize};
 pub struct Instance {
     /// The gps coordinates of the places that must be visited.
     pub destinations: Vec<Location>,
-    /// The distance (in metres) between all pairs of destinations
-    pub distances: Vec<Vec<f32>>,
+    /// The distance (in metres) between all pairs of destinations. A pair of
+    /// destinations that cannot be reached from one another is represented by
+    /// `null` in json and by an infinite distance in memory.
+    pub distances: Matrix,
+    /// The travel time (in seconds) between all pairs of destinations, when it is
+    /// known. Unreachable pairs are encoded like in `distances`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub durations: Option<Matrix>,
+    /// Snapshots of the travel times (in seconds) for several departure times, each
+    /// one labelled with its departure time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub durations_by_time: Option<Vec<(String, Matrix)>>,
+    /// An optional human readable name for each of the destinations
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub labels: Option<Vec<String>>,
+    /// The extra fields of each destination (ie a customer id, a priority or some
+    /// notes), which the tools do not interpret but keep along with their
+    /// destination when they rewrite the instance
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extras: Option<Vec<Value>>,
+    /// The index of the destination where the tours start and end (0 by default)
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub depot: usize,
+    /// The legs (from, to) which must not be used by the tours
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub forbidden_arcs: Vec<(usize, usize)>,
+    /// The legs (from, to) which must be used by the tours: `to` immediately
+    /// follows `from`. They must form vertex-disjoint paths.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub forced_arcs: Vec<(usize, usize)>,
+    /// Information about how the instance was produced
+    #[serde(default)]
+    pub metadata: Metadata,
+}
+
+/// Information about an instance which is not needed to solve it, but which is
+/// needed to interpret it (or its solutions) correctly.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Metadata {
+    /// The unit of the entries in the distance matrix
+    #[serde(default)]
+    pub unit: Unit,
+    /// When the distance matrix was computed (in seconds since the unix epoch)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generated_at: Option<u64>,
+    /// The url of the osrm server which computed the matrix (when it was not the
+    /// default one)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub router: Option<String>,
+    /// The seed of the random generation of the destinations
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u128>,
+    /// The GeoJSON file delimiting the area where the destinations were generated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub boundary: Option<String>,
+    /// The name of the administrative area where the destinations were generated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub area: Option<String>,
+    /// The id of the OpenStreetMap relation delimiting that area
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub osm_relation: Option<u64>,
+    /// The route around which the destinations were generated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub corridor: Option<Corridor>,
+    /// The distance (in metres) by which each destination was moved when it was
+    /// snapped to the road network
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snap_distances: Option<Vec<f32>>,
+    /// The csv grid of weights the destinations were sampled from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub density: Option<String>,
+    /// The checksum of the density grid file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub density_checksum: Option<String>,
+    /// The minimum distance (in metres, as the crow flies) between two destinations
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_separation: Option<f32>,
+    /// The GeoJSON files delimiting the zones where no destination was generated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exclusions: Option<Vec<String>>,
+    /// How the instance was anonymized, if it was
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub anonymization: Option<Anonymization>,
+    /// The public transport journeys whose travel times fill the distance matrix,
+    /// when the instance was generated from a GTFS feed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transit: Option<Transit>,
+}
+
+/// The formats of the instance files. Except for the binary format, the files
+/// may be compressed with gzip.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum InstanceFormat {
+    /// The json serialization of an instance, which holds all of its fields
+    Json,
+    /// The binary format of the large instances (`.tspb`), which holds all of
+    /// their fields
+    Binary,
+    /// A TSPLIB file (`.tsp` or `.atsp`) holding a matrix of integer weights
+    Tsplib,
+    /// A bare distance matrix (`.csv`)
+    Csv,
+}
+
+impl InstanceFormat {
+    /// Infers the format of a file from its extension, ignoring the `.gz`
+    /// extension of a compressed file (json by default)
+    pub fn of(path: &str) -> Self {
+        let path = path.strip_suffix(".gz").unwrap_or(path);
+        match Path::new(path).extension().and_then(|e| e.to_str()) {
+            Some(binary::EXTENSION) => InstanceFormat::Binary,
+            Some("tsp" | "atsp")    => InstanceFormat::Tsplib,
+            Some("csv")             => InstanceFormat::Csv,
+            _                       => InstanceFormat::Json,
+        }
+    }
+
+    /// Lists what the given instance holds which is lost when it is written in
+    /// this format
+    pub fn lost(self, instance: &Instance) -> Vec<&'static str> {
+        if matches!(self, InstanceFormat::Json | InstanceFormat::Binary) {
+            return vec![];
+        }
+        let mut lost = vec![];
+        if self == InstanceFormat::Csv && instance.has_coordinates() {
+            lost.push("the coordinates");
+        }
+        if instance.labels.is_some() {
+            lost.push("the labels");
+        }
+        if instance.extras.is_some() {
+            lost.push("the extra fields of the destinations");
+        }
+        if instance.durations.is_some() || instance.durations_by_time.is_some() {
+            lost.push("the durations");
+        }
+        if self == InstanceFormat::Csv && instance.depot != 0 {
+            lost.push("the depot");
+        }
+        if !instance.forbidden_arcs.is_empty() || !instance.forced_arcs.is_empty() {
+            lost.push("the forbidden and forced arcs");
+        }
+        let bare = Metadata { unit: instance.metadata.unit, ..Default::default() };
+        if serde_json::to_value(&instance.metadata).ok() != serde_json::to_value(&bare).ok() {
+            lost.push("the metadata");
+        }
+        if self == InstanceFormat::Csv && instance.metadata.unit != Unit::Meters {
+            lost.push("the unit");
+        }
+        if self == InstanceFormat::Tsplib && instance.distances.iter().flatten().any(|d| d.is_finite() && d.fract() != 0.0) {
+            lost.push("the fractional part of the distances");
+        }
+        lost
+    }
+}
+
+/// How the coordinates of an anonymized instance were hidden
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Anonymization {
+    /// The coordinates were removed (they are all 0)
+    Removed,
+    /// The coordinates were moved by a random rotation and translation
+    Jittered,
+}
+
+/// A corridor along the route between two places: the destinations lie at most
+/// `buffer_km` kilometres away from the route
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Corridor {
+    /// The start of the route as (latitude, longitude)
+    pub from: (f32, f32),
+    /// The end of the route as (latitude, longitude)
+    pub to: (f32, f32),
+    pub buffer_km: f64,
+    /// The standard deviation (in kilometres) of the distance between the
+    /// destinations and the route, when it is not uniform over the buffer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lateral_std_km: Option<f64>,
+}
+
+/// The public transport journeys of a transit instance
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Transit {
+    /// The GTFS feed giving the timetables
+    pub feed: String,
+    /// The departure time of the journeys (HH:MM)
+    pub departure: String,
+    /// The date of the journeys (YYYYMMDD), when the trips were restricted to the
+    /// services running that day
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub date: Option<String>,
+    /// The longest walk (in metres) to, from or between the stops
+    pub max_walk: f64,
+}
+
+/// Maps the arcs between the destinations at the given indices to the positions
+/// of these destinations, the other arcs are dropped
+fn pick_arcs(arcs: &[(usize, usize)], indices: &[usize]) -> Vec<(usize, usize)> {
+    arcs.iter()
+        .filter_map(|(from, to)| Some((indices.iter().position(|i| i == from)?, indices.iter().position(|i| i == to)?)))
+        .collect()
+}
+
+/// Opens the file at the given path (or stdin) for reading, decompressing it on
+/// the fly when it is compressed with gzip
+fn open_reader(path: &str) -> Result<Box<dyn BufRead>, String> {
+    let mut input = open_input(path)?;
+    let compressed = input.fill_buf().map_err(|e| format!("cannot read {}: {e}", input_name(path)))?.starts_with(&[0x1f, 0x8b]);
+    if compressed {
+        Ok(Box::new(BufReader::new(GzDecoder::new(input))))
+    } else {
+        Ok(input)
+    }
+}
+
+/// Reads the whole (possibly compressed) text from the given reader, opened on
+/// the given path
+fn read_text(path: &str, mut reader: impl Read) -> Result<String, String> {
+    let mut text = String::new();
+    reader.read_to_string(&mut text).map_err(|e| format!("cannot read {}: {e}", input_name(path)))?;
+    Ok(text)
+}
+
+/// Writes the given text in the file at the given path, compressed with gzip when
+/// the path ends with `.gz` (or on stdout)
+fn write_text(path: &str, text: &str) -> Result<(), String> {
+    if !path.ends_with(".gz") {
+        return write_output(path, text);
+    }
+    let error = |e: std::io::Error| format!("cannot write {path}: {e}");
+    let mut encoder = GzEncoder::new(open_output(path)?, Compression::default());
+    encoder.write_all(text.as_bytes()).map_err(error)?;
+    encoder.finish().map_err(error).map(|_| ())
+}
+
+/// Tells whether a number is zero (the default values are not serialized)
+fn is_zero(value: &usize) -> bool {
+    *value == 0
+}
+
+/// Tells what the json file at the given path holds when it is a file produced by
+/// this crate which is commonly mistaken for an instance (stdin cannot be read
+/// a second time)
+fn misplaced_file_kind(path: &str) -> Option<&'static str> {
+    let file = File::open(path).ok().filter(|_| !is_stdio(path))?;
+    let value: serde_json::Value = serde_json::from_reader(BufReader::new(file)).ok()?;
+    let object = value.as_object()?;
+    if object.contains_key("destinations") {
+        None
+    } else if object.contains_key("tour") || object.contains_key("is_exact") {
+        Some("a solve report")
+    } else if object.contains_key("runs") {
+        Some("the report of repeated solver runs")
+    } else {
+        None
+    }
+}
+
+/// The unit in which the entries of a distance matrix are expressed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Unit {
+    #[default]
+    Meters,
+    Kilometers,
+    Miles,
+    Seconds,
+}
+
+/// How the distances are rounded when they are converted to integers (by the
+/// solver and by the TSPLIB export). Rounding to the nearest integer may break
+/// the triangle inequality of a metric matrix.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Rounding {
+    #[default]
+    Nearest,
+    Floor,
+    Ceil,
+}
+
+impl Rounding {
+    /// Rounds the given value to an integer
+    pub fn round(self, value: f64) -> f64 {
+        match self {
+            Rounding::Nearest => value.round(),
+            Rounding::Floor   => value.floor(),
+            Rounding::Ceil    => value.ceil(),
+        }
+    }
+}
+
+impl Unit {
+    /// Converts a total expressed in this unit to the unit used in the reports
+    /// (kilometers for metric distances, miles and hours otherwise) and returns
+    /// the converted value along with the symbol of the reported unit.
+    pub fn report(self, value: f64) -> (f64, &'static str) {
+        match self {
+            Unit::Meters     => (value / 1000.0, self.report_symbol()),
+            Unit::Seconds    => (value / 3600.0, self.report_symbol()),
+            _                => (value, self.report_symbol()),
+        }
+    }
+
+    /// Converts a total expressed in the unit of the reports back to this unit
+    pub fn from_report(self, value: f64) -> f64 {
+        match self {
+            Unit::Meters  => value * 1000.0,
+            Unit::Seconds => value * 3600.0,
+            _             => value,
+        }
+    }
+
+    /// The symbol of the unit used to report totals expressed in this unit
+    pub fn report_symbol(self) -> &'static str {
+        match self {
+            Unit::Meters | Unit::Kilometers => "km",
+            Unit::Miles                     => "mi",
+            Unit::Seconds                   => "h",
+        }
+    }
 }
 
 impl Instance {
+    /// Creates an instance from the coordinates of its destinations and its
+    /// distance matrix (expressed in the given unit)
+    pub fn from_distances(destinations: Vec<Location>, distances: Matrix, unit: Unit) -> Result<Self, String> {
+        let instance = Instance {
+            destinations,
+            distances,
+            durations: None,
+            durations_by_time: None,
+            labels: None,
+            extras: None,
+            depot: 0,
+            forbidden_arcs: vec![],
+            forced_arcs: vec![],
+            metadata: Metadata { unit, ..Default::default() },
+        };
+        instance.validate()?;
+        Ok(instance)
+    }
+
+    /// Reads the instance stored in the file at the given path (or piped on stdin
+    /// when the path is `-`) and checks that it is well formed. The format of the
+    /// file is inferred from its extension (see `InstanceFormat::of`), binary
+    /// instances being recognized whatever their extension. An instance piped on
+    /// stdin is json unless it is binary.
+    pub fn load(path: &str) -> Result<Self, String> {
+        Self::load_with(path, false)
+    }
+
+    /// Reads an instance like `load`. When `truncate_to_matrix` is set and the
+    /// (square) distance matrix is smaller than the list of destinations, the
+    /// surplus destinations are dropped instead of rejecting the instance.
+    pub fn load_with(path: &str, truncate_to_matrix: bool) -> Result<Self, String> {
+        Self::load_as(path, None, truncate_to_matrix)
+    }
+
+    /// Reads an instance like `load_with`, in the given format rather than the
+    /// one inferred from the path when there is one
+    pub fn load_as(path: &str, format: Option<InstanceFormat>, truncate_to_matrix: bool) -> Result<Self, String> {
+        let name = input_name(path);
+        let mut reader = open_reader(path)?;
+        let format = match format {
+            Some(format) => format,
+            None if is_stdio(path) => {
+                let head = reader.fill_buf().map_err(|e| format!("cannot read {name}: {e}"))?;
+                if binary::has_magic(head) { InstanceFormat::Binary } else { InstanceFormat::Json }
+            }
+            None if binary::is_binary(path) => InstanceFormat::Binary,
+            None => InstanceFormat::of(path),
+        };
+        let mut instance = match format {
+            InstanceFormat::Json   => Self::read_json(path, reader)?,
+            // stdin cannot be memory mapped
+            InstanceFormat::Binary if is_stdio(path) => {
+                let mut bytes = vec![];
+                reader.read_to_end(&mut bytes).map_err(|e| format!("cannot read {name}: {e}"))?;
+                binary::parse(&bytes, name)?
+            }
+            InstanceFormat::Binary => binary::read(path)?,
+            InstanceFormat::Tsplib => tsplib::read(&read_text(path, reader)?).map_err(|e| format!("{name}: {e}"))?,
+            InstanceFormat::Csv    => csv::read(&read_text(path, reader)?, Unit::Meters).map_err(|e| format!("{name}: {e}"))?,
+        };
+        let (n, m) = (instance.destinations.len(), instance.distances.len());
+        if truncate_to_matrix && m < n && instance.distances.iter().all(|row| row.len() == m) {
+            eprintln!("warning: dropped the last {} destinations of {name} which have no distances", n - m);
+            instance = instance.subset(&(0..m).collect::<Vec<_>>());
+        }
+        instance.validate().map_err(|e| format!("{name}: {e}"))?;
+        Ok(instance)
+    }
+
+    /// Parses the json instance read from the given reader, opened on the given
+    /// path
+    fn read_json(path: &str, reader: impl Read) -> Result<Self, String> {
+        let name = input_name(path);
+        let mut deserializer = serde_json::Deserializer::from_reader(reader);
+        serde_path_to_error::deserialize(&mut deserializer).map_err(|e| {
+            if let Some(kind) = misplaced_file_kind(path) {
+                return format!("{path} is {kind}, not an instance");
+            }
+            match e.path().to_string().as_str() {
+                "." => format!("cannot parse the instance in {name}: {}", e.inner()),
+                at  => format!("cannot parse the instance in {name} at {at}: {}", e.inner()),
+            }
+        })
+    }
+
+    /// Writes this instance in the file at the given path, in the format inferred
+    /// from its extension (see `InstanceFormat::of`) and compressed with gzip when
+    /// it ends with `.gz`. The instance is printed as pretty json on stdout when
+    /// there is no path or when the path is `-`.
+    pub fn save(&self, path: Option<&str>) -> Result<(), String> {
+        self.save_as(path, path.map_or(InstanceFormat::Json, InstanceFormat::of))
+    }
+
+    /// Writes this instance like `save`, in the given format
+    pub fn save_as(&self, path: Option<&str>, format: InstanceFormat) -> Result<(), String> {
+        let path = path.unwrap_or(STDIO);
+        let text = match format {
+            InstanceFormat::Binary if !path.ends_with(".gz") => return binary::write(self, path),
+            InstanceFormat::Binary => return Err("binary instances are memory mapped, they cannot be compressed".to_string()),
+            InstanceFormat::Json   => serde_json::to_string_pretty(self).unwrap() + "\n",
+            InstanceFormat::Csv    => csv::write(self),
+            InstanceFormat::Tsplib => {
+                let name = Some(path).filter(|p| !is_stdio(p)).and_then(|p| Path::new(p.strip_suffix(".gz").unwrap_or(p)).file_stem())
+                    .map_or("instance".to_string(), |stem| stem.to_string_lossy().to_string());
+                tsplib::write(&name, self, 1.0, Rounding::Nearest, self.has_coordinates())?
+            }
+        };
+        write_text(path, &text)
+    }
+
+    /// Checks that the distance matrix of this instance has a row and a column per
+    /// destination and only holds numbers, and that the other matrices (if any)
+    /// have the same size
+    pub fn validate(&self) -> Result<(), String> {
+        let n = self.destinations.len();
+        if self.distances.len() != n {
+            return Err(format!("the distance matrix has {} rows but the instance has {n} destinations", self.distances.len()));
+        }
+        if let Some((i, row)) = self.distances.iter().enumerate().find(|(_, row)| row.len() != n) {
+            return Err(format!("row {i} of the distance matrix has {} entries but the instance has {n} destinations", row.len()));
+        }
+        if let Some(extras) = self.extras.as_ref().filter(|extras| extras.len() != n) {
+            return Err(format!("the instance has extra fields for {} destinations but it has {n} destinations", extras.len()));
+        }
+        if n > 0 && self.depot >= n {
+            return Err(format!("the depot {} is not one of the {n} destinations", self.depot));
+        }
+        if let Some((from, to)) = self.forbidden_arcs.iter().find(|(from, to)| *from >= n || *to >= n) {
+            return Err(format!("the forbidden arc ({from}, {to}) refers to a destination which does not exist"));
+        }
+        self.validate_forced_arcs()?;
+        for (i, row) in self.distances.iter().enumerate() {
+            if let Some(j) = row.iter().position(|d| d.is_nan()) {
+                return Err(format!("the distance from {i} to {j} is not a number"));
+            }
+        }
+        if let Some(durations) = self.durations.as_ref() {
+            self.validate_matrix("durations", durations)?;
+        }
+        for (label, durations) in self.durations_by_time.iter().flatten() {
+            self.validate_matrix(&format!("durations at {label}"), durations)?;
+        }
+        Ok(())
+    }
+
+    /// Checks that the forced arcs form vertex-disjoint paths (or a single cycle
+    /// through all the destinations), since no tour can use them otherwise
+    fn validate_forced_arcs(&self) -> Result<(), String> {
+        let n = self.destinations.len();
+        let mut next = vec![None; n];
+        let mut has_previous = vec![false; n];
+        for (from, to) in self.forced_arcs.iter().copied() {
+            if from >= n || to >= n || from == to {
+                return Err(format!("the forced arc ({from}, {to}) does not join two destinations of the instance"));
+            }
+            if next[from].is_some() || has_previous[to] {
+                return Err(format!("the forced arcs are infeasible: they do not form vertex-disjoint paths ({from}, {to})"));
+            }
+            next[from] = Some(to);
+            has_previous[to] = true;
+        }
+        for start in 0..n {
+            let mut length = 0;
+            let mut at = start;
+            while let Some(to) = next[at] {
+                length += 1;
+                at = to;
+                if at == start && length < n {
+                    return Err(format!("the forced arcs are infeasible: they form a cycle through {start} which misses some destinations"));
+                }
+                if at == start {
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that the given matrix has the size of the distance matrix and only
+    /// holds numbers
+    fn validate_matrix(&self, name: &str, matrix: &Matrix) -> Result<(), String> {
+        if matrix.len() != self.distances.len() || matrix.iter().any(|row| row.len() != matrix.len()) {
+            return Err(format!("the {name} matrix does not have the size of the distance matrix"));
+        }
+        for (i, row) in matrix.iter().enumerate() {
+            if let Some(j) = row.iter().position(|d| d.is_nan()) {
+                return Err(format!("the {name} from {i} to {j} is not a number"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the durations snapshot taken at the given departure time
+    pub fn durations_at(&self, departure: &str) -> Result<&Matrix, String> {
+        let snapshots = self.durations_by_time.as_deref().unwrap_or_default();
+        snapshots.iter()
+            .find(|(label, _)| label == departure)
+            .map(|(_, durations)| durations)
+            .ok_or_else(|| {
+                let labels = snapshots.iter().map(|(label, _)| label.as_str()).collect::<Vec<_>>();
+                format!("the instance has no durations for the departure '{departure}' (available: {})", labels.join(", "))
+            })
+    }
+
+    /// Returns true iff the instance knows the coordinates of its destinations:
+    /// they are all 0 when they were removed by the anonymization or when the
+    /// instance was built from a bare matrix
+    pub fn has_coordinates(&self) -> bool {
+        self.metadata.anonymization != Some(Anonymization::Removed)
+            && self.destinations.iter().any(|l| l.longitude != 0.0 || l.latitude != 0.0)
+    }
+
+    /// Returns the length of the closed tour visiting the destinations in the given
+    /// order (infinite when the tour uses an unreachable leg). The tour of a
+    /// single destination has no leg and thus a length of 0.
+    pub fn tour_length(&self, tour: &[usize]) -> f64 {
+        tour.iter().zip(tour.iter().cycle().skip(1))
+            .filter(|(from, to)| from != to)
+            .map(|(from, to)| self.distances[*from][*to] as f64)
+            .sum()
+    }
+
+    /// Returns true iff a tour may go from `from` directly to `to`: the arc is not
+    /// forbidden and does not contradict a forced arc (leaving `from` towards
+    /// another destination or entering `to` from another destination)
+    pub fn is_allowed(&self, from: usize, to: usize) -> bool {
+        !self.forbidden_arcs.contains(&(from, to))
+            && self.forced_arcs.iter().all(|(a, b)| (*a != from || *b == to) && (*b != to || *a == from))
+    }
+
+    /// Returns the first leg (from, to) of the closed tour visiting the destinations
+    /// in the given order which is not allowed
+    pub fn disallowed_leg(&self, tour: &[usize]) -> Option<(usize, usize)> {
+        tour.iter().zip(tour.iter().cycle().skip(1))
+            .map(|(from, to)| (*from, *to))
+            .filter(|(from, to)| from != to)
+            .find(|(from, to)| !self.is_allowed(*from, *to))
+    }
+
+    /// Returns the travel time (in seconds) of the closed tour visiting the
+    /// destinations in the given order, when the instance holds durations
+    pub fn tour_duration(&self, tour: &[usize]) -> Option<f64> {
+        self.durations.as_ref().map(|durations| tour.iter().zip(tour.iter().cycle().skip(1))
+            .filter(|(from, to)| from != to)
+            .map(|(from, to)| durations[*from][*to] as f64)
+            .sum())
+    }
+
+    /// Returns the total distance (in the reported unit of `unit`, the unit of the
+    /// distance matrix) and the total travel time (in hours) of the closed tour
+    /// visiting the destinations in the given order. Either one is None when the
+    /// instance has no matrix to measure it: a matrix expressed in seconds holds
+    /// travel times and no distances.
+    pub fn tour_totals(&self, tour: &[usize], unit: Unit) -> (Option<f64>, Option<f64>) {
+        let length = self.tour_length(tour);
+        match unit {
+            Unit::Seconds => (None, Some(unit.report(length).0)),
+            _             => (Some(unit.report(length).0), self.tour_duration(tour).map(|d| Unit::Seconds.report(d).0)),
+        }
+    }
+
+    /// Returns the instance restricted to the destinations at the given indices
+    /// (in the given order): their labels, extra fields, snap distances, distances and forbidden
+    /// or forced arcs are kept.
+    /// The depot keeps its role when it is selected, the first of the selected
+    /// destinations becomes the depot otherwise.
+    pub fn subset(&self, indices: &[usize]) -> Instance {
+        let pick = |values: &[f32]| indices.iter().map(|i| values[*i]).collect::<Vec<_>>();
+        Instance {
+            destinations: indices.iter().map(|i| self.destinations[*i]).collect(),
+            distances: indices.iter().map(|i| pick(&self.distances[*i])).collect(),
+            durations: self.durations.as_ref().map(|durations| indices.iter().map(|i| pick(&durations[*i])).collect()),
+            durations_by_time: self.durations_by_time.as_ref().map(|snapshots| snapshots.iter()
+                .map(|(label, durations)| (label.clone(), indices.iter().map(|i| pick(&durations[*i])).collect()))
+                .collect()),
+            labels: self.labels.as_ref().map(|labels| indices.iter().map(|i| labels[*i].clone()).collect()),
+            extras: self.extras.as_ref().map(|extras| indices.iter().map(|i| extras[*i].clone()).collect()),
+            depot: indices.iter().position(|i| *i == self.depot).unwrap_or(0),
+            forbidden_arcs: pick_arcs(&self.forbidden_arcs, indices),
+            forced_arcs: pick_arcs(&self.forced_arcs, indices),
+            metadata: Metadata {
+                snap_distances: self.metadata.snap_distances.as_deref().map(pick),
+                ..self.metadata.clone()
+            },
+        }
+    }
+
+    /// Returns a digest of the destinations and distances of this instance which
+    /// can be used to check that a solution was computed for this very instance
+    pub fn digest(&self) -> String {
+        let mut bytes = vec![];
+        bytes.extend((self.distances.len() as u32).to_le_bytes());
+        for location in self.destinations.iter() {
+            bytes.extend(location.longitude.to_bits().to_le_bytes());
+            bytes.extend(location.latitude.to_bits().to_le_bytes());
+        }
+        for d in self.distances.iter().flatten() {
+            bytes.extend(d.to_bits().to_le_bytes());
+        }
+        checksum(&bytes)
+    }
+
     /// Generates a string corresponding a description of the instance in the form 
     /// which is usually used to encode TSP instances
     #[allow(dead_code)]
@@ -39,9 +677,21 @@ impl Instance {
 
     /// Returns a geojson multipoint geometry where each point is one of the destinations
     /// to be visited
+    #[allow(dead_code)]
     pub fn geojson(&self) -> GeoJsonGeometry {
         GeoJsonGeometry::MultiPoint { 
             coordinates: self.destinations.iter().copied().map(GeoJsonPoint::from).collect::<Vec<_>>()
         }
     }
 }
+
+/// Returns a checksum of the given bytes (16 hex chars)
+pub fn checksum(bytes: &[u8]) -> String {
+    // 64 bits FNV-1a: simple, and stable across platforms and rust versions
+    let mut hash = 0xcbf29ce484222325_u64;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{hash:016x}")
+}
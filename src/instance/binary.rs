@@ -0,0 +1,233 @@
+//! This module implements the binary instance format, meant for the instances
+//! whose json matrices are too large to be parsed comfortably. The file is
+//! memory mapped and the matrices of the instance read their rows in place, the
+//! map being kept alive as long as the instance. An instance piped on stdin is
+//! read in a buffer instead, its matrices are copied out of it.
+//!
+//! All numbers are little endian. The file is laid out as follows:
+//! - the magic bytes `TSPB` and the version of the format (u32);
+//! - the number of destinations `n` (u64), a flag telling whether the file holds
+//!   durations (u32) and the length of the json header (u64);
+//! - the json header: the instance without its destinations, distances and
+//!   durations, padded with spaces to a multiple of 4 bytes;
+//! - the coordinate table: the longitude and latitude of each destination (2n f32);
+//! - the distance matrix, row by row (n² f32, unreachable pairs are infinite);
+//! - the duration matrix when there is one (n² f32).
+
+use std::{fs::File, io::{BufWriter, Write}, sync::Arc};
+
+use memmap2::Mmap;
+use osrm_client::Location;
+
+use crate::stdio::{is_stdio, open_output, output_name};
+
+use super::{Instance, Matrix};
+
+/// The bytes every binary instance starts with
+const MAGIC: &[u8; 4] = b"TSPB";
+/// The version of the format written by this module
+const VERSION: u32 = 1;
+/// The extension of the binary instance files
+pub const EXTENSION: &str = "tspb";
+
+/// Returns true iff the file at the given path is a binary instance
+pub fn is_binary(path: &str) -> bool {
+    let mut magic = [0; 4];
+    File::open(path).and_then(|mut f| std::io::Read::read_exact(&mut f, &mut magic)).is_ok() && &magic == MAGIC
+}
+
+/// Returns true iff the given bytes start like a binary instance
+pub fn has_magic(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// Writes the instance in the binary format in the file at the given path (or
+/// on stdout). An instance whose matrices are mapped is written in a temporary
+/// file which then replaces the target: the target may be the mapped file.
+pub fn write(instance: &Instance, path: &str) -> Result<(), String> {
+    let mapped = instance.distances.is_mapped() || instance.durations.as_ref().is_some_and(Matrix::is_mapped);
+    if !mapped || is_stdio(path) {
+        return write_to(instance, open_output(path)?, path);
+    }
+    let temporary = format!("{path}.tmp");
+    let file = File::create(&temporary).map_err(|e| format!("cannot write {temporary}: {e}"))?;
+    write_to(instance, Box::new(file), path)?;
+    std::fs::rename(&temporary, path).map_err(|e| format!("cannot write {path}: {e}"))
+}
+
+/// Writes the instance in the binary format in the given output, which goes to
+/// the file at the given path
+fn write_to(instance: &Instance, output: Box<dyn Write>, path: &str) -> Result<(), String> {
+    let error = |e: std::io::Error| format!("cannot write {}: {e}", output_name(path));
+    let header = Instance { destinations: vec![], distances: Matrix::default(), durations: None, ..instance.clone() };
+    let mut header = serde_json::to_vec(&header).map_err(|e| format!("cannot write {}: {e}", output_name(path)))?;
+    header.resize(header.len().next_multiple_of(4), b' ');
+
+    let mut file = BufWriter::new(output);
+    file.write_all(MAGIC).map_err(error)?;
+    file.write_all(&VERSION.to_le_bytes()).map_err(error)?;
+    file.write_all(&(instance.destinations.len() as u64).to_le_bytes()).map_err(error)?;
+    file.write_all(&(instance.durations.is_some() as u32).to_le_bytes()).map_err(error)?;
+    file.write_all(&(header.len() as u64).to_le_bytes()).map_err(error)?;
+    file.write_all(&header).map_err(error)?;
+    for location in instance.destinations.iter() {
+        file.write_all(&location.longitude.to_le_bytes()).map_err(error)?;
+        file.write_all(&location.latitude.to_le_bytes()).map_err(error)?;
+    }
+    for matrix in std::iter::once(&instance.distances).chain(instance.durations.as_ref()) {
+        for value in matrix.iter().flatten() {
+            file.write_all(&value.to_le_bytes()).map_err(error)?;
+        }
+    }
+    file.flush().map_err(error)
+}
+
+/// Reads the binary instance in the file at the given path. The instance is not
+/// validated.
+pub fn read(path: &str) -> Result<Instance, String> {
+    let file = File::open(path).map_err(|e| format!("cannot open {path}: {e}"))?;
+    // the file must not be modified while it is mapped: the instances are
+    // rewritten in place through a temporary file (see `write`)
+    let map = Arc::new(unsafe { Mmap::map(&file) }.map_err(|e| format!("cannot map {path}: {e}"))?);
+    decode(&map, Some(&map), path)
+}
+
+/// Parses the binary instance held by the given bytes, read from the input
+/// called `name`. The instance is not validated.
+pub fn parse(bytes: &[u8], name: &str) -> Result<Instance, String> {
+    decode(bytes, None, name)
+}
+
+/// Parses the binary instance held by the given bytes. When they are those of
+/// the given map, the matrices of the instance read them in place.
+fn decode(bytes: &[u8], map: Option<&Arc<Mmap>>, name: &str) -> Result<Instance, String> {
+    let truncated = || format!("{name} is truncated");
+
+    let mut reader = Reader { bytes, map, position: 0 };
+    if reader.take(4).ok_or_else(truncated)? != MAGIC {
+        return Err(format!("{name} is not a binary instance"));
+    }
+    let version = reader.u32().ok_or_else(truncated)?;
+    if version != VERSION {
+        return Err(format!("{name} uses version {version} of the binary format, only version {VERSION} is supported"));
+    }
+    let n = reader.u64().ok_or_else(truncated)? as usize;
+    let has_durations = reader.u32().ok_or_else(truncated)? != 0;
+    let length = reader.u64().ok_or_else(truncated)? as usize;
+    let header = reader.take(length).ok_or_else(truncated)?;
+    let mut instance: Instance = serde_json::from_slice(header)
+        .map_err(|e| format!("cannot parse the header of {name}: {e}"))?;
+
+    let coordinates = reader.f32s(n.checked_mul(2).ok_or_else(truncated)?).ok_or_else(truncated)?;
+    instance.destinations = coordinates.chunks(2).map(|c| Location { longitude: c[0], latitude: c[1] }).collect();
+    instance.distances = reader.matrix(n).ok_or_else(truncated)?;
+    if has_durations {
+        instance.durations = Some(reader.matrix(n).ok_or_else(truncated)?);
+    }
+    if reader.position != bytes.len() {
+        return Err(format!("{name} has {} unexpected trailing bytes", bytes.len() - reader.position));
+    }
+    Ok(instance)
+}
+
+/// Reads the successive fields of a binary instance
+struct Reader<'a> {
+    bytes: &'a [u8],
+    map: Option<&'a Arc<Mmap>>,
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    /// The next `length` bytes, if there are that many left
+    fn take(&mut self, length: usize) -> Option<&'a [u8]> {
+        let end = self.position.checked_add(length).filter(|end| *end <= self.bytes.len())?;
+        let bytes = &self.bytes[self.position..end];
+        self.position = end;
+        Some(bytes)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take(4).map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        self.take(8).map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+    }
+
+    fn f32s(&mut self, count: usize) -> Option<Vec<f32>> {
+        let bytes = self.take(count.checked_mul(4)?)?;
+        Some(bytes.chunks_exact(4).map(|b| f32::from_le_bytes(b.try_into().unwrap())).collect())
+    }
+
+    /// The next `n` × `n` matrix, read in place when the bytes are mapped
+    fn matrix(&mut self, n: usize) -> Option<Matrix> {
+        let Some(map) = self.map else {
+            let values = self.f32s(n.checked_mul(n)?)?;
+            return Some(values.chunks(n.max(1)).map(|row| row.to_vec()).collect());
+        };
+        let matrix = Matrix::mapped(map, self.position, n)?;
+        self.take(n * n * 4)?;
+        Some(matrix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use osrm_client::Location;
+    use tempfile::TempDir;
+
+    use crate::instance::{Instance, Unit};
+
+    use super::parse;
+
+    #[test]
+    fn an_instance_survives_a_round_trip_through_the_binary_format() {
+        let destinations = vec![
+            Location { longitude: 4.35, latitude: 50.85 },
+            Location { longitude: 4.70, latitude: 50.88 },
+            Location { longitude: 5.57, latitude: 50.63 },
+        ];
+        let distances = vec![
+            vec![0.0, 25_930.5, 97_002.25],
+            vec![26_010.0, 0.0, f32::INFINITY],
+            vec![96_800.75, 73_400.0, 0.0],
+        ];
+        let mut instance = Instance::from_distances(destinations, distances.into(), Unit::Meters).unwrap();
+        instance.durations = Some(vec![vec![0.0, 1_200.0, 3_900.5], vec![1_250.0, 0.0, f32::INFINITY], vec![3_850.0, 3_100.0, 0.0]].into());
+        instance.labels = Some(vec!["Brussels".to_string(), "Leuven".to_string(), "Liège".to_string()]);
+        instance.depot = 1;
+        instance.forbidden_arcs = vec![(0, 2)];
+
+        let dir = TempDir::new().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        instance.save(Some(&path("original.json"))).unwrap();
+        let from_json = Instance::load(&path("original.json")).unwrap();
+        from_json.save(Some(&path("converted.tspb"))).unwrap();
+        let from_binary = Instance::load(&path("converted.tspb")).unwrap();
+        assert!(from_binary.distances.is_mapped() || cfg!(target_endian = "big"));
+        assert_eq!(from_binary.distances, from_json.distances);
+        assert_eq!(from_binary.durations, from_json.durations);
+        from_binary.save(Some(&path("converted.json"))).unwrap();
+
+        let read = |name: &str| std::fs::read_to_string(path(name)).unwrap();
+        assert_eq!(read("converted.json"), read("original.json"));
+
+        // the mapped instance can be written over the file it is mapped from
+        from_binary.save(Some(&path("converted.tspb"))).unwrap();
+        let rewritten = Instance::load(&path("converted.tspb")).unwrap();
+        assert_eq!(rewritten.distances, from_json.distances);
+        assert_eq!(rewritten.durations, from_json.durations);
+    }
+
+    #[test]
+    fn a_huge_number_of_destinations_is_reported_as_truncated() {
+        let mut bytes = b"TSPB".to_vec();
+        bytes.extend(1_u32.to_le_bytes());
+        bytes.extend(u64::MAX.to_le_bytes());
+        bytes.extend(0_u32.to_le_bytes());
+        let header = br#"{"destinations": [], "distances": []}"#;
+        bytes.extend((header.len() as u64).to_le_bytes());
+        bytes.extend(header);
+        assert_eq!(parse(&bytes, "huge.tspb").unwrap_err(), "huge.tspb is truncated");
+    }
+}
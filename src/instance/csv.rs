@@ -0,0 +1,39 @@
+//! This module reads and writes bare distance matrices in csv files: one line
+//! per row of the matrix, the entries being separated by commas. An empty entry
+//! is a pair of destinations which cannot be reached from one another.
+
+use osrm_client::Location;
+
+use super::{Instance, Matrix, Unit};
+
+/// Parses the csv distance matrix in the given text as an instance whose
+/// distances are expressed in the given unit. The coordinates of the
+/// destinations are unknown.
+pub fn read(text: &str, unit: Unit) -> Result<Instance, String> {
+    let distances = text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .enumerate()
+        .map(|(i, line)| line.split(',')
+            .map(str::trim)
+            .map(|entry| match entry {
+                "" => Ok(f32::INFINITY),
+                entry => entry.parse::<f32>().map_err(|_| format!("invalid distance '{entry}' on row {i} of the matrix")),
+            })
+            .collect::<Result<Vec<_>, _>>())
+        .collect::<Result<Matrix, _>>()?;
+    let destinations = vec![Location { longitude: 0.0, latitude: 0.0 }; distances.len()];
+    Instance::from_distances(destinations, distances, unit)
+}
+
+/// Renders the distance matrix of the instance in the csv format
+pub fn write(instance: &Instance) -> String {
+    let mut text = String::new();
+    for row in instance.distances.iter() {
+        let row = row.iter()
+            .map(|d| if d.is_finite() { d.to_string() } else { String::new() })
+            .collect::<Vec<_>>();
+        text.push_str(&row.join(","));
+        text.push('\n');
+    }
+    text
+}
@@ -0,0 +1,173 @@
+//! This module defines the square matrices of an instance. A matrix either owns
+//! its rows or reads them in place from a memory mapped binary instance, which
+//! spares a copy of the matrices that are too large to be parsed comfortably.
+//! Both are read through the same accessors: indexing a matrix yields a row and
+//! iterating over it yields all the rows in order.
+
+use std::{ops::{Index, IndexMut}, sync::Arc};
+
+use memmap2::Mmap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// A square matrix holding a value for each pair of destinations
+#[derive(Clone, Default)]
+pub struct Matrix {
+    storage: Storage,
+}
+
+/// Where the values of a matrix are held
+#[derive(Clone)]
+enum Storage {
+    /// The rows are owned by the matrix
+    Owned(Vec<Vec<f32>>),
+    /// The `n` rows are stored as little endian f32, row by row, from the given
+    /// offset of the mapped file. The map is kept alive as long as the matrix.
+    Mapped { map: Arc<Mmap>, offset: usize, n: usize },
+}
+
+impl Default for Storage {
+    fn default() -> Self {
+        Storage::Owned(vec![])
+    }
+}
+
+impl Matrix {
+    /// Reads the `n` × `n` matrix stored from the given offset of the mapped
+    /// file, or returns None when the file is too short to hold it. The values
+    /// are only copied when they cannot be read in place (on big endian targets
+    /// or when they are not aligned).
+    pub fn mapped(map: &Arc<Mmap>, offset: usize, n: usize) -> Option<Matrix> {
+        let length = n.checked_mul(n)?.checked_mul(4)?;
+        let bytes = map.get(offset..offset.checked_add(length)?)?;
+        let aligned = bytes.as_ptr().align_offset(std::mem::align_of::<f32>()) == 0;
+        if cfg!(target_endian = "little") && aligned {
+            return Some(Matrix { storage: Storage::Mapped { map: map.clone(), offset, n } });
+        }
+        let values = bytes.chunks_exact(4).map(|b| f32::from_le_bytes(b.try_into().unwrap())).collect::<Vec<_>>();
+        Some(values.chunks(n.max(1)).map(|row| row.to_vec()).collect())
+    }
+
+    /// The number of rows of the matrix
+    pub fn len(&self) -> usize {
+        match &self.storage {
+            Storage::Owned(rows) => rows.len(),
+            Storage::Mapped { n, .. } => *n,
+        }
+    }
+
+    /// Returns true iff the matrix has no row
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns true iff the values are read in place from a mapped file
+    pub fn is_mapped(&self) -> bool {
+        matches!(self.storage, Storage::Mapped { .. })
+    }
+
+    /// Iterates over the rows of the matrix
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &[f32]> + ExactSizeIterator + Clone {
+        (0..self.len()).map(|i| &self[i])
+    }
+
+    /// Iterates mutably over the rows of the matrix. A mapped matrix is copied
+    /// first, the mapped file is never written.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Vec<f32>> {
+        self.rows_mut().iter_mut()
+    }
+
+    /// Consumes the matrix and returns its rows
+    pub fn into_rows(self) -> Vec<Vec<f32>> {
+        match self.storage {
+            Storage::Owned(rows) => rows,
+            Storage::Mapped { .. } => self.iter().map(<[f32]>::to_vec).collect(),
+        }
+    }
+
+    /// The rows owned by the matrix, copied out of the mapped file if needed
+    fn rows_mut(&mut self) -> &mut Vec<Vec<f32>> {
+        if self.is_mapped() {
+            self.storage = Storage::Owned(self.iter().map(<[f32]>::to_vec).collect());
+        }
+        match &mut self.storage {
+            Storage::Owned(rows) => rows,
+            Storage::Mapped { .. } => unreachable!("the matrix has just been copied"),
+        }
+    }
+}
+
+impl Index<usize> for Matrix {
+    type Output = [f32];
+
+    fn index(&self, i: usize) -> &[f32] {
+        match &self.storage {
+            Storage::Owned(rows) => &rows[i],
+            Storage::Mapped { map, offset, n } => {
+                assert!(i < *n, "row {i} is out of a matrix of {n} rows");
+                let start = offset + i * n * 4;
+                let bytes = &map[start..start + n * 4];
+                // SAFETY: `mapped` checked that the values are aligned f32 in the
+                // native (little endian) byte order, every bit pattern is a valid
+                // f32, and the slice borrows the map which outlives it.
+                unsafe { std::slice::from_raw_parts(bytes.as_ptr().cast::<f32>(), *n) }
+            }
+        }
+    }
+}
+
+impl IndexMut<usize> for Matrix {
+    fn index_mut(&mut self, i: usize) -> &mut [f32] {
+        &mut self.rows_mut()[i]
+    }
+}
+
+impl<'a> IntoIterator for &'a Matrix {
+    type Item = &'a [f32];
+    type IntoIter = Box<dyn Iterator<Item = &'a [f32]> + 'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Box::new(self.iter())
+    }
+}
+
+impl From<Vec<Vec<f32>>> for Matrix {
+    fn from(rows: Vec<Vec<f32>>) -> Self {
+        Matrix { storage: Storage::Owned(rows) }
+    }
+}
+
+impl FromIterator<Vec<f32>> for Matrix {
+    fn from_iter<I: IntoIterator<Item = Vec<f32>>>(rows: I) -> Self {
+        Matrix::from(rows.into_iter().collect::<Vec<_>>())
+    }
+}
+
+impl PartialEq for Matrix {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().zip(other.iter()).all(|(a, b)| a == b)
+    }
+}
+
+impl std::fmt::Debug for Matrix {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+/// The unreachable pairs are written as `null`, like serde_json does with the
+/// infinite values
+impl Serialize for Matrix {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+/// The unreachable pairs are encoded as `null` and read as infinite values
+impl<'de> Deserialize<'de> for Matrix {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let rows = Vec::<Vec<Option<f32>>>::deserialize(deserializer)?;
+        Ok(rows.into_iter()
+            .map(|row| row.into_iter().map(|d| d.unwrap_or(f32::INFINITY)).collect())
+            .collect())
+    }
+}
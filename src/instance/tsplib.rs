@@ -0,0 +1,278 @@
+//! This module reads and writes instances in the TSPLIB format understood by
+//! the classical TSP solvers such as LKH and Concorde.
+//!
+//! The files written by this module hold an explicit full matrix of integer
+//! weights, and their COMMENT line tells how the weights were derived from the
+//! distances: reading such a file gives the original distances back (up to the
+//! rounding). The other files are read with their weights as distances, in
+//! metres, for want of a unit in the format.
+
+use osrm_client::Location;
+
+use super::{Instance, Matrix, Rounding, Unit};
+
+/// Renders an instance as a TSPLIB `.tsp` file with an explicit full matrix of
+/// weights, each one being `distance * multiplier` rounded with the given
+/// strategy. The instance is
+/// declared as a TSP when its rounded matrix is symmetric and as an ATSP
+/// otherwise.
+pub fn write(name: &str, instance: &Instance, multiplier: f64, rounding: Rounding, coordinates: bool) -> Result<String, String> {
+    if !multiplier.is_finite() || multiplier <= 0.0 {
+        return Err(format!("the multiplier must be a positive number, got {multiplier}"));
+    }
+    let n = instance.destinations.len();
+    let rounded = instance.distances.iter()
+        .map(|row| row.iter().map(|d| d.is_finite().then(|| rounding.round(*d as f64 * multiplier))).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+
+    // any tour avoiding the unreachable pairs is shorter than this weight
+    let longest = rounded.iter().flatten().flatten().fold(0.0_f64, |a, b| a.max(*b));
+    let unreachable = longest * n as f64 + 1.0;
+    if unreachable > i32::MAX as f64 {
+        return Err(format!("the weights exceed the range of TSPLIB solvers with the multiplier {multiplier}, use a smaller one"));
+    }
+
+    let weights = rounded.iter().enumerate()
+        .map(|(i, row)| row.iter().enumerate()
+            .map(|(j, w)| match w {
+                _ if i == j => 0,
+                Some(w) if instance.is_allowed(i, j) => *w as i64,
+                _ => unreachable as i64,
+            })
+            .collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    let symmetric = (0..n).all(|i| (0..i).all(|j| weights[i][j] == weights[j][i]));
+
+    let unit = serde_json::to_value(instance.metadata.unit).map_err(|e| e.to_string())?;
+    let rounding = match rounding {
+        Rounding::Nearest => "round",
+        Rounding::Floor   => "floor",
+        Rounding::Ceil    => "ceil",
+    };
+    let mut comment = format!("weight = {rounding}(distance in {} * {multiplier})", unit.as_str().unwrap_or_default());
+    if instance.depot != 0 {
+        comment.push_str(&format!(", the depot is node {}", instance.depot + 1));
+    }
+    if rounded.iter().enumerate().any(|(i, row)| row.iter().enumerate().any(|(j, w)| i != j && w.is_none())) {
+        comment.push_str(&format!(", unreachable pairs weigh {unreachable}"));
+    }
+    if !instance.forbidden_arcs.is_empty() || !instance.forced_arcs.is_empty() {
+        comment.push_str(&format!(", the arcs which are forbidden or contradict a forced arc weigh {unreachable}"));
+    }
+
+    let mut text = format!("NAME : {name}\nCOMMENT : {comment}\n");
+    text.push_str(&format!("TYPE : {}\nDIMENSION : {n}\n", if symmetric { "TSP" } else { "ATSP" }));
+    text.push_str("EDGE_WEIGHT_TYPE : EXPLICIT\nEDGE_WEIGHT_FORMAT : FULL_MATRIX\n");
+    if coordinates {
+        text.push_str("DISPLAY_DATA_TYPE : TWOD_DISPLAY\n");
+    }
+    text.push_str("EDGE_WEIGHT_SECTION\n");
+    for row in weights.iter() {
+        let row = row.iter().map(|w| w.to_string()).collect::<Vec<_>>();
+        text.push_str(&row.join(" "));
+        text.push('\n');
+    }
+    if coordinates {
+        text.push_str("DISPLAY_DATA_SECTION\n");
+        for (i, location) in instance.destinations.iter().enumerate() {
+            text.push_str(&format!("{} {} {}\n", i + 1, location.longitude, location.latitude));
+        }
+    }
+    text.push_str("EOF\n");
+    Ok(text)
+}
+
+/// The keywords which open the sections of a TSPLIB file
+const SECTIONS: [&str; 3] = ["NODE_COORD_SECTION", "DISPLAY_DATA_SECTION", "EDGE_WEIGHT_SECTION"];
+
+/// Parses the TSPLIB instance in the given text. The weights are either given
+/// explicitly (full matrix, or one of its triangles) or derived from the
+/// coordinates of the nodes (EUC_2D, CEIL_2D, ATT and GEO distances).
+pub fn read(text: &str) -> Result<Instance, String> {
+    let mut fields = vec![];
+    let mut sections: Vec<(&str, Vec<f64>)> = vec![];
+    for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        if line == "EOF" {
+            break;
+        }
+        if let Some(section) = SECTIONS.iter().find(|s| line.starts_with(**s)) {
+            sections.push((section, vec![]));
+        } else if line.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            let (key, value) = line.split_once(':').ok_or_else(|| format!("invalid TSPLIB line '{line}'"))?;
+            fields.push((key.trim(), value.trim()));
+        } else if let Some((_, numbers)) = sections.last_mut() {
+            for token in line.split_whitespace() {
+                numbers.push(token.parse::<f64>().map_err(|_| format!("invalid number '{token}' in a TSPLIB section"))?);
+            }
+        } else {
+            return Err(format!("unexpected TSPLIB line '{line}'"));
+        }
+    }
+    let field = |key: &str| fields.iter().find(|(k, _)| *k == key).map(|(_, v)| *v);
+    let section = |key: &str| sections.iter().find(|(k, _)| *k == key).map(|(_, v)| v.as_slice());
+
+    let n = field("DIMENSION").and_then(|d| d.parse::<usize>().ok())
+        .ok_or("the TSPLIB file has no valid DIMENSION")?;
+    let nodes = |numbers: &[f64]| -> Result<Vec<(f64, f64)>, String> {
+        if numbers.len() != 3 * n {
+            return Err(format!("the TSPLIB file has {} coordinates for {n} nodes", numbers.len() / 3));
+        }
+        Ok(numbers.chunks(3).map(|c| (c[1], c[2])).collect())
+    };
+    let coordinates = section("NODE_COORD_SECTION").map(nodes).transpose()?;
+    let display = section("DISPLAY_DATA_SECTION").map(nodes).transpose()?;
+
+    let kind = field("EDGE_WEIGHT_TYPE").unwrap_or("EXPLICIT");
+    let mut distances = match (kind, coordinates.as_ref()) {
+        ("EXPLICIT", _) => {
+            let weights = section("EDGE_WEIGHT_SECTION").ok_or("the TSPLIB file has no EDGE_WEIGHT_SECTION")?;
+            explicit(weights, n, field("EDGE_WEIGHT_FORMAT").unwrap_or("FULL_MATRIX"))?
+        }
+        (kind, Some(nodes)) => {
+            let distance = metric(kind)?;
+            (0..n).map(|i| (0..n).map(|j| if i == j { 0.0 } else { distance(nodes[i], nodes[j]) as f32 }).collect()).collect()
+        }
+        (kind, None) => return Err(format!("the TSPLIB file has {kind} weights but no NODE_COORD_SECTION")),
+    };
+
+    // the comment of the files written by `write` tells how to undo the weights
+    let comment = field("COMMENT").unwrap_or_default();
+    let unit = after(comment, "distance in ").and_then(|u| serde_json::from_value::<Unit>(serde_json::json!(u)).ok());
+    let multiplier = after(comment, "* ").and_then(|m| m.trim_end_matches([')', ',']).parse::<f32>().ok()).filter(|m| *m > 0.0);
+    let unreachable = after(comment, "weigh ").and_then(|w| w.trim_end_matches(',').parse::<f32>().ok());
+    let depot = after(comment, "the depot is node ").and_then(|d| d.trim_end_matches(',').parse::<usize>().ok());
+    for d in distances.iter_mut().flatten() {
+        if unreachable.is_some_and(|w| *d >= w) {
+            *d = f32::INFINITY;
+        } else if let Some(m) = multiplier {
+            *d /= m;
+        }
+    }
+
+    let destinations = match (kind, coordinates.or(display)) {
+        ("GEO", Some(nodes)) => nodes.iter().map(|(x, y)| Location { longitude: geo_degrees(*y) as f32, latitude: geo_degrees(*x) as f32 }).collect(),
+        (_, Some(nodes)) => nodes.iter().map(|(x, y)| Location { longitude: *x as f32, latitude: *y as f32 }).collect(),
+        (_, None) => vec![Location { longitude: 0.0, latitude: 0.0 }; n],
+    };
+    let unit = match (kind, unit) {
+        (_, Some(unit)) => unit,
+        ("GEO", None) => Unit::Kilometers,
+        _ => Unit::Meters,
+    };
+    let mut instance = Instance::from_distances(destinations, distances, unit)?;
+    if let Some(depot) = depot.filter(|d| (1..=n).contains(d)) {
+        instance.depot = depot - 1;
+    }
+    Ok(instance)
+}
+
+/// Returns the word which follows the given prefix in a text
+fn after<'a>(text: &'a str, prefix: &str) -> Option<&'a str> {
+    let start = text.find(prefix)? + prefix.len();
+    text[start..].split_whitespace().next()
+}
+
+/// Builds the matrix of explicit weights given in the given TSPLIB format
+fn explicit(weights: &[f64], n: usize, format: &str) -> Result<Matrix, String> {
+    // the (row, column) of the entries given by each format, in order
+    let entries: Vec<(usize, usize)> = match format {
+        "FULL_MATRIX"    => (0..n).flat_map(|i| (0..n).map(move |j| (i, j))).collect(),
+        "UPPER_ROW"      => (0..n).flat_map(|i| (i + 1..n).map(move |j| (i, j))).collect(),
+        "LOWER_ROW"      => (0..n).flat_map(|i| (0..i).map(move |j| (i, j))).collect(),
+        "UPPER_DIAG_ROW" => (0..n).flat_map(|i| (i..n).map(move |j| (i, j))).collect(),
+        "LOWER_DIAG_ROW" => (0..n).flat_map(|i| (0..=i).map(move |j| (i, j))).collect(),
+        format => return Err(format!("the TSPLIB edge weight format {format} is not supported")),
+    };
+    if weights.len() != entries.len() {
+        return Err(format!("the TSPLIB file has {} weights where the {format} format of {n} nodes needs {}", weights.len(), entries.len()));
+    }
+    let mut matrix = Matrix::from(vec![vec![0.0; n]; n]);
+    for ((i, j), w) in entries.into_iter().zip(weights) {
+        matrix[i][j] = *w as f32;
+        if format != "FULL_MATRIX" {
+            matrix[j][i] = *w as f32;
+        }
+    }
+    Ok(matrix)
+}
+
+/// The distance between two points of a TSPLIB file
+type Metric = fn((f64, f64), (f64, f64)) -> f64;
+
+/// The distance function of the given TSPLIB edge weight type
+fn metric(kind: &str) -> Result<Metric, String> {
+    match kind {
+        "EUC_2D"  => Ok(|a, b| (a.0 - b.0).hypot(a.1 - b.1).round()),
+        "CEIL_2D" => Ok(|a, b| (a.0 - b.0).hypot(a.1 - b.1).ceil()),
+        "ATT"     => Ok(|a, b| {
+            let r = (((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)) / 10.0).sqrt();
+            let t = r.round();
+            if t < r { t + 1.0 } else { t }
+        }),
+        "GEO"     => Ok(|a, b| {
+            const RRR: f64 = 6378.388;
+            let radians = |x: f64| std::f64::consts::PI * geo_degrees(x) / 180.0;
+            let (lat_a, lon_a, lat_b, lon_b) = (radians(a.0), radians(a.1), radians(b.0), radians(b.1));
+            let q1 = (lon_a - lon_b).cos();
+            let q2 = (lat_a - lat_b).cos();
+            let q3 = (lat_a + lat_b).cos();
+            (RRR * (0.5 * ((1.0 + q1) * q2 - (1.0 - q1) * q3)).acos() + 1.0).trunc()
+        }),
+        kind => Err(format!("the TSPLIB edge weight type {kind} is not supported")),
+    }
+}
+
+/// Converts a TSPLIB GEO coordinate (DDD.MM, degrees and minutes) to degrees
+fn geo_degrees(x: f64) -> f64 {
+    let degrees = x.trunc();
+    degrees + 5.0 * (x - degrees) / 3.0
+}
+
+#[cfg(test)]
+mod tests {
+    use osrm_client::Location;
+
+    use crate::instance::{Instance, Rounding, Unit};
+
+    use super::{read, write};
+
+    fn instance() -> Instance {
+        let destinations = vec![
+            Location { longitude: 4.35, latitude: 50.85 },
+            Location { longitude: 4.70, latitude: 50.88 },
+            Location { longitude: 5.57, latitude: 50.63 },
+        ];
+        let distances = vec![
+            vec![0.0, 25.93, 97.004],
+            vec![26.01, 0.0, f32::INFINITY],
+            vec![96.8075, 73.4, 0.0],
+        ];
+        let mut instance = Instance::from_distances(destinations, distances.into(), Unit::Kilometers).unwrap();
+        instance.depot = 2;
+        instance
+    }
+
+    #[test]
+    fn a_matrix_survives_an_export_within_the_rounding() {
+        let original = instance();
+        for (rounding, below, above) in [(Rounding::Nearest, 0.5, 0.5), (Rounding::Floor, 0.0, 1.0), (Rounding::Ceil, 1.0, 0.0)] {
+            let multiplier = 100.0;
+            let text = write("roundtrip", &original, multiplier, rounding, true).unwrap();
+            let imported = read(&text).unwrap();
+            assert_eq!(imported.metadata.unit, Unit::Kilometers);
+            assert_eq!(imported.depot, 2);
+            assert_eq!(imported.destinations.len(), 3);
+            for (a, b) in original.distances.iter().zip(imported.distances.iter()) {
+                for (d, r) in a.iter().zip(b.iter()) {
+                    if d.is_infinite() {
+                        assert!(r.is_infinite(), "{rounding:?}: {r} should be unreachable");
+                    } else {
+                        // the bounds are widened a little for the f32 arithmetic
+                        let (low, high) = (d - (above + 1e-3) / multiplier as f32, d + (below + 1e-3) / multiplier as f32);
+                        assert!(low <= *r && *r <= high, "{rounding:?}: {d} became {r}");
+                    }
+                }
+            }
+        }
+    }
+}
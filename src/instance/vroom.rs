@@ -0,0 +1,246 @@
+//! This module writes instances as VROOM problems, which lets the tours found by
+//! these tools be cross-checked with the VROOM dispatcher, and reads the VROOM
+//! problems (or the openrouteservice optimization requests, which share their
+//! format) back as the places of a tour.
+//!
+//! The destinations become the jobs of a single vehicle which starts and ends
+//! its route at the depot, and the matrices of the instance are given as custom
+//! matrices (VROOM then needs no router). VROOM expects integer durations in
+//! seconds and distances in metres.
+
+use std::collections::HashMap;
+
+use osrm_client::Location;
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use super::{Instance, Matrix, Unit};
+
+/// The routing profile of the vehicle and of the custom matrices
+const PROFILE: &str = "car";
+
+/// Renders an instance as a VROOM problem (json). The durations are those of
+/// the instance, or its distance matrix when it holds travel times. The
+/// coordinates of the destinations are only written when `coordinates` is set.
+pub fn write(instance: &Instance, coordinates: bool) -> Result<String, String> {
+    let (durations, distances) = match instance.metadata.unit {
+        Unit::Seconds => (&instance.distances, None),
+        unit => {
+            let durations = instance.durations.as_ref()
+                .ok_or("VROOM needs the durations between the destinations, which the instance does not hold")?;
+            let metres = match unit {
+                Unit::Kilometers => 1000.0,
+                Unit::Miles      => 1609.344,
+                _                => 1.0,
+            };
+            (durations, Some(integer_matrix(instance, &instance.distances, metres)?))
+        }
+    };
+    let mut matrices = json!({ "durations": integer_matrix(instance, durations, 1.0)? });
+    if let Some(distances) = distances {
+        matrices["distances"] = json!(distances);
+    }
+
+    // the coordinates are written as in the instance, not widened to f64 digits
+    let decimal = |x: f32| x.to_string().parse::<f64>().unwrap_or(x as f64);
+    let location = |i: usize| json!([decimal(instance.destinations[i].longitude), decimal(instance.destinations[i].latitude)]);
+    let mut vehicle = json!({
+        "id": 0,
+        "profile": PROFILE,
+        "start_index": instance.depot,
+        "end_index": instance.depot,
+    });
+    if coordinates {
+        vehicle["start"] = location(instance.depot);
+        vehicle["end"] = location(instance.depot);
+    }
+    let jobs = (0..instance.destinations.len())
+        .filter(|i| *i != instance.depot)
+        .map(|i| {
+            let mut job = json!({ "id": i, "location_index": i });
+            if coordinates {
+                job["location"] = location(i);
+            }
+            if let Some(label) = instance.labels.as_ref().and_then(|labels| labels.get(i)) {
+                job["description"] = Value::from(label.as_str());
+            }
+            job
+        })
+        .collect::<Vec<_>>();
+
+    let problem = json!({
+        "vehicles": [vehicle],
+        "jobs": jobs,
+        "matrices": { PROFILE: matrices },
+    });
+    Ok(serde_json::to_string_pretty(&problem).unwrap())
+}
+
+/// Converts the given matrix to integers after multiplying it by `factor`. Like
+/// in the TSPLIB export, the pairs which cannot be reached and the forbidden arcs
+/// get a value larger than the length of any route avoiding them.
+fn integer_matrix(instance: &Instance, matrix: &Matrix, factor: f64) -> Result<Vec<Vec<u32>>, String> {
+    let n = instance.destinations.len();
+    let longest = matrix.iter().flatten().filter(|d| d.is_finite()).fold(0.0_f64, |a, d| a.max((*d as f64 * factor).round()));
+    let unreachable = longest * n as f64 + 1.0;
+    if unreachable > u32::MAX as f64 {
+        return Err("the values of the matrices exceed the range of VROOM".to_string());
+    }
+    Ok(matrix.iter().enumerate()
+        .map(|(i, row)| row.iter().enumerate()
+            .map(|(j, d)| match d {
+                _ if i == j                                   => 0,
+                d if d.is_finite() && instance.is_allowed(i, j) => (*d as f64 * factor).round() as u32,
+                _                                             => unreachable as u32,
+            })
+            .collect())
+        .collect())
+}
+
+/// A VROOM problem as it is read: only the fields which make sense for a tour
+/// are kept, the others are only detected
+#[derive(Debug, Deserialize)]
+struct VroomProblem {
+    #[serde(default)]
+    vehicles: Vec<Vehicle>,
+    #[serde(default)]
+    jobs: Vec<Job>,
+    #[serde(default)]
+    shipments: Vec<Value>,
+    /// The custom matrices of each profile
+    #[serde(default)]
+    matrices: HashMap<String, Matrices>,
+    /// The custom durations of the former versions of VROOM
+    matrix: Option<Matrix>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Vehicle {
+    profile: Option<String>,
+    start: Option<[f32; 2]>,
+    end: Option<[f32; 2]>,
+    start_index: Option<usize>,
+    end_index: Option<usize>,
+    #[serde(default)]
+    capacity: Vec<i64>,
+    time_window: Option<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Job {
+    id: u64,
+    description: Option<String>,
+    location: Option<[f32; 2]>,
+    location_index: Option<usize>,
+    #[serde(default)]
+    delivery: Vec<i64>,
+    #[serde(default)]
+    pickup: Vec<i64>,
+    #[serde(default)]
+    amount: Vec<i64>,
+    #[serde(default)]
+    time_windows: Vec<Value>,
+    #[serde(default)]
+    service: u64,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Matrices {
+    durations: Option<Matrix>,
+    distances: Option<Matrix>,
+}
+
+/// The places of a tour read from a VROOM problem: the depot (first) and the
+/// jobs, along with the matrices between them when the problem gives some
+#[derive(Debug, Clone)]
+pub struct Problem {
+    /// The places of the depot and of the jobs (all 0 when the problem only
+    /// refers to them by their index in the custom matrices)
+    pub places: Vec<Location>,
+    /// The descriptions of the jobs (or their ids), the depot being labelled
+    /// `depot`
+    pub labels: Vec<String>,
+    /// The custom distances (in metres) between the places
+    pub distances: Option<Matrix>,
+    /// The custom durations (in seconds) between the places
+    pub durations: Option<Matrix>,
+    /// What the problem holds which a tour cannot (ie the demands)
+    pub dropped: Vec<&'static str>,
+}
+
+/// Parses the VROOM problem in the given text. The problem must have a single
+/// vehicle whose route starts and ends at the same place, which becomes the
+/// depot, and no shipment.
+pub fn read(text: &str) -> Result<Problem, String> {
+    let problem: VroomProblem = serde_json::from_str(text).map_err(|e| format!("invalid VROOM problem: {e}"))?;
+    let [vehicle] = problem.vehicles.as_slice() else {
+        return Err(format!("the problem has {} vehicles but a tour is made by exactly one", problem.vehicles.len()));
+    };
+    if !problem.shipments.is_empty() {
+        return Err("the shipments of the problem cannot be part of a tour".to_string());
+    }
+    if problem.jobs.is_empty() {
+        return Err("the problem has no job".to_string());
+    }
+    let (start, end) = ((vehicle.start, vehicle.start_index), (vehicle.end, vehicle.end_index));
+    let depot = match (start, end) {
+        ((None, None), (None, None)) => return Err("the vehicle has neither a start nor an end, which a tour needs".to_string()),
+        ((None, None), end)          => end,
+        (start, (None, None))        => start,
+        (start, end) if start == end => start,
+        _                            => return Err("the vehicle ends its route elsewhere than at its start, which a tour cannot".to_string()),
+    };
+
+    let mut matrices = match (problem.matrix, vehicle.profile.as_deref().unwrap_or(PROFILE)) {
+        (Some(durations), _) => Matrices { durations: Some(durations), distances: None },
+        (None, profile)      => problem.matrices.into_iter().find(|(p, _)| p == profile).map(|(_, m)| m).unwrap_or_default(),
+    };
+    let custom = matrices.durations.is_some() || matrices.distances.is_some();
+    let location = |place: (Option<[f32; 2]>, Option<usize>), name: &str| match place {
+        (_, Some(index)) if custom => Ok((place.0, Some(index))),
+        _ if custom                => Err(format!("{name} has no location index in the custom matrices")),
+        (Some(location), _)        => Ok((Some(location), None)),
+        _                          => Err(format!("{name} has no location and the problem has no custom matrix")),
+    };
+    let mut places = vec![location(depot, "the vehicle")?];
+    for job in problem.jobs.iter() {
+        places.push(location((job.location, job.location_index), &format!("job {}", job.id))?);
+    }
+
+    if custom {
+        let indices = places.iter().filter_map(|place| place.1).collect::<Vec<_>>();
+        let pick = |matrix: Matrix| -> Result<Matrix, String> {
+            let size = matrix.len();
+            if let Some(index) = indices.iter().find(|i| **i >= size || matrix[**i].len() < size) {
+                return Err(format!("the location index {index} is outside of the {size}x{size} custom matrix"));
+            }
+            Ok(indices.iter().map(|i| indices.iter().map(|j| matrix[*i][*j]).collect()).collect())
+        };
+        matrices.durations = matrices.durations.map(pick).transpose()?;
+        matrices.distances = matrices.distances.map(pick).transpose()?;
+    }
+
+    let mut dropped = vec![];
+    let jobs = problem.jobs.iter();
+    if !vehicle.capacity.is_empty() || jobs.clone().any(|j| !j.delivery.is_empty() || !j.pickup.is_empty() || !j.amount.is_empty()) {
+        dropped.push("the demands and capacities");
+    }
+    if vehicle.time_window.is_some() || jobs.clone().any(|j| !j.time_windows.is_empty()) {
+        dropped.push("the time windows");
+    }
+    if jobs.clone().any(|j| j.service > 0) {
+        dropped.push("the service times");
+    }
+
+    let mut labels = vec!["depot".to_string()];
+    labels.extend(jobs.map(|j| j.description.clone().unwrap_or_else(|| j.id.to_string())));
+    Ok(Problem {
+        places: places.iter()
+            .map(|place| place.0.map_or(Location { longitude: 0.0, latitude: 0.0 }, |[longitude, latitude]| Location { longitude, latitude }))
+            .collect(),
+        labels,
+        distances: matrices.distances,
+        durations: matrices.durations,
+        dropped,
+    })
+}
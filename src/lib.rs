@@ -0,0 +1,40 @@
+//! TspGen generates realistic TSP instances where the cities to visit are
+//! grouped in clusters, and solves them with branch and bound over decision
+//! diagrams.
+//!
+//! The `instance` and `resolution` modules are the public API of the library:
+//! they let another crate load, build and solve instances without going through
+//! the command line. The other modules implement the commands of the `tsptools`
+//! binary and are only exposed so that it can be kept thin.
+
+pub mod instance;
+pub mod resolution;
+
+pub mod anonymization;
+pub mod certificate;
+pub mod comparison;
+pub mod conversion;
+pub mod deduplication;
+pub mod diffing;
+pub mod directions;
+pub mod export;
+pub mod generation;
+pub mod geometry;
+pub mod glob;
+pub mod import;
+pub mod inspection;
+pub mod nominatim;
+pub mod refresh;
+pub mod reindexing;
+pub mod results;
+pub mod statistics;
+pub mod status;
+pub mod stdio;
+pub mod tour;
+pub mod transit;
+pub mod tuning;
+pub mod verification;
+pub mod visualisation;
+
+#[cfg(feature = "ffi")]
+pub mod ffi;
@@ -1,13 +1,9 @@
-use clap::{Parser, Subcommand};
-use generation::GenerateInstance;
-use resolution::Solve;
-use visualisation::Visualize;
-
-
-mod instance;
-mod generation;
-mod visualisation;
-mod resolution;
+use clap::{Parser, Subcommand, ValueEnum};
+use tracing_subscriber::fmt::format::FmtSpan;
+use tsptools::{
+    anonymization::Anonymize, certificate::CheckCertificate, comparison::Compare, conversion::Convert, deduplication::Dedupe, diffing::Diff, export::Export, generation::GenerateInstance, import::Import, inspection::InspectState, refresh::Refresh, reindexing::Reindex, resolution::Solve,
+    statistics::Stats, status::ExitStatus, tuning::Tune, verification::Verify, visualisation::Visualize,
+};
 
 /// TspGen is a generator for realistic TSP instances where the cities to visit are gouped in clusters.
 /// 
@@ -18,24 +14,84 @@ mod resolution;
 #[derive(Debug, Parser)]
 #[command(author, version, about, long_about = None)]
 #[command(propagate_version = true)]
+#[command(after_help = tsptools::status::HELP)]
 struct TspTools {
     #[command(subcommand)]
     command: Command,
+    /// Only print machine readable output on stdout (diagnostics go to stderr)
+    #[arg(short, long, global = true)]
+    quiet: bool,
+    /// Traces the phases of the solver runs (loading, preprocessing, search) on
+    /// stderr along with their durations, the incumbents and the cutoffs
+    #[arg(long, global = true, value_enum)]
+    trace_format: Option<TraceFormat>,
+}
+
+/// The formats of the traces
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum TraceFormat {
+    /// Multi-line human readable records
+    Pretty,
+    /// One json object per line
+    Json,
 }
 
 #[derive(Debug, Subcommand)]
 enum Command {
-    Generate(GenerateInstance),
+    Generate(Box<GenerateInstance>),
     Visualize(Visualize),
-    Solve(Solve)
+    Solve(Solve),
+    Verify(Verify),
+    Compare(Compare),
+    Dedupe(Dedupe),
+    Refresh(Refresh),
+    Stats(Stats),
+    Tune(Tune),
+    InspectState(InspectState),
+    Export(Export),
+    Import(Import),
+    Reindex(Reindex),
+    Convert(Convert),
+    Anonymize(Anonymize),
+    Diff(Diff),
+    CheckCertificate(CheckCertificate),
 }
 
 #[tokio::main]
 async fn main() {
-    let cli = TspTools::parse();
+    // the usage errors are input errors, not the status 2 of clap which tells
+    // that a tour was not proven optimal
+    let cli = TspTools::try_parse().unwrap_or_else(|e| {
+        let _ = e.print();
+        if e.use_stderr() { ExitStatus::InputError.exit() } else { ExitStatus::Success.exit() }
+    });
+    if let Some(format) = cli.trace_format {
+        // the duration of each span is traced when it closes
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(std::io::stderr)
+            .with_span_events(FmtSpan::CLOSE);
+        match format {
+            TraceFormat::Pretty => subscriber.pretty().init(),
+            TraceFormat::Json   => subscriber.json().with_span_list(true).init(),
+        }
+    }
     match cli.command {
-        Command::Generate(generate) => generate.execute().await,
-        Command::Visualize(visualize) => visualize.execute().await,
-        Command::Solve(solve) => solve.execute().await
+        Command::Generate(mut generate) => generate.execute(cli.quiet).await,
+        Command::Visualize(visualize) => visualize.execute(cli.quiet).await,
+        Command::Solve(solve) => solve.execute(cli.quiet).await,
+        Command::Verify(verify) => verify.execute(cli.quiet).await,
+        Command::Compare(compare) => compare.execute(cli.quiet).await,
+        Command::Dedupe(dedupe) => dedupe.execute(cli.quiet).await,
+        Command::Refresh(refresh) => refresh.execute(cli.quiet).await,
+        Command::Stats(stats) => stats.execute(cli.quiet).await,
+        Command::Tune(tune) => tune.execute(cli.quiet).await,
+        Command::InspectState(inspect) => inspect.execute(cli.quiet).await,
+        Command::Export(export) => export.execute(cli.quiet).await,
+        Command::Import(import) => import.execute(cli.quiet).await,
+        Command::Reindex(reindex) => reindex.execute(cli.quiet).await,
+        Command::Convert(convert) => convert.execute(cli.quiet).await,
+        Command::Anonymize(anonymize) => anonymize.execute(cli.quiet).await,
+        Command::Diff(diff) => diff.execute(cli.quiet).await,
+        Command::CheckCertificate(check) => check.execute(cli.quiet).await,
     }
 }
\ No newline at end of file
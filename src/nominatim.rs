@@ -0,0 +1,150 @@
+//! This module provides a minimal client for the Nominatim geocoding service of
+//! OpenStreetMap. It complies with the usage policy of the service: every request
+//! carries an identifying user agent and the requests are spaced by a minimum delay.
+
+use std::{collections::BTreeMap, fs::{File, read_to_string}, io::Write, path::Path, time::Duration};
+
+use osrm_client::Location;
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::{sync::Mutex, time::Instant};
+
+/// One of the places matching a search
+#[derive(Debug, Clone, Deserialize)]
+pub struct Place {
+    pub display_name: String,
+    #[serde(default)]
+    pub osm_type: String,
+    #[serde(default)]
+    pub osm_id: u64,
+    /// The outline of the place (only present when it was requested)
+    #[serde(default)]
+    pub geojson: Option<Value>,
+}
+
+/// The response of a reverse geocoding request
+#[derive(Debug, Clone, Deserialize)]
+struct Address {
+    #[serde(default)]
+    display_name: Option<String>,
+    #[serde(default)]
+    address: BTreeMap<String, Value>,
+}
+
+impl Address {
+    /// A short description of the address: street and house number followed by
+    /// the locality, or the beginning of the full address when there is no street
+    fn short(&self) -> Option<String> {
+        let field = |keys: &[&str]| keys.iter().find_map(|k| self.address.get(*k).and_then(|v| v.as_str()));
+        let street = field(&["road", "pedestrian", "footway", "square"]);
+        let locality = field(&["city", "town", "village", "hamlet", "municipality"]);
+        match (street, field(&["house_number"]), locality) {
+            (Some(street), Some(number), Some(locality)) => Some(format!("{street} {number}, {locality}")),
+            (Some(street), None, Some(locality)) => Some(format!("{street}, {locality}")),
+            (Some(street), number, None) => Some(format!("{street} {}", number.unwrap_or_default()).trim_end().to_string()),
+            _ => self.display_name.as_ref().map(|name| name.split(',').take(2).collect::<Vec<_>>().join(",")),
+        }
+    }
+}
+
+/// A client of a Nominatim server
+#[derive(Debug)]
+pub struct Nominatim {
+    client: reqwest::Client,
+    base_url: String,
+    min_delay: Duration,
+    last_request: Mutex<Option<Instant>>,
+}
+
+impl Nominatim {
+    /// Creates a client of the server at `base_url` which identifies itself with
+    /// the given user agent and waits `min_delay` between two requests
+    pub fn new(base_url: &str, user_agent: &str, min_delay: Duration) -> Result<Self, String> {
+        let client = reqwest::Client::builder()
+            .user_agent(user_agent)
+            .build()
+            .map_err(|e| format!("cannot create the nominatim client: {e}"))?;
+        Ok(Nominatim {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            min_delay,
+            last_request: Mutex::new(None),
+        })
+    }
+
+    /// Returns the places matching the given name along with their outline
+    pub async fn search_outlines(&self, name: &str) -> Result<Vec<Place>, String> {
+        let query = [("q", name), ("format", "jsonv2"), ("polygon_geojson", "1")];
+        self.get("search", &query).await
+    }
+
+    /// Returns a short address of the given location (None when there is no
+    /// address at this location)
+    pub async fn reverse(&self, location: Location) -> Result<Option<String>, String> {
+        let lat = location.latitude.to_string();
+        let lon = location.longitude.to_string();
+        let query = [("lat", lat.as_str()), ("lon", lon.as_str()), ("format", "jsonv2"), ("addressdetails", "1")];
+        let address: Address = self.get("reverse", &query).await?;
+        Ok(address.short())
+    }
+
+    /// Sends a request to the given endpoint and parses its json response
+    async fn get<T: for<'de> Deserialize<'de>>(&self, endpoint: &str, query: &[(&str, &str)]) -> Result<T, String> {
+        {
+            let mut last = self.last_request.lock().await;
+            if let Some(last) = *last {
+                tokio::time::sleep_until(last + self.min_delay).await;
+            }
+            *last = Some(Instant::now());
+        }
+        self.client.get(format!("{}/{endpoint}", self.base_url))
+            .query(query)
+            .send().await
+            .and_then(|rsp| rsp.error_for_status())
+            .map_err(|e| format!("nominatim request failed: {e}"))?
+            .json::<T>().await
+            .map_err(|e| format!("invalid nominatim response: {e}"))
+    }
+}
+
+/// An on-disk cache of the addresses of the locations that were reverse geocoded
+#[derive(Debug, Default)]
+pub struct AddressCache {
+    path: String,
+    addresses: BTreeMap<String, String>,
+}
+
+impl AddressCache {
+    /// Opens the cache stored in the json file at the given path (an empty cache
+    /// when the file does not exist yet)
+    pub fn open(path: &str) -> Result<Self, String> {
+        let addresses = if Path::new(path).exists() {
+            let text = read_to_string(path).map_err(|e| format!("cannot read {path}: {e}"))?;
+            serde_json::from_str(&text).map_err(|e| format!("invalid address cache {path}: {e}"))?
+        } else {
+            BTreeMap::new()
+        };
+        Ok(AddressCache { path: path.to_string(), addresses })
+    }
+
+    /// Returns the short address of the given location, asking Nominatim when it
+    /// is not in the cache yet
+    pub async fn address(&mut self, nominatim: &Nominatim, location: Location) -> Result<Option<String>, String> {
+        let key = format!("{:.6},{:.6}", location.latitude, location.longitude);
+        if let Some(address) = self.addresses.get(&key) {
+            return Ok(Some(address.clone()));
+        }
+        let address = nominatim.reverse(location).await?;
+        if let Some(address) = address.as_ref() {
+            self.addresses.insert(key, address.clone());
+        }
+        Ok(address)
+    }
+
+    /// Writes the cache back to its file
+    pub fn save(&self) -> Result<(), String> {
+        let text = serde_json::to_string_pretty(&self.addresses).unwrap();
+        File::create(&self.path).and_then(|mut f| f.write_all(text.as_bytes()))
+            .map_err(|e| format!("cannot write {}: {e}", self.path))
+    }
+}
@@ -0,0 +1,127 @@
+//! This module implements the command which recomputes the distance matrix of
+//! an existing instance.
+
+use clap::Args;
+use serde::Serialize;
+
+use crate::{generation::{travel_matrices, now}, instance::{Instance, Matrix, Unit}, status::fail, stdio::is_stdio};
+
+/// This command fetches the distance matrix of an instance again (the map data
+/// of the router may have changed) while keeping its destinations, labels and
+/// metadata. It prints a summary of the changes.
+#[derive(Debug, Args)]
+pub struct Refresh {
+    /// The path to the instance file
+    #[clap(short, long)]
+    pub instance: String,
+    /// URL of the osrm server to use (optional)
+    #[clap(short, long)]
+    pub url_osrm: Option<String>,
+    /// The changes larger than this threshold (in the unit of the matrix) are counted
+    #[clap(short, long, default_value="0")]
+    pub threshold: f32,
+    /// Also add (or refresh) the durations matrix of an instance whose costs are
+    /// distances. The durations of an instance which already has some are always
+    /// refreshed.
+    #[clap(short, long)]
+    pub durations: bool,
+    /// Name of the file where to write the refreshed instance, `-` for stdout (the
+    /// instance file is overwritten by default)
+    #[clap(short, long)]
+    pub output: Option<String>,
+}
+
+/// A summary of the changes between the old and the new matrix
+#[derive(Debug, Clone, Serialize)]
+pub struct MatrixChanges {
+    /// The largest absolute change of an entry (both entries being finite)
+    pub max_change: f32,
+    /// The mean absolute change of the entries (both entries being finite)
+    pub mean_change: f64,
+    /// The number of entries which changed by more than the threshold
+    pub nb_changed: usize,
+    /// The number of pairs which were reachable and are not anymore (or conversely)
+    pub nb_reachability_changes: usize,
+}
+
+impl Refresh {
+    /// Executes this command. In quiet mode, the summary is printed as json. It
+    /// is printed on stderr when the refreshed instance is written on stdout.
+    pub async fn execute(&self, quiet: bool) {
+        match self.refresh().await {
+            // the refreshed instance is the only output in quiet mode
+            Ok(_) if quiet && is_stdio(self.target()) => {}
+            Ok(changes) if quiet => println!("{}", serde_json::to_string(&changes).unwrap()),
+            Ok(changes) => {
+                let summary = format!("max change          {:.3}\nmean change         {:.3}\nchanged entries     {} (by more than {})\nreachability change {}",
+                    changes.max_change, changes.mean_change, changes.nb_changed, self.threshold, changes.nb_reachability_changes);
+                if is_stdio(self.target()) { eprintln!("{summary}") } else { println!("{summary}") }
+            }
+            Err(e) => fail(e),
+        }
+    }
+
+    /// The path where the refreshed instance is written
+    fn target(&self) -> &str {
+        self.output.as_deref().unwrap_or(&self.instance)
+    }
+
+    async fn refresh(&self) -> Result<MatrixChanges, String> {
+        let mut instance = Instance::load(&self.instance)?;
+        if instance.metadata.anonymization.is_some() {
+            return Err(format!("{} is anonymized: its coordinates are not those of the destinations", self.instance));
+        }
+        if instance.metadata.transit.is_some() {
+            return Err(format!("{} holds transit travel times, which the road router cannot refresh (generate it again from the GTFS feed)", self.instance));
+        }
+        if !instance.has_coordinates() {
+            return Err(format!("{} has no coordinates to route (it was built from a bare matrix)", self.instance));
+        }
+        let duration = match instance.metadata.unit {
+            Unit::Meters  => false,
+            Unit::Seconds => true,
+            unit => return Err(format!("cannot refresh a matrix expressed in {unit:?}, the router gives metres or seconds")),
+        };
+
+        let mut client = osrm_client::Client::default();
+        if let Some(url) = self.url_osrm.as_ref() {
+            client = client.base_url(url.clone());
+        }
+        let (distances, durations) = travel_matrices(&client, &instance.destinations).await?;
+        let (costs, durations) = if duration { (durations, None) } else { (distances, Some(durations)) };
+        let changes = matrix_changes(&instance.distances, &costs, self.threshold);
+
+        instance.distances = costs;
+        if self.durations || instance.durations.is_some() {
+            instance.durations = durations;
+        }
+        instance.metadata.generated_at = Some(now());
+        instance.metadata.router = self.url_osrm.clone();
+        instance.save(Some(self.target()))?;
+        Ok(changes)
+    }
+}
+
+/// Summarizes the differences between two matrices of the same size, the changes
+/// larger than the threshold being counted
+pub fn matrix_changes(old: &Matrix, new: &Matrix, threshold: f32) -> MatrixChanges {
+    let mut changes = MatrixChanges { max_change: 0.0, mean_change: 0.0, nb_changed: 0, nb_reachability_changes: 0 };
+    let mut nb_finite = 0;
+    for (a, b) in old.iter().flatten().zip(new.iter().flatten()) {
+        if a.is_finite() != b.is_finite() {
+            changes.nb_reachability_changes += 1;
+        } else if a.is_finite() {
+            let change = (a - b).abs();
+            changes.max_change = changes.max_change.max(change);
+            changes.mean_change += change as f64;
+            nb_finite += 1;
+            if change > threshold {
+                changes.nb_changed += 1;
+            }
+        }
+    }
+    if nb_finite > 0 {
+        changes.mean_change /= nb_finite as f64;
+    }
+    changes
+}
@@ -0,0 +1,58 @@
+//! This module implements the command which moves the depot of an instance to
+//! index 0, where the solver expects it.
+
+use clap::Args;
+
+use crate::{instance::Instance, status::fail};
+
+/// This command writes an equivalent instance where the chosen destination (the
+/// depot declared by the instance by default) is the depot at index 0. The other
+/// destinations keep their relative order, and the matrices, labels and other
+/// per-destination data are permuted accordingly.
+#[derive(Debug, Args)]
+pub struct Reindex {
+    /// The path to the instance file
+    #[clap(short, long)]
+    pub instance: String,
+    /// The index of the destination to use as depot
+    #[clap(short, long)]
+    pub depot: Option<usize>,
+    /// The label of the destination to use as depot
+    #[clap(long)]
+    pub depot_label: Option<String>,
+    /// Name of the file where to write the reindexed instance (stdout by default)
+    #[clap(short, long)]
+    pub output: Option<String>,
+}
+
+impl Reindex {
+    /// Executes this command
+    pub async fn execute(&self, quiet: bool) {
+        if let Err(e) = self.reindex(quiet) {
+            fail(e);
+        }
+    }
+
+    fn reindex(&self, quiet: bool) -> Result<(), String> {
+        let instance = Instance::load(&self.instance)?;
+        let n = instance.destinations.len();
+        let depot = match (self.depot, self.depot_label.as_ref()) {
+            (Some(_), Some(_)) => return Err("a depot index and a depot label cannot be used together".to_string()),
+            (None, None) => instance.depot,
+            (Some(depot), None) if depot >= n => return Err(format!("there is no destination {depot}, the instance has {n} destinations")),
+            (Some(depot), None) => depot,
+            (None, Some(label)) => instance.labels.as_ref()
+                .and_then(|labels| labels.iter().position(|l| l == label))
+                .ok_or_else(|| format!("there is no destination labelled '{label}' in {}", self.instance))?,
+        };
+
+        let mut order = vec![depot];
+        order.extend((0..n).filter(|d| *d != depot));
+        if !quiet && depot != 0 {
+            eprintln!("destination {depot} is now the depot, destinations 0 to {} are shifted by one", depot - 1);
+        }
+        let mut reindexed = instance.subset(&order);
+        reindexed.depot = 0;
+        reindexed.save(self.output.as_deref())
+    }
+}
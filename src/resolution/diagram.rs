@@ -0,0 +1,202 @@
+//! This module compiles small decision diagrams layer by layer, outside of the
+//! branch and bound, so that they can be inspected. It follows the same top-down
+//! scheme as ddo: the layers are expanded one variable at a time and, whenever
+//! a layer grows larger than the maximum width, the worst nodes are either
+//! dropped (restricted diagram) or merged into a single node (relaxed diagram).
+
+use std::{collections::HashMap, fmt::Write};
+
+use ddo::{Decision, Problem, Relaxation, StateRanking};
+use smallbitset::Set64;
+use tracing::info_span;
+
+use super::model::{TspModel, TspRelax, TspRanking, TspState};
+
+/// The kind of decision diagram to compile
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompilationKind {
+    /// The worst nodes of a layer that is too wide are merged together
+    Relaxed,
+    /// The worst nodes of a layer that is too wide are dropped
+    Restricted,
+}
+
+/// A node of a compiled decision diagram
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub state: TspState,
+    /// The value of the longest path from the root to this node
+    pub value: isize,
+    /// True iff this node results from the merger of several states
+    pub merged: bool,
+}
+
+/// An arc of a compiled decision diagram
+#[derive(Debug, Clone)]
+pub struct Edge {
+    pub from: usize,
+    pub to: usize,
+    pub decision: Decision,
+    pub cost: isize,
+}
+
+/// Some statistics about one layer of a compiled decision diagram
+#[derive(Debug, Clone)]
+pub struct LayerStats {
+    /// The depth of the nodes in this layer
+    pub depth: usize,
+    /// The number of distinct states in the layer before it was restricted/relaxed
+    pub width_before: usize,
+    /// The number of nodes in the layer after it was restricted/relaxed
+    pub width_after: usize,
+    /// The number of states that have been merged (or dropped) in this layer
+    pub nb_merged: usize,
+    /// The best bound that can be derived from the nodes of this layer
+    pub bound: isize,
+}
+
+/// A compiled decision diagram
+#[derive(Debug, Clone)]
+pub struct Diagram {
+    pub kind: CompilationKind,
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+    pub layers: Vec<LayerStats>,
+    /// The value of the best path from the root to the terminal layer (if any)
+    pub best_value: Option<isize>,
+}
+
+/// Compiles the decision diagram of the given kind rooted in the initial state
+/// of the problem, with layers of at most `width` nodes.
+pub fn compile(problem: &TspModel, relaxation: &TspRelax, ranking: &TspRanking, width: usize, kind: CompilationKind) -> Diagram {
+    let _span = info_span!("compile", ?kind, width).entered();
+    let width = width.max(1);
+    let root = Node { state: problem.initial_state(), value: problem.initial_value(), merged: false };
+    let mut dd = Diagram { kind, nodes: vec![root], edges: vec![], layers: vec![], best_value: None };
+    let mut layer = vec![0];
+
+    while let Some(var) = problem.next_variable(&mut layer.iter().map(|i| &dd.nodes[*i].state)) {
+        let mut arcs = vec![];
+        for from in layer.iter().copied() {
+            let state = dd.nodes[from].state.clone();
+            problem.for_each_in_domain(var, &state, &mut |decision| {
+                let dest = problem.transition(&state, decision);
+                let cost = problem.transition_cost(&state, decision);
+                arcs.push((from, dest, decision, cost));
+            });
+        }
+
+        // the distinct states of the next layer along with their best value
+        let mut index: HashMap<TspState, usize> = HashMap::new();
+        let mut candidates: Vec<(TspState, isize)> = vec![];
+        for (from, dest, _, cost) in arcs.iter() {
+            let value = dd.nodes[*from].value + cost;
+            match index.get(dest) {
+                Some(i) => candidates[*i].1 = candidates[*i].1.max(value),
+                None => {
+                    index.insert(dest.clone(), candidates.len());
+                    candidates.push((dest.clone(), value));
+                }
+            }
+        }
+        candidates.sort_by(|(sa, va), (sb, vb)| vb.cmp(va).then_with(|| ranking.compare(sb, sa)));
+
+        let width_before = candidates.len();
+        let keep = match kind {
+            _ if width_before <= width => width_before,
+            CompilationKind::Restricted => width,
+            CompilationKind::Relaxed => width - 1,
+        };
+        let rest = candidates.split_off(keep);
+
+        layer.clear();
+        let mut node_of = HashMap::new();
+        for (state, value) in candidates {
+            node_of.insert(state.clone(), dd.nodes.len());
+            layer.push(dd.nodes.len());
+            dd.nodes.push(Node { state, value, merged: false });
+        }
+        let merged = if kind == CompilationKind::Relaxed && !rest.is_empty() {
+            let state = relaxation.merge(&mut rest.iter().map(|(s, _)| s));
+            let node = *node_of.entry(state.clone()).or_insert_with(|| {
+                layer.push(dd.nodes.len());
+                dd.nodes.push(Node { state: state.clone(), value: isize::MIN, merged: true });
+                dd.nodes.len() - 1
+            });
+            dd.nodes[node].merged = true;
+            Some((state, node))
+        } else {
+            None
+        };
+
+        for (from, dest, decision, cost) in arcs {
+            let (to, cost) = match (node_of.get(&dest), &merged) {
+                (Some(to), _) => (*to, cost),
+                (None, Some((state, to))) => (*to, relaxation.relax(&dd.nodes[from].state, &dest, state, decision, cost)),
+                (None, None) => continue,
+            };
+            let value = dd.nodes[from].value + cost;
+            dd.nodes[to].value = dd.nodes[to].value.max(value);
+            dd.edges.push(Edge { from, to, decision, cost });
+        }
+
+        let bound = layer.iter()
+            .map(|i| dd.nodes[*i].value.saturating_add(relaxation.fast_upper_bound(&dd.nodes[*i].state)))
+            .max()
+            .unwrap_or(isize::MIN);
+        dd.layers.push(LayerStats {
+            depth: var.0 + 1,
+            width_before,
+            width_after: layer.len(),
+            nb_merged: rest.len(),
+            bound,
+        });
+    }
+
+    dd.best_value = layer.iter().map(|i| dd.nodes[*i].value).max();
+    dd
+}
+
+impl Diagram {
+    /// Renders this diagram as a graphviz cluster whose node names are prefixed
+    /// with the given prefix
+    pub fn to_dot_cluster(&self, prefix: &str) -> String {
+        let mut out = String::new();
+        let name = match self.kind {
+            CompilationKind::Relaxed => "relaxed",
+            CompilationKind::Restricted => "restricted",
+        };
+        writeln!(out, "  subgraph cluster_{name} {{").unwrap();
+        writeln!(out, "    label=\"{name} dd\";").unwrap();
+        for (i, node) in self.nodes.iter().enumerate() {
+            let style = if node.merged { ", style=filled, fillcolor=orange" } else { "" };
+            writeln!(out, "    {prefix}{i} [label=\"current {}\\nmust {}\\nmight {}\\nvalue {}\"{style}];",
+                set_to_string(node.state.current()),
+                set_to_string(node.state.must_visit()),
+                set_to_string(node.state.might_visit()),
+                node.value).unwrap();
+        }
+        for edge in self.edges.iter() {
+            writeln!(out, "    {prefix}{} -> {prefix}{} [label=\"x{} = {} ({})\"];",
+                edge.from, edge.to, edge.decision.variable.0, edge.decision.value, edge.cost).unwrap();
+        }
+        writeln!(out, "  }}").unwrap();
+        out
+    }
+}
+
+/// Renders the given diagrams in one graphviz digraph
+pub fn to_dot(diagrams: &[&Diagram]) -> String {
+    let mut out = String::from("digraph dd {\n");
+    for (i, dd) in diagrams.iter().enumerate() {
+        out.push_str(&dd.to_dot_cluster(&format!("n{i}_")));
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// Formats a set of destinations as `{1, 4, 9}`
+fn set_to_string(set: Set64) -> String {
+    let items = set.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+    format!("{{{}}}", items.join(", "))
+}
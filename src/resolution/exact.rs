@@ -0,0 +1,202 @@
+//! This module provides an exact reference solver for small instances. It is
+//! completely independent from the decision diagram machinery and is meant to
+//! be used as a source of truth when checking the results of the main solver.
+
+use ddo::Problem;
+
+use super::model::TspModel;
+
+/// The largest instance that can be solved by the reference solver
+pub const MAX_REFERENCE_SIZE: usize = 20;
+
+/// Solves the given problem with the Held-Karp dynamic program (O(2^n n^2)).
+///
+/// The returned value follows the conventions of the main solver (the edge
+/// values are those of `TspModel::edge_value` and are maximized) so that both
+/// results can be compared exactly. The tour is expressed the same way as the
+/// solutions of the main solver: it lists the destinations in visit order,
+/// starting after the depot and ending with the return to the depot (0).
+/// Returns None when no tour visits all the destinations.
+pub fn held_karp(model: &TspModel) -> Option<(isize, Vec<usize>)> {
+    let n = model.nb_variables();
+    assert!(n <= MAX_REFERENCE_SIZE, "the reference solver only handles instances of up to {MAX_REFERENCE_SIZE} destinations");
+    if n <= 1 {
+        return Some((model.edge_value(0, 0).unwrap_or(0), vec![0]));
+    }
+
+    // the subsets only range over the destinations 1..n (bit j-1 stands for j)
+    let m = n - 1;
+    let full = (1_usize << m) - 1;
+    let mut best = vec![isize::MIN; (full + 1) * m];
+    let mut parent = vec![0_u8; (full + 1) * m];
+
+    for j in 1..n {
+        if let Some(v) = model.edge_value(0, j) {
+            best[(1 << (j - 1)) * m + (j - 1)] = v;
+        }
+    }
+    for mask in 1..=full {
+        for j in 1..n {
+            let here = mask * m + (j - 1);
+            if mask & (1 << (j - 1)) == 0 || best[here] == isize::MIN {
+                continue;
+            }
+            for k in 1..n {
+                if mask & (1 << (k - 1)) != 0 {
+                    continue;
+                }
+                if let Some(v) = model.edge_value(j, k) {
+                    let there = (mask | (1 << (k - 1))) * m + (k - 1);
+                    if best[here] + v > best[there] {
+                        best[there] = best[here] + v;
+                        parent[there] = j as u8;
+                    }
+                }
+            }
+        }
+    }
+
+    let (value, last) = (1..n)
+        .filter(|j| best[full * m + (j - 1)] != isize::MIN)
+        .filter_map(|j| model.edge_value(j, 0).map(|v| (best[full * m + (j - 1)] + v, j)))
+        .max()?;
+
+    let mut tour = vec![0];
+    let mut mask = full;
+    let mut j = last;
+    while j != 0 {
+        tour.push(j);
+        let p = parent[mask * m + (j - 1)] as usize;
+        mask &= !(1 << (j - 1));
+        j = p;
+    }
+    tour.reverse();
+    Some((value, tour))
+}
+
+#[cfg(test)]
+mod tests {
+    use osrm_client::Location;
+    use ddo::{Decision, Problem, Variable};
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaChaRng;
+
+    use crate::instance::{Instance, Unit};
+    use crate::resolution::{FrontierKind, MergePolicy, ObjectiveSense, RelaxVariant, SolveOptions, TspModel, solve_instance};
+
+    use super::held_karp;
+
+    /// A random instance of `n` destinations whose distances are whole
+    /// kilometres, so that the reported lengths are exact
+    fn random_instance(rng: &mut ChaChaRng, n: usize, symmetric: bool) -> Instance {
+        let drawn = (0..n).map(|_| (0..n).map(|_| rng.gen_range(1..100) as f32).collect::<Vec<_>>()).collect::<Vec<_>>();
+        let distances = (0..n)
+            .map(|i| (0..n).map(|j| match (i == j, symmetric) {
+                (true, _)      => 0.0,
+                (false, true)  => drawn[i.min(j)][i.max(j)],
+                (false, false) => drawn[i][j],
+            }).collect())
+            .collect();
+        let destinations = (0..n).map(|i| Location { longitude: i as f32, latitude: 0.0 }).collect();
+        Instance::from_distances(destinations, distances, Unit::Kilometers).unwrap()
+    }
+
+    #[test]
+    fn the_solver_finds_the_length_of_held_karp() {
+        for seed in 0..6 {
+            let mut rng = ChaChaRng::seed_from_u64(seed);
+            let n = rng.gen_range(8..=12);
+            // the symmetry is only broken on symmetric instances
+            let symmetric = seed % 2 == 0;
+            let instance = random_instance(&mut rng, n, symmetric);
+            let model = TspModel::new(instance.clone(), ObjectiveSense::Min).unwrap();
+            assert_eq!(model.break_symmetry, symmetric);
+            let (reference, _) = held_karp(&model).unwrap();
+            let expected = ObjectiveSense::Min.tour_length(reference);
+
+            for relax in [RelaxVariant::Plain, RelaxVariant::Corrected] {
+                for merge_policy in [MergePolicy::UnionCurrent, MergePolicy::RepresentativeCurrent] {
+                    for frontier in [FrontierKind::Simple, FrontierKind::NoDup] {
+                        // a narrow width forces the relaxation to merge
+                        let options = SolveOptions::builder()
+                            .width(n)
+                            .relax(relax)
+                            .merge_policy(merge_policy)
+                            .frontier(frontier)
+                            .build()
+                            .unwrap();
+                        let report = solve_instance("random", instance.clone(), &options).unwrap();
+                        let setting = format!("seed {seed}, {n} destinations, {relax} relaxation, {merge_policy} merges, {frontier:?} frontier");
+                        assert!(report.is_exact, "{setting}: the search did not complete");
+                        assert_eq!(report.value, Some(expected), "{setting}");
+                        assert_eq!(report.symmetry_breaking, symmetric, "{setting}");
+                    }
+                }
+            }
+        }
+    }
+
+    /// The destinations which may be visited first from the depot
+    fn first_destinations(model: &TspModel) -> Vec<isize> {
+        let mut values = vec![];
+        model.for_each_in_domain(Variable(0), &model.initial_state(), &mut |decision: Decision| values.push(decision.value));
+        values
+    }
+
+    #[test]
+    fn breaking_the_symmetry_keeps_the_optimal_length() {
+        for seed in 0..4 {
+            let mut rng = ChaChaRng::seed_from_u64(127 + seed);
+            let instance = random_instance(&mut rng, 9, true);
+            let model = TspModel::new(instance.clone(), ObjectiveSense::Min).unwrap();
+            assert!(model.break_symmetry);
+            // one orientation of the tours is cut off from the first decision
+            assert_eq!(first_destinations(&model).len(), 7);
+            let (reference, _) = held_karp(&model).unwrap();
+
+            let report = solve_instance("symmetric", instance, &SolveOptions::builder().timeout(0).build().unwrap()).unwrap();
+            assert!(report.symmetry_breaking);
+            assert!(report.is_exact);
+            assert_eq!(report.value, Some(ObjectiveSense::Min.tour_length(reference)));
+        }
+    }
+
+    #[test]
+    fn the_symmetry_is_not_broken_on_asymmetric_instances() {
+        let mut rng = ChaChaRng::seed_from_u64(127);
+        let mut instance = random_instance(&mut rng, 9, true);
+        // a single asymmetric pair is enough to keep both orientations
+        instance.distances[3][5] += 1.0;
+        let model = TspModel::new(instance.clone(), ObjectiveSense::Min).unwrap();
+        assert!(!model.break_symmetry);
+        assert_eq!(first_destinations(&model).len(), 8);
+
+        let report = solve_instance("asymmetric", instance, &SolveOptions::builder().timeout(0).build().unwrap()).unwrap();
+        assert!(!report.symmetry_breaking);
+        assert_eq!(report.value, Some(ObjectiveSense::Min.tour_length(held_karp(&model).unwrap().0)));
+    }
+
+    #[test]
+    fn a_forced_arc_off_the_optimal_tour_lengthens_it() {
+        let mut rng = ChaChaRng::seed_from_u64(180);
+        let instance = random_instance(&mut rng, 9, false);
+        let options = SolveOptions::builder().timeout(0).build().unwrap();
+        let free = solve_instance("free", instance.clone(), &options).unwrap();
+        let tour = free.tour.unwrap();
+
+        // the first destination of the optimal tour is followed by the second one,
+        // not by the third one
+        let forced = (tour[0], tour[2]);
+        let mut constrained = instance;
+        constrained.forced_arcs = vec![forced];
+        let model = TspModel::new(constrained.clone(), ObjectiveSense::Min).unwrap();
+        let (reference, _) = held_karp(&model).unwrap();
+        let report = solve_instance("forced", constrained, &options).unwrap();
+        assert!(report.is_exact);
+        assert_eq!(report.value, Some(ObjectiveSense::Min.tour_length(reference)));
+        assert!(report.value > free.value);
+        let tour = report.tour.unwrap();
+        let from = tour.iter().position(|d| *d == forced.0).unwrap();
+        assert_eq!(tour[from + 1], forced.1);
+    }
+}
@@ -0,0 +1,57 @@
+//! This module measures the memory used by the solver: a lightweight thread
+//! samples the resident set size (RSS) of the process while the search runs.
+
+use std::{sync::{Arc, atomic::{AtomicBool, AtomicU64, Ordering}}, thread::JoinHandle, time::Duration};
+
+/// The time between two samples of the resident set size
+const SAMPLING_PERIOD: Duration = Duration::from_millis(50);
+
+/// Samples the resident set size of the process on a dedicated thread until it
+/// is stopped, and keeps track of its peak
+pub(super) struct MemorySampler {
+    stopped: Arc<AtomicBool>,
+    peak: Arc<AtomicU64>,
+    handle: JoinHandle<()>,
+}
+
+impl MemorySampler {
+    /// Starts sampling the resident set size
+    pub(super) fn start() -> Self {
+        let stopped = Arc::new(AtomicBool::new(false));
+        let peak = Arc::new(AtomicU64::new(0));
+        let (flag, max) = (stopped.clone(), peak.clone());
+        let handle = std::thread::spawn(move || {
+            while !flag.load(Ordering::Relaxed) {
+                if let Some(rss) = resident_memory() {
+                    max.fetch_max(rss, Ordering::Relaxed);
+                }
+                std::thread::park_timeout(SAMPLING_PERIOD);
+            }
+        });
+        MemorySampler { stopped, peak, handle }
+    }
+
+    /// Stops sampling and returns the peak and the final resident set size (in
+    /// bytes), which are unknown when the platform does not tell them
+    pub(super) fn stop(self) -> (Option<u64>, Option<u64>) {
+        self.stopped.store(true, Ordering::Relaxed);
+        self.handle.thread().unpark();
+        let _ = self.handle.join();
+        let last = resident_memory();
+        let peak = last.map(|rss| rss.max(self.peak.load(Ordering::Relaxed)));
+        (peak, last)
+    }
+}
+
+/// The resident set size of the process in bytes, read from /proc on Linux. It
+/// is unknown on the other platforms.
+fn resident_memory() -> Option<u64> {
+    if cfg!(target_os = "linux") {
+        let status = std::fs::read_to_string("/proc/self/status").ok()?;
+        let line = status.lines().find(|l| l.starts_with("VmRSS:"))?;
+        let kilobytes = line.trim_start_matches("VmRSS:").trim().trim_end_matches("kB").trim().parse::<u64>().ok()?;
+        Some(kilobytes * 1024)
+    } else {
+        None
+    }
+}
@@ -1,58 +1,931 @@
 //! This module provides the facilities to solve a tsp instance using branch and bound with mdd
 
-use std::{fs::File, io::BufReader, time::Duration};
+use std::{fs::read_to_string, path::Path, sync::{Mutex, atomic::{AtomicBool, AtomicUsize, Ordering}}, time::Instant};
 
-use clap::Args;
-use ddo::{ParallelSolver, FixedWidth, TimeBudget, SimpleFrontier, MaxUB, Solver, Completion};
+use clap::{Args, ValueEnum};
+use serde::{Serialize, Deserialize};
+use ddo::{Relaxation, ParallelSolver, SimpleFrontier, NoDupFrontier, Frontier, MaxUB, Solver, Completion, Problem, Cutoff};
+use tracing::{info, info_span};
 
-use self::model::{TspModel, TspRelax, TspRanking};
+use crate::{certificate::Certificate, glob, instance::{Instance, Rounding, Unit}, results::{self, ResultRow, ResultsSink}, status::{ExitStatus, Failure, fail}, stdio::{carries_data, input_name, is_stdio, write_output}, tour::tsplib_tour};
 
+use self::{diagram::CompilationKind, memory::MemorySampler};
+
+pub use self::{
+    model::{TspModel, TspRelax, TspRanking, TspState, ObjectiveSense, RelaxVariant, MergePolicy, SCALE, MAX_DESTINATIONS},
+    options::{SolveOptions, SolveOptionsBuilder},
+    pool::{PoolMember, SolutionPool},
+    probing::Probe,
+    report::{SolveReport, RepeatedReport, Aggregate, Contender, Phases},
+    stream::{solve_stream, SolveEvent, SolveStream},
+    width::{WidthChoice, WidthPolicy},
+};
+
+mod diagram;
+mod exact;
+mod memory;
 mod model;
+mod options;
+mod pool;
+mod portfolio;
+mod probing;
+mod report;
+mod stream;
+mod width;
+
+/// The largest instance whose decision diagrams can be dumped
+const MAX_DUMP_SIZE: usize = 12;
+
+/// The implementations of the frontier offered by the solver
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FrontierKind {
+    Simple,
+    NoDup,
+}
 
 /// This command lets you generate an html file to visualize a given instance
 /// and an optional solution.
 #[derive(Debug, Args)]
+#[command(after_help = crate::status::HELP)]
 pub struct Solve {
-    /// The path to the instance file
-    #[clap(short, long)]
-    pub instance: String,
-    /// max number of nodes in a layeer
-    #[clap(short, long, default_value="100")]
-    pub width: usize,
-    /// timeout
-    #[clap(short, long, default_value="60")]
+    /// The path to the instance file (`-` to read it from stdin). Several
+    /// instances (or glob patterns such as `'bench/*.json'`) are solved in turn,
+    /// the failure of one of them not preventing the others from being solved.
+    #[clap(short, long, required = true, num_args = 1..)]
+    pub instance: Vec<String>,
+    /// max number of nodes in a layeer (per destination left to visit with the
+    /// state-size width policy). With `auto`, short probes try a few fixed widths
+    /// before the search and the one which closes the gap best is kept (the
+    /// probes take a quarter of the timeout at most, a probe which completes its
+    /// search solves the instance).
+    #[clap(short, long, default_value_t = WidthChoice::Given(SolveOptions::default().width))]
+    pub width: WidthChoice,
+    /// How the width of the diagrams is chosen: the same for all of them (fixed)
+    /// or proportional to the number of destinations left to visit from their
+    /// root state (state-size)
+    #[clap(long, value_enum, default_value_t = SolveOptions::default().width_policy)]
+    pub width_policy: WidthPolicy,
+    /// The smallest width given by the state-size width policy
+    #[clap(long, default_value_t = SolveOptions::default().min_width)]
+    pub min_width: usize,
+    /// The largest width given by the state-size width policy
+    #[clap(long, default_value_t = SolveOptions::default().max_width)]
+    pub max_width: usize,
+    /// A per-depth width schedule overriding `width` and `width-policy`: either a file with one width
+    /// per depth or an expression of the depth `d` such as `min(50+10*d, 2000)`
+    #[clap(long)]
+    pub width_schedule: Option<String>,
+    /// timeout (in seconds, 0 means no time limit). The budget only starts with
+    /// the search: the loading and preprocessing of the instance is not counted.
+    #[clap(short, long, default_value_t = SolveOptions::default().timeout)]
     pub timeout: u64,
+    /// Forces a reproducible execution: the search runs on a single thread and
+    /// explores the nodes in a fixed order. Expect the solver to be roughly as
+    /// many times slower as there are cores on the machine.
+    #[clap(long)]
+    pub deterministic: bool,
+    /// Overrides the unit of the distance matrix declared by the instance
+    #[clap(long, value_enum)]
+    pub unit: Option<Unit>,
+    /// Drops the destinations of the instance which have no row in its distance
+    /// matrix rather than rejecting it
+    #[clap(long)]
+    pub truncate_to_matrix: bool,
+    /// The destination where the tour starts and ends (the depot declared by the
+    /// instance by default)
+    #[clap(long)]
+    pub start: Option<usize>,
+    /// Solves the generalized TSP where exactly one destination of each cluster
+    /// is visited. The file gives the name of the cluster of each destination,
+    /// one per line in the order of the instance (the depot always stands for
+    /// its cluster).
+    #[clap(long)]
+    pub gtsp: Option<String>,
+    /// The longest leg allowed between two consecutive destinations of the tour
+    /// (in the unit of the matrix, or in seconds with --departure)
+    #[clap(long)]
+    pub max_leg: Option<f64>,
+    /// Optimizes the travel times of the durations snapshot taken at this departure
+    /// time rather than the distance matrix of the instance
+    #[clap(long)]
+    pub departure: Option<String>,
+    /// The frontier holding the open subproblems: simple keeps all of them while
+    /// no-dup merges the subproblems rooted in the same state (less memory but
+    /// each insertion needs a lookup)
+    #[clap(long, value_enum, default_value_t = SolveOptions::default().frontier)]
+    pub frontier: FrontierKind,
+    /// How the distances (scaled by SCALE) are rounded to the integer costs of
+    /// the solver
+    #[clap(long, value_enum, default_value_t = SolveOptions::default().rounding)]
+    pub rounding: Rounding,
+    /// Whether to look for the shortest (min) or the longest (max) tour
+    #[clap(long, value_enum, default_value_t = SolveOptions::default().objective)]
+    pub objective_sense: ObjectiveSense,
+    /// How the relaxation sets the cost of the arcs entering a merged node: plain
+    /// keeps their cost while corrected lowers it by what the merge is sure to
+    /// give back on the next arc (a tighter bound for a slightly costlier merge).
+    /// Compare the root bounds of both with --diagnose-root.
+    #[clap(long, value_enum, default_value_t = SolveOptions::default().relax)]
+    pub relax: RelaxVariant,
+    /// Where the vehicle might be in a merged state: anywhere it is in one of the
+    /// merged states (union-current), or only where it is in most of them
+    /// (representative-current, the other states being accounted for in the cost
+    /// of the arcs entering the merged node)
+    #[clap(long, value_enum, default_value_t = SolveOptions::default().merge_policy)]
+    pub merge_policy: MergePolicy,
+
+    /// If present, the path where to write the relaxed and restricted decision
+    /// diagrams compiled at the root (graphviz format, small instances only)
+    #[clap(long)]
+    pub dump_dd: Option<String>,
 
-    /// If present, the path where to write the output html
+    /// Only compiles a relaxed decision diagram at the root and prints per-layer
+    /// diagnostics about it (the branch and bound is not started)
+    #[clap(long)]
+    pub diagnose_root: bool,
+
+    /// Cross-checks the result of the solver against the Held-Karp dynamic program
+    /// (instances of up to 20 destinations only)
+    #[clap(long)]
+    pub reference: bool,
+
+    /// Solves the instance this many times with the same settings and reports
+    /// statistics over the runs: the parallel solver does not explore the nodes in
+    /// the same order from one run to the next, hence a single run is seldom
+    /// representative
+    #[clap(long, default_value="1")]
+    pub repeats: usize,
+
+    /// Runs 2 to 4 predefined variants of the settings concurrently (the cores
+    /// being split among them) and keeps the best tour: the search stops as soon
+    /// as one of them proves optimality or the timeout is reached
+    #[clap(long)]
+    pub portfolio: bool,
+
+    /// Races this many independent runs (cycling through the variants of the
+    /// portfolio, the cores being split among them) and stops as soon as one of
+    /// them completes its search or the timeout is reached
+    #[clap(long)]
+    pub race: Option<usize>,
+
+    /// If present, the path where to write the json report of the run (`-` for
+    /// stdout)
     #[clap(short, long)]
     pub output: Option<String>,
+    /// If present, the path where to write the best tour in the TSPLIB TOUR format
+    /// (`-` for stdout)
+    #[clap(long)]
+    pub tour_out: Option<String>,
+    /// If present, the path where to write the certificate of the run: its best
+    /// tour, the cost of the tour recomputed on the matrix, the final bound and
+    /// the digests of the instance and of the settings, which check-certificate
+    /// verifies (`-` for stdout)
+    #[clap(long)]
+    pub certificate: Option<String>,
+    /// Print the json report of the run on stdout, the human readable one going
+    /// to stderr
+    #[clap(long)]
+    pub json: bool,
+    /// If present, a csv or parquet file where a row is appended for each run
+    #[clap(long)]
+    pub results_file: Option<String>,
+    /// Solves this many of the instances concurrently, the cores being split
+    /// among them (small instances make a better use of the cores that way).
+    /// The report of each instance is printed at once when it is solved.
+    #[clap(long, default_value = "1")]
+    pub parallel_instances: usize,
+    /// Also reports a pool of up to this many good tours which differ from one
+    /// another, found by additional searches (each one with the timeout) which
+    /// forbid a share of the legs of the tours already in the pool
+    #[clap(long, default_value = "1")]
+    pub pool_size: usize,
+    /// The smallest difference between two tours of the pool: the share of the
+    /// legs of one tour which the other does not use (between 0 and 1)
+    #[clap(long, default_value = "0.2")]
+    pub pool_min_diff: f64,
 }
 
 impl Solve {
-    pub async fn execute(&self) {
-        let instance = serde_json::from_reader(BufReader::new(File::open(&self.instance).unwrap())).unwrap();
-        
-        let problem = TspModel{instance};
-        let relaxation = TspRelax;
+    /// Executes this command. In quiet mode, the only thing printed on stdout is
+    /// the json report of the run. The human readable report is printed on stderr
+    /// when stdout carries the json report or the tour.
+    pub async fn execute(&self, quiet: bool) {
+        let options = self.options().unwrap_or_else(|e| fail(e));
+        if let Err(e) = self.check_flags(&options) {
+            fail(e);
+        }
+        let instances = glob::expand(&self.instance).unwrap_or_else(|e| fail(e));
+        let [instance] = instances.as_slice() else {
+            self.solve_all(&instances, &options, quiet).exit()
+        };
+        let outcome = self.solve(instance, &options, quiet, None).unwrap_or_else(|e| e.exit());
+        let data_on_stdout = self.json || carries_data(&[self.output.as_deref(), self.tour_out.as_deref(), self.certificate.as_deref()]);
+        match (quiet, data_on_stdout) {
+            (true, _)      => {}
+            (false, true)  => eprint!("{}", outcome.text),
+            (false, false) => print!("{}", outcome.text),
+        }
+        if let Some(path) = self.results_file.as_deref() {
+            let written = results::open(path).and_then(|mut sink| {
+                sink.write(&outcome.rows)?;
+                sink.close()
+            });
+            if let Err(e) = written {
+                eprintln!("warning: {e}");
+            }
+        }
+        if let Some(json) = outcome.json {
+            if let Some(output) = self.output.as_deref() {
+                if let Err(e) = write_output(output, &(serde_json::to_string_pretty(&json).unwrap() + "\n")) {
+                    eprintln!("warning: {e}");
+                }
+            }
+            if (quiet || self.json) && self.output.as_deref().is_none_or(|output| !is_stdio(output)) {
+                println!("{json}");
+            }
+        }
+        outcome.status.exit()
+    }
+
+    /// Checks that the flags of this command can be used together
+    fn check_flags(&self, options: &SolveOptions) -> Result<(), String> {
+        if self.repeats == 0 {
+            return Err("the number of repeats must be positive".to_string());
+        }
+        if self.parallel_instances == 0 {
+            return Err("the number of instances solved concurrently must be positive".to_string());
+        }
+        if self.portfolio && self.race.is_some() {
+            return Err("a portfolio and a race cannot be run together".to_string());
+        }
+        if (self.portfolio || self.race.is_some()) && (self.repeats > 1 || options.deterministic || self.parallel_instances > 1) {
+            return Err("a portfolio or a race cannot be repeated, run deterministically nor run on several instances at once".to_string());
+        }
+        if self.width == WidthChoice::Auto && (options.width_schedule.is_some() || options.width_policy != WidthPolicy::Fixed) {
+            return Err("--width auto chooses a fixed width, it cannot be combined with a width schedule nor another width policy".to_string());
+        }
+        if self.instance.len() > 1 || self.instance.iter().any(|path| glob::is_pattern(path)) {
+            if self.tour_out.is_some() || self.certificate.is_some() || self.dump_dd.is_some() || self.diagnose_root {
+                return Err("--tour-out, --certificate, --dump-dd and --diagnose-root can only be used with a single instance".to_string());
+            }
+            if self.instance.iter().any(|path| is_stdio(path)) {
+                return Err("an instance read from stdin cannot be solved along with other instances".to_string());
+            }
+        }
+        if self.pool_size == 0 {
+            return Err("the size of the pool must be positive".to_string());
+        }
+        if !(self.pool_min_diff > 0.0 && self.pool_min_diff <= 1.0) {
+            return Err(format!("the minimum difference of the pool must be in ]0, 1], got {}", self.pool_min_diff));
+        }
+        if self.pool_size > 1 && options.gtsp.is_some() {
+            return Err("the pool of tours does not handle the generalized TSP".to_string());
+        }
+        if self.certificate.is_some() && options.gtsp.is_some() {
+            return Err("the tours of a generalized TSP visit a part of the destinations, they cannot be certified".to_string());
+        }
+        Ok(())
+    }
+
+    /// Solves the given instances (several of them at once with
+    /// --parallel-instances) and prints a summary of the outcomes. The reports
+    /// and the results of each instance are printed and written as soon as it is
+    /// solved: its json report is printed on stdout (in quiet mode or with
+    /// --json) and written as one line of the output file. The exit status is the
+    /// largest of those of the instances.
+    fn solve_all(&self, instances: &[String], options: &SolveOptions, quiet: bool) -> ExitStatus {
+        let data_on_stdout = quiet || self.json || carries_data(&[self.output.as_deref()]);
+        let results = match self.results_file.as_deref().map(results::open).transpose() {
+            Ok(results) => results,
+            Err(e) => fail(e),
+        };
+        let workers = self.parallel_instances.min(instances.len()).min(portfolio::cores());
+        let threads = (workers > 1).then(|| (portfolio::cores() / workers).max(1));
+
+        let next = AtomicUsize::new(0);
+        let sink = Mutex::new(BatchSink { results, records: String::new(), nb_solved: 0, nb_timeouts: 0, nb_failures: 0, status: ExitStatus::Success });
+        std::thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| {
+                    while let Some(instance) = instances.get(next.fetch_add(1, Ordering::Relaxed)) {
+                        let outcome = self.solve(instance, options, quiet, threads);
+                        let mut sink = sink.lock().unwrap();
+                        let status = match outcome {
+                            Ok(outcome) => {
+                                if !quiet {
+                                    let text = format!("== {instance}\n{}", outcome.text);
+                                    if data_on_stdout { eprint!("{text}") } else { print!("{text}") }
+                                }
+                                if let Some(Err(e)) = sink.results.as_mut().map(|r| r.write(&outcome.rows)) {
+                                    eprintln!("warning: {e}");
+                                }
+                                if let Some(json) = outcome.json {
+                                    if (quiet || self.json) && self.output.as_deref().is_none_or(|output| !is_stdio(output)) {
+                                        println!("{json}");
+                                    }
+                                    sink.records.push_str(&format!("{json}\n"));
+                                }
+                                outcome.status
+                            }
+                            Err(e) => {
+                                eprintln!("error: {instance}: {e}");
+                                e.status()
+                            }
+                        };
+                        match status {
+                            ExitStatus::Success | ExitStatus::Infeasible => sink.nb_solved += 1,
+                            ExitStatus::Feasible | ExitStatus::NoSolution => sink.nb_timeouts += 1,
+                            ExitStatus::Mismatch | ExitStatus::InputError => sink.nb_failures += 1,
+                        }
+                        sink.status = sink.status.max(status);
+                    }
+                });
+            }
+        });
+
+        let sink = sink.into_inner().unwrap();
+        if let Some(Err(e)) = sink.results.map(|mut results| results.close()) {
+            eprintln!("warning: {e}");
+        }
+        if let Some(output) = self.output.as_deref() {
+            if let Err(e) = write_output(output, &sink.records) {
+                eprintln!("warning: {e}");
+            }
+        }
+        let summary = format!("{} instances: {} solved, {} timed out, {} failed", instances.len(), sink.nb_solved, sink.nb_timeouts, sink.nb_failures);
+        if data_on_stdout { eprintln!("{summary}") } else { println!("{summary}") }
+        sink.status
+    }
+
+    /// Solves the given instance (on the given number of threads, all the cores
+    /// by default)
+    fn solve(&self, instance: &str, options: &SolveOptions, quiet: bool, threads: Option<usize>) -> Result<Outcome, Failure> {
+        let prepared = prepare(instance, options)?;
+        let problem = &prepared.problem;
+        if problem.is_trivial() {
+            eprintln!("warning: {instance} is trivial, it has {} destination(s) and thus a single tour", problem.instance.destinations.len());
+        }
+        let relaxation = TspRelax{model: problem, variant: options.relax, merge_policy: options.merge_policy};
+        if let Some(path) = self.dump_dd.as_ref() {
+            self.dump_dd(path, problem, &relaxation, options.root_width(problem.nb_variables()));
+        }
+
+        if self.diagnose_root {
+            self.diagnose_root(problem, &relaxation, options.root_width(problem.nb_variables()), prepared.unit);
+            return Ok(Outcome { status: ExitStatus::Success, json: None, text: String::new(), rows: vec![] });
+        }
+
+        let (mut options, probes, solved) = match self.width {
+            WidthChoice::Auto => {
+                let probing = probing::probe_width(instance, options, &prepared, threads)?;
+                (probing.options, Some(probing.probes), probing.solved)
+            }
+            WidthChoice::Given(_) => (options.clone(), None, None),
+        };
+        options.adapt(problem);
+        let options = &options;
+        // a probe which completed its search stands for a single plain run
+        let single = self.repeats == 1 && self.race.is_none() && !self.portfolio;
+        let mut runs = match solved {
+            Some(mut run) if single => {
+                run.0.settings = options.clone();
+                vec![run]
+            }
+            _ => (0..self.repeats)
+                .map(|_| match self.race {
+                    Some(contenders) => portfolio::solve_race(instance, options, &prepared, contenders),
+                    None if self.portfolio => portfolio::solve_portfolio(instance, options, &prepared),
+                    None => search(instance, options, &prepared, options.cutoff().as_ref(), quiet, threads),
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+        };
+        for (report, _) in runs.iter_mut() {
+            report.probes = probes.clone();
+        }
+        let best = (0..runs.len())
+            .filter(|i| runs[*i].1.is_some())
+            .max_by_key(|i| runs[*i].1)
+            .unwrap_or(0);
+        if self.pool_size > 1 {
+            let pool = pool::gather(instance, options, &prepared, &runs, self.pool_size, self.pool_min_diff, threads)?;
+            runs[best].0.pool = Some(pool);
+        }
+        let (best_value, is_exact, found) = (runs[best].1, runs[best].0.is_exact, runs[best].0.tour.is_some());
+        if let (Some(path), Some(tour)) = (self.tour_out.as_deref(), runs[best].0.tour.as_ref()) {
+            if let Err(e) = write_tour(instance, path, tour, &runs[best].0) {
+                eprintln!("warning: {e}");
+            }
+        }
+        if let Some(path) = self.certificate.as_deref() {
+            if let Err(e) = write_certificate(instance, path, options, &prepared, &runs[best]) {
+                eprintln!("warning: {e}");
+            }
+        }
+
+        let rows = runs.iter().map(|(report, _)| ResultRow::from(report)).collect::<Vec<_>>();
+        let (json, text) = if self.repeats == 1 {
+            let report = &runs[0].0;
+            (serde_json::to_value(report).unwrap(), report.to_string())
+        } else {
+            let report = RepeatedReport::new(runs.into_iter().map(|(report, _)| report).collect(), best);
+            (serde_json::to_value(&report).unwrap(), report.to_string())
+        };
+        let status = if self.reference && !self.check_reference(problem, best_value, is_exact) {
+            ExitStatus::Mismatch
+        } else {
+            ExitStatus::of_run(found, is_exact)
+        };
+        Ok(Outcome { status, json: Some(json), text, rows })
+    }
+
+    /// The solver options given by the flags of this command
+    pub fn options(&self) -> Result<SolveOptions, String> {
+        SolveOptions::builder()
+            .objective(self.objective_sense)
+            .width(match self.width {
+                WidthChoice::Given(width) => width,
+                WidthChoice::Auto         => SolveOptions::default().width,
+            })
+            .width_schedule(self.width_schedule.clone())
+            .width_policy(self.width_policy)
+            .min_width(self.min_width)
+            .max_width(self.max_width)
+            .timeout(self.timeout)
+            .deterministic(self.deterministic)
+            .frontier(self.frontier)
+            .rounding(self.rounding)
+            .relax(self.relax)
+            .merge_policy(self.merge_policy)
+            .departure(self.departure.clone())
+            .unit(self.unit)
+            .truncate_to_matrix(self.truncate_to_matrix)
+            .start(self.start)
+            .gtsp(self.gtsp.clone())
+            .max_leg(self.max_leg)
+            .build()
+    }
+
+    /// Writes the relaxed and restricted decision diagrams compiled at the root
+    /// of the problem in a graphviz file
+    fn dump_dd(&self, path: &str, problem: &TspModel, relaxation: &TspRelax, width: usize) {
+        if problem.nb_variables() > MAX_DUMP_SIZE {
+            eprintln!("warning: not dumping the decision diagrams, the instance has more than {MAX_DUMP_SIZE} destinations");
+            return;
+        }
+        let relaxed = diagram::compile(problem, relaxation, &TspRanking, width, CompilationKind::Relaxed);
+        let restricted = diagram::compile(problem, relaxation, &TspRanking, width, CompilationKind::Restricted);
+        if let Err(e) = write_output(path, &diagram::to_dot(&[&relaxed, &restricted])) {
+            eprintln!("warning: {e}");
+        }
+    }
+
+    /// Prints one row of diagnostics per layer of the relaxed decision diagram
+    /// compiled at the root, followed by the resulting root bound
+    fn diagnose_root(&self, problem: &TspModel, relaxation: &TspRelax, width: usize, unit: Unit) {
+        let relaxed = diagram::compile(problem, relaxation, &TspRanking, width, CompilationKind::Relaxed);
+        let bound = |value: isize| unit.report(self.objective_sense.tour_length(value)).0;
+
+        println!("{:>6} {:>12} {:>12} {:>8} {:>15}", "depth", "width before", "width after", "merged", "bound");
+        for layer in relaxed.layers.iter() {
+            println!("{:>6} {:>12} {:>12} {:>8} {:>15.3}",
+                layer.depth, layer.width_before, layer.width_after, layer.nb_merged, bound(layer.bound));
+        }
+        let symbol = unit.report_symbol();
+        match relaxed.best_value {
+            Some(value) => println!("root bound {:.3} {symbol} ({} relaxation, {} merges)", bound(value), relaxation.variant, relaxation.merge_policy),
+            None        => println!("root bound: the relaxed diagram has no feasible path"),
+        }
+        let fast_bound = relaxation.fast_upper_bound(&problem.initial_state());
+        println!("fast upper bound {:.3} {symbol}", bound(fast_bound));
+    }
+
+    /// Solves the problem with the reference solver and tells loudly when its
+    /// result contradicts the one of the decision diagram solver (false is then
+    /// returned)
+    fn check_reference(&self, problem: &TspModel, best_value: Option<isize>, is_exact: bool) -> bool {
+        if problem.is_clustered() {
+            eprintln!("warning: the reference solver does not handle clusters");
+            return true;
+        }
+        if problem.nb_variables() > exact::MAX_REFERENCE_SIZE {
+            eprintln!("warning: the instance is too large for the reference solver");
+            return true;
+        }
+        let reference = exact::held_karp(problem).map(|(value, _)| value);
+        let consistent = match (best_value, reference) {
+            (Some(v), Some(r)) => v == r || (!is_exact && v < r),
+            (None, None) => true,
+            (None, Some(_)) => !is_exact,
+            (Some(_), None) => false,
+        };
+        if !consistent {
+            eprintln!("error: the solver found {best_value:?} (exact: {is_exact}) but the reference solver found {reference:?}");
+            return false;
+        }
+        eprintln!("the reference solver agrees with the result");
+        true
+    }
+}
+
+/// Writes the given tour of the instance at the given path in the TSPLIB TOUR
+/// format
+fn write_tour(instance: &str, path: &str, tour: &[usize], report: &SolveReport) -> Result<(), String> {
+    let name = Path::new(input_name(instance)).file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    let comment = report.value.map(|v| format!("length {v:.3} {}", report.unit));
+    // the tours found by the solver end with the return to the depot
+    let depot = tour.last().copied().unwrap_or_default();
+    let text = tsplib_tour(&name, tour, depot, comment.as_deref())?;
+    write_output(path, &text)
+}
+
+/// Writes the certificate of the given run (and of the best value it found) at
+/// the given path
+fn write_certificate(instance: &str, path: &str, options: &SolveOptions, prepared: &Prepared, run: &(SolveReport, Option<isize>)) -> Result<(), String> {
+    let (report, best_value) = run;
+    let tour = report.tour.as_ref().ok_or("no tour was found, there is no certificate to write")?;
+    // the bound of a completed search is the value of the optimal tour
+    let bound = match best_value {
+        Some(value) if report.is_exact => Some(options.objective.tour_length(*value)),
+        _ => report.bound.map(|bound| prepared.unit.from_report(bound)),
+    };
+    let certificate = Certificate::new(instance, &prepared.digest, options, &prepared.problem.instance, tour, bound, report.is_exact)?;
+    write_output(path, &(serde_json::to_string_pretty(&certificate).unwrap() + "\n"))
+}
+
+/// The outcome of the runs of the solver on one instance
+struct Outcome {
+    /// The exit status telling the outcome of the runs
+    status: ExitStatus,
+    /// The json report of the runs (none when only the root was diagnosed)
+    json: Option<serde_json::Value>,
+    /// The human readable report of the runs
+    text: String,
+    /// The rows of the runs in the results file
+    rows: Vec<ResultRow>,
+}
+
+/// Where the outcomes of the instances solved by a single command are gathered
+struct BatchSink {
+    results: Option<Box<dyn ResultsSink + Send>>,
+    /// The json reports of the instances, one per line
+    records: String,
+    nb_solved: usize,
+    nb_timeouts: usize,
+    nb_failures: usize,
+    /// The largest exit status of the instances
+    status: ExitStatus,
+}
+
+/// A problem ready to be solved
+struct Prepared {
+    problem: TspModel,
+    /// The unit in which the results are reported
+    unit: Unit,
+    /// The digest of the instance file
+    digest: String,
+    /// The instance as loaded when its distances were replaced by the travel
+    /// times at a departure, to measure the distance of the tours
+    original: Option<Instance>,
+    /// The time spent loading the instance in seconds
+    loading: f64,
+    /// The time spent loading and preparing the problem in seconds
+    preprocessing: f64,
+}
+
+/// Solves the instance at the given path with the given options and returns the
+/// report of the run without printing anything
+pub fn solve(instance: &str, options: &SolveOptions) -> Result<SolveReport, String> {
+    let prepared = prepare(instance, options)?;
+    search(instance, options, &prepared, options.cutoff().as_ref(), true, None).map(|(report, _)| report)
+}
+
+/// Solves the given instance with the given options and returns the report of
+/// the run, which refers to the instance by `name`
+pub fn solve_instance(name: &str, instance: Instance, options: &SolveOptions) -> Result<SolveReport, String> {
+    let prepared = prepare_instance(instance, options, Instant::now())?;
+    search(name, options, &prepared, options.cutoff().as_ref(), true, None).map(|(report, _)| report)
+}
+
+/// Loads the instance and creates the model to solve
+fn prepare(instance: &str, options: &SolveOptions) -> Result<Prepared, Failure> {
+    let start = Instant::now();
+    let loaded = info_span!("load", instance).in_scope(|| Instance::load_with(instance, options.truncate_to_matrix))?;
+    let loading = start.elapsed().as_secs_f64();
+    let mut prepared = prepare_instance(loaded, options, start)?;
+    prepared.loading = loading;
+    Ok(prepared)
+}
+
+/// Creates the model to solve, the preparation started at `start`
+fn prepare_instance(mut instance: Instance, options: &SolveOptions, start: Instant) -> Result<Prepared, Failure> {
+    let _span = info_span!("preprocess", destinations = instance.destinations.len()).entered();
+    let digest = instance.digest();
+    let original = options.departure.is_some().then(|| instance.clone());
+    if let Some(departure) = options.departure.as_ref() {
+        instance.distances = instance.durations_at(departure)?.clone();
+        instance.metadata.unit = Unit::Seconds;
+    }
+    if let Some(start) = options.start {
+        if start >= instance.destinations.len() {
+            return Err(format!("cannot start at destination {start}, the instance has {} destinations", instance.destinations.len()).into());
+        }
+        instance.depot = start;
+    }
+    if let Some(max) = options.max_leg {
+        cap_legs(&mut instance, max);
+    }
+    let restricted = !instance.forbidden_arcs.is_empty() || !instance.forced_arcs.is_empty();
+    if (options.max_leg.is_some() || restricted) && options.gtsp.is_none() {
+        check_admissible_legs(&instance)?;
+    }
+    let unit = options.unit.unwrap_or(instance.metadata.unit);
+    let mut problem = TspModel::with_rounding(instance, options.objective, options.rounding)?;
+    if let Some(path) = options.gtsp.as_ref() {
+        let text = read_to_string(path).map_err(|e| format!("cannot read {path}: {e}"))?;
+        let membership = text.lines().map(|l| l.trim().to_string()).filter(|l| !l.is_empty()).collect::<Vec<_>>();
+        problem = problem.with_clusters(&membership).map_err(|e| format!("{path}: {e}"))?;
+    }
+    Ok(Prepared { problem, unit, digest, original, loading: 0.0, preprocessing: start.elapsed().as_secs_f64() })
+}
+
+/// Makes the legs longer than `max` unreachable
+fn cap_legs(instance: &mut Instance, max: f64) {
+    for d in instance.distances.iter_mut().flatten().filter(|d| **d as f64 > max) {
+        *d = f32::INFINITY;
+    }
+}
+
+/// Fails when one of the destinations cannot be entered or left by a leg which
+/// is both reachable and allowed, since no tour can visit it then
+fn check_admissible_legs(instance: &Instance) -> Result<(), Failure> {
+    let n = instance.destinations.len();
+    if n < 2 {
+        return Ok(());
+    }
+    let admissible = |from: usize, to: usize| from != to && instance.distances[from][to].is_finite()
+        && instance.is_allowed(from, to);
+    for d in 0..n {
+        if !(0..n).any(|to| admissible(d, to)) {
+            return Err(Failure::Infeasible(format!("no admissible leg leaves destination {d}")));
+        }
+        if !(0..n).any(|from| admissible(from, d)) {
+            return Err(Failure::Infeasible(format!("no admissible leg enters destination {d}")));
+        }
+    }
+    Ok(())
+}
 
-        let width = FixedWidth(self.width);
-        let cutoff = TimeBudget::new(Duration::from_secs(self.timeout));
-        let ranking = TspRanking;
-        let mut fringe = SimpleFrontier::new(MaxUB::new(&ranking));
+/// Runs the branch and bound on the prepared problem until it completes or the
+/// cutoff stops it, on the given number of threads (all the cores by default).
+/// Returns the report of the run along with the best value found by the solver.
+fn search(instance: &str, options: &SolveOptions, prepared: &Prepared, cutoff: &(dyn Cutoff + Send + Sync), quiet: bool, threads: Option<usize>) -> Result<(SolveReport, Option<isize>), String> {
+    let Prepared { problem, unit, .. } = prepared;
+    let span = info_span!("search", width = options.width, frontier = ?options.frontier, threads);
+    let _entered = span.enter();
+    let relaxation = TspRelax{model: problem, variant: options.relax, merge_policy: options.merge_policy};
+    let width = options.width_heuristic(problem.nb_variables(), quiet)?;
 
-        let mut solver = ParallelSolver::new(&problem, &relaxation, &ranking, &width, &cutoff, &mut fringe);
+    let ranking = TspRanking;
+    let mut fringe: Box<dyn Frontier<State = TspState> + Send + Sync> = match options.frontier {
+        FrontierKind::Simple => Box::new(SimpleFrontier::new(MaxUB::new(&ranking))),
+        FrontierKind::NoDup  => Box::new(NoDupFrontier::new(MaxUB::new(&ranking))),
+    };
 
+    // the solver borrows the frontier, which is only inspected once it is gone
+    let start = Instant::now();
+    let sampler = MemorySampler::start();
+    let cutoff = TracedCutoff { inner: cutoff, start, triggered: AtomicBool::new(false), polls: AtomicUsize::new(0) };
+    let (best_value, is_exact, solution, best_bound) = {
+        let threads = if options.deterministic { Some(1) } else { threads };
+        let mut solver = match threads {
+            Some(threads) => ParallelSolver::custom(problem, &relaxation, &ranking, width.as_ref(), &cutoff, fringe.as_mut(), threads),
+            None          => ParallelSolver::new(problem, &relaxation, &ranking, width.as_ref(), &cutoff, fringe.as_mut()),
+        };
         let Completion{best_value, is_exact} = solver.maximize();
+        (best_value, is_exact, solver.best_solution(), solver.best_upper_bound())
+    };
+    let time = start.elapsed().as_secs_f64();
+    let (peak_rss, final_rss) = sampler.stop();
+    // ddo 1.0 does not notify the improvements of its incumbent while it runs,
+    // hence the best one is traced once the search is over
+    if let Some(value) = best_value {
+        info!(value = options.objective.tour_length(value), elapsed = time, "incumbent");
+    }
+    let explored_nodes = cutoff.polls.load(Ordering::Relaxed);
+    info!(is_exact, elapsed = time, explored_nodes, frontier_size = fringe.len(), "search over");
+
+    let mut tour = solution;
+    if let Some(tour) = tour.as_mut() {
+        tour.sort_unstable_by_key(|d| d.variable.0);
+    }
+    let tour = tour.map(|t| t.iter().map(|d| d.value as usize).collect::<Vec<_>>());
+    let value = best_value.map(|v| unit.report(options.objective.tour_length(v)).0);
+    // the distance is measured on the instance as loaded, the duration on the
+    // travel times which were optimized if any
+    let (measured, distance_unit) = match prepared.original.as_ref() {
+        Some(original) => (original, original.metadata.unit),
+        None           => (&problem.instance, *unit),
+    };
+    let (distance, duration) = tour.as_ref()
+        .map(|t| (measured.tour_totals(t, distance_unit).0, problem.instance.tour_totals(t, *unit).1))
+        .unwrap_or_default();
+    // the solver starts from sentinel bounds, those are not worth reporting
+    let bound = Some(best_bound)
+        .filter(|b| *b != isize::MAX && *b != isize::MIN)
+        .map(|b| unit.report(options.objective.tour_length(b)).0);
+    let frontier_size = fringe.len();
+    let extras = problem.instance.extras.as_ref().zip(tour.as_ref()).map(|(extras, t)| t.iter().map(|d| extras[*d].clone()).collect());
+    let clusters = tour.as_ref().and_then(|t| t.iter().map(|d| problem.cluster_name(*d).map(str::to_string)).collect());
+    let report = SolveReport {
+        instance: instance.to_string(),
+        digest: prepared.digest.clone(),
+        objective: options.objective,
+        settings: options.clone(),
+        symmetry_breaking: problem.break_symmetry,
+        is_exact,
+        value,
+        bound,
+        unit: unit.report_symbol().to_string(),
+        distance,
+        distance_unit: distance.map(|_| distance_unit.report_symbol().to_string()),
+        duration,
+        tour,
+        extras,
+        clusters,
+        found_by: None,
+        proved_by: None,
+        contenders: None,
+        probes: None,
+        pool: None,
+        explored_nodes,
+        frontier_size,
+        peak_rss,
+        final_rss,
+        preprocessing: prepared.preprocessing,
+        time,
+        phases: Phases { load: prepared.loading, preprocess: prepared.preprocessing - prepared.loading, search: time },
+    };
+    Ok((report, best_value))
+}
+
+/// A cutoff which traces the moment it stops the search. It also counts how many
+/// times the solver asks whether to stop, which it does for each node it takes
+/// from the frontier (and for each layer of the diagrams it compiles): ddo 1.0
+/// does not report the number of nodes it explores.
+struct TracedCutoff<'a> {
+    inner: &'a (dyn Cutoff + Send + Sync),
+    start: Instant,
+    triggered: AtomicBool,
+    polls: AtomicUsize,
+}
+
+impl Cutoff for TracedCutoff<'_> {
+    fn must_stop(&self) -> bool {
+        self.polls.fetch_add(1, Ordering::Relaxed);
+        let stop = self.inner.must_stop();
+        if stop && !self.triggered.swap(true, Ordering::Relaxed) {
+            info!(elapsed = self.start.elapsed().as_secs_f64(), "cutoff triggered");
+        }
+        stop
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use osrm_client::Location;
+    use tempfile::TempDir;
+
+    use crate::instance::{Instance, Unit};
+
+    use super::{Solve, SolveOptions, solve, solve_instance};
+
+    /// The command line of the solve command alone
+    #[derive(Parser)]
+    struct Command {
+        #[command(flatten)]
+        solve: Solve,
+    }
+
+    /// Checks the flags of the solve command given by `args`
+    fn check_flags(args: &[&str]) -> Result<(), String> {
+        let solve = Command::try_parse_from(["solve"].iter().chain(args)).unwrap().solve;
+        let options = solve.options()?;
+        solve.check_flags(&options)
+    }
+
+    /// Solves the instance of the given distances (in kilometres) and returns its
+    /// tour and value. An open tour, which does not come back to the depot 0, is
+    /// solved as a closed tour whose legs back to the depot are free.
+    fn solve_trivial(mut distances: Vec<Vec<f32>>, open: bool) -> (Option<Vec<usize>>, Option<f64>) {
+        if open {
+            distances.iter_mut().for_each(|row| row[0] = 0.0);
+        }
+        let destinations = (0..distances.len()).map(|i| Location { longitude: i as f32, latitude: 0.0 }).collect();
+        let instance = Instance::from_distances(destinations, distances.into(), Unit::Kilometers).unwrap();
+        let report = solve_instance("trivial", instance, &SolveOptions::builder().timeout(0).build().unwrap()).unwrap();
+        assert!(report.is_exact);
+        (report.tour, report.value)
+    }
+
+    #[test]
+    fn the_closed_tour_of_a_single_destination_has_no_length() {
+        assert_eq!(solve_trivial(vec![vec![7.0]], false), (Some(vec![0]), Some(0.0)));
+    }
+
+    #[test]
+    fn the_open_tour_of_a_single_destination_has_no_length() {
+        assert_eq!(solve_trivial(vec![vec![7.0]], true), (Some(vec![0]), Some(0.0)));
+    }
+
+    #[test]
+    fn the_closed_tour_of_two_destinations_goes_there_and_back() {
+        assert_eq!(solve_trivial(vec![vec![0.0, 3.0], vec![5.0, 0.0]], false), (Some(vec![1, 0]), Some(8.0)));
+    }
 
-        let best_value = best_value.map(|v| v as f32 / -100_000_000.0).unwrap_or(0.0); // en kilometres
-        println!("is exact {is_exact}");
-        println!("best value {best_value}");
+    #[test]
+    fn the_open_tour_of_two_destinations_only_goes_there() {
+        assert_eq!(solve_trivial(vec![vec![0.0, 3.0], vec![5.0, 0.0]], true), (Some(vec![1, 0]), Some(3.0)));
+    }
+
+    #[test]
+    fn compatible_flags_are_accepted() {
+        assert_eq!(check_flags(&["-i", "a.json", "--repeats", "3", "--pool-size", "4", "--tour-out", "a.tour"]), Ok(()));
+        assert_eq!(check_flags(&["-i", "a.json", "b.json", "--race", "3"]), Ok(()));
+    }
 
-        let mut sol = String::new();
-        solver.best_solution().unwrap()
-            .iter().map(|d| d.value)
-            .for_each(|v| sol.push_str(&format!("{v} ")));
+    #[test]
+    fn the_counts_must_be_positive() {
+        assert_eq!(check_flags(&["-i", "a.json", "--repeats", "0"]).unwrap_err(), "the number of repeats must be positive");
+        assert_eq!(check_flags(&["-i", "a.json", "--parallel-instances", "0"]).unwrap_err(), "the number of instances solved concurrently must be positive");
+        assert_eq!(check_flags(&["-i", "a.json", "--pool-size", "0"]).unwrap_err(), "the size of the pool must be positive");
+    }
 
-        println!("solution: {sol}");
+    #[test]
+    fn a_portfolio_and_a_race_cannot_be_run_together() {
+        assert_eq!(check_flags(&["-i", "a.json", "--portfolio", "--race", "3"]).unwrap_err(), "a portfolio and a race cannot be run together");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn a_portfolio_or_a_race_runs_once_on_a_single_instance() {
+        let error = "a portfolio or a race cannot be repeated, run deterministically nor run on several instances at once";
+        assert_eq!(check_flags(&["-i", "a.json", "--portfolio", "--repeats", "2"]).unwrap_err(), error);
+        assert_eq!(check_flags(&["-i", "a.json", "--race", "3", "--deterministic"]).unwrap_err(), error);
+        assert_eq!(check_flags(&["-i", "a.json", "b.json", "--portfolio", "--parallel-instances", "2"]).unwrap_err(), error);
+    }
+
+    #[test]
+    fn the_automatic_width_is_fixed() {
+        let error = "--width auto chooses a fixed width, it cannot be combined with a width schedule nor another width policy";
+        assert_eq!(check_flags(&["-i", "a.json", "-w", "auto", "--width-schedule", "10*(d+1)"]).unwrap_err(), error);
+        assert_eq!(check_flags(&["-i", "a.json", "-w", "auto", "--width-policy", "state-size"]).unwrap_err(), error);
+    }
+
+    #[test]
+    fn the_outputs_of_a_single_run_need_a_single_instance() {
+        let error = "--tour-out, --certificate, --dump-dd and --diagnose-root can only be used with a single instance";
+        assert_eq!(check_flags(&["-i", "a.json", "b.json", "--tour-out", "a.tour"]).unwrap_err(), error);
+        assert_eq!(check_flags(&["-i", "a.json", "b.json", "--certificate", "a.cert"]).unwrap_err(), error);
+        assert_eq!(check_flags(&["-i", "a.json", "b.json", "--dump-dd", "a.dot"]).unwrap_err(), error);
+        assert_eq!(check_flags(&["-i", "bench/*.json", "--diagnose-root"]).unwrap_err(), error);
+    }
+
+    #[test]
+    fn stdin_cannot_be_solved_along_with_other_instances() {
+        assert_eq!(check_flags(&["-i", "a.json", "-"]).unwrap_err(), "an instance read from stdin cannot be solved along with other instances");
+    }
+
+    #[test]
+    fn the_minimum_difference_of_the_pool_is_a_share() {
+        for diff in ["0", "1.5", "NaN"] {
+            let error = check_flags(&["-i", "a.json", "--pool-min-diff", diff]).unwrap_err();
+            assert!(error.starts_with("the minimum difference of the pool must be in ]0, 1]"));
+        }
+        assert_eq!(check_flags(&["-i", "a.json", "--pool-min-diff", "1"]), Ok(()));
+    }
+
+    #[test]
+    fn a_generalized_tsp_has_neither_a_pool_nor_a_certificate() {
+        assert_eq!(check_flags(&["-i", "a.json", "--gtsp", "a.txt", "--pool-size", "2"]).unwrap_err(), "the pool of tours does not handle the generalized TSP");
+        assert_eq!(check_flags(&["-i", "a.json", "--gtsp", "a.txt", "--certificate", "a.cert"]).unwrap_err(),
+            "the tours of a generalized TSP visit a part of the destinations, they cannot be certified");
+    }
+
+    #[test]
+    fn an_instance_in_metres_is_reported_in_kilometres() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("metres.json");
+        let instance = r#"{
+            "destinations": [{"longitude": 4.35, "latitude": 50.85}, {"longitude": 4.7, "latitude": 50.88}, {"longitude": 5.57, "latitude": 50.63}],
+            "distances": [[0, 1200, 3500], [1200, 0, 2300], [3500, 2300, 0]],
+            "metadata": {"unit": "meters"}
+        }"#;
+        std::fs::write(&path, instance).unwrap();
+
+        let report = solve(path.to_str().unwrap(), &SolveOptions::builder().timeout(0).build().unwrap()).unwrap();
+        assert_eq!(report.unit, "km");
+        assert_eq!(report.value, Some(7.0));
+        assert_eq!(report.distance, Some(7.0));
+    }
+}
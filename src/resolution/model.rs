@@ -1,26 +1,353 @@
+use std::{fmt::Display, sync::Arc};
+
+use clap::ValueEnum;
 use ddo::{Problem, Variable, Decision, Relaxation, StateRanking};
+use serde::{Serialize, Deserialize};
 use smallbitset::Set64;
 
-use crate::instance::Instance;
+use crate::instance::{Instance, Rounding};
+
+/// The factor by which the distances are multiplied before being rounded to the
+/// integer costs manipulated by the solver
+pub const SCALE: f32 = 100_000.0;
+
+/// The value used to mark the pairs of destinations that cannot reach each other
+const UNREACHABLE: isize = isize::MIN;
+
+/// The largest instance that can be solved (the sets of destinations are Set64)
+pub const MAX_DESTINATIONS: usize = 64;
+
+/// The state of a (possibly merged) node in the decision diagrams. Millions of
+/// these live in the fringe at once, hence they are kept small: the depth is
+/// stored on a single byte (there are at most MAX_DESTINATIONS decisions in a
+/// tour) and so is the place of the vehicle when it is known. Only the merged
+/// states where the vehicle might be at several places are stored out of line.
+///
+/// In json, the sets of destinations are written as sorted lists, ie
+/// `{"depth": 7, "current": [3], "must_visit": [1, 4, 9], "might_visit": []}`.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(into = "StateRepr", try_from = "StateRepr")]
+pub struct TspState(Position);
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
-pub struct TspState {
-    depth:       usize,
+/// Where the vehicle is in a state, along with the rest of the state. A state is
+/// `At` a single place whenever it can be, so that equal states are represented
+/// the same way.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+enum Position {
+    At { depth: u8, current: u8, must_visit: Set64, might_visit: Set64 },
+    Among(Arc<Merged>),
+}
+
+/// A merged state where the vehicle might be at several places
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+struct Merged {
+    depth:       u8,
     current:     Set64,
     must_visit:  Set64,
     might_visit: Set64,
 }
 
+impl TspState {
+    /// Creates the state where the vehicle might be at the `current` places
+    fn new(depth: u8, current: Set64, must_visit: Set64, might_visit: Set64) -> Self {
+        match current.iter().next() {
+            Some(at) if current.len() == 1 => TspState(Position::At { depth, current: at, must_visit, might_visit }),
+            _ => TspState(Position::Among(Arc::new(Merged { depth, current, must_visit, might_visit }))),
+        }
+    }
+    /// The number of decisions that have been made to reach this state
+    pub fn depth(&self) -> usize {
+        match &self.0 {
+            Position::At { depth, .. } => *depth as usize,
+            Position::Among(merged)    => merged.depth as usize,
+        }
+    }
+    /// The destinations where the vehicle might currently be
+    pub fn current(&self) -> Set64 {
+        match &self.0 {
+            Position::At { current, .. } => Set64::singleton(*current),
+            Position::Among(merged)      => merged.current,
+        }
+    }
+    /// The destinations (clusters for a generalized TSP) that must still be visited
+    pub fn must_visit(&self) -> Set64 {
+        match &self.0 {
+            Position::At { must_visit, .. } => *must_visit,
+            Position::Among(merged)         => merged.must_visit,
+        }
+    }
+    /// The destinations (clusters for a generalized TSP) that might still have to
+    /// be visited (merged states and their descendants only)
+    pub fn might_visit(&self) -> Set64 {
+        match &self.0 {
+            Position::At { might_visit, .. } => *might_visit,
+            Position::Among(merged)          => merged.might_visit,
+        }
+    }
+}
+
+impl Display for TspState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let set = |s: Set64| s.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(",");
+        write!(f, "{{depth: {}, current: {{{}}}, must: {{{}}}, might: {{{}}}}}",
+            self.depth(), set(self.current()), set(self.must_visit()), set(self.might_visit()))
+    }
+}
+
+/// The serialized form of a state, where the sets are sorted lists
+#[derive(Serialize, Deserialize)]
+struct StateRepr {
+    depth:       u8,
+    current:     Vec<u8>,
+    must_visit:  Vec<u8>,
+    #[serde(default)]
+    might_visit: Vec<u8>,
+}
+
+impl From<TspState> for StateRepr {
+    fn from(state: TspState) -> Self {
+        StateRepr {
+            depth:       state.depth() as u8,
+            current:     state.current().iter().collect(),
+            must_visit:  state.must_visit().iter().collect(),
+            might_visit: state.might_visit().iter().collect(),
+        }
+    }
+}
+
+impl TryFrom<StateRepr> for TspState {
+    type Error = String;
+
+    fn try_from(repr: StateRepr) -> Result<Self, String> {
+        let set = |values: &[u8]| values.iter().try_fold(Set64::empty(), |set, v| {
+            if (*v as usize) < MAX_DESTINATIONS { Ok(set.insert(*v)) } else { Err(format!("destination {v} is out of range")) }
+        });
+        Ok(TspState::new(repr.depth, set(&repr.current)?, set(&repr.must_visit)?, set(&repr.might_visit)?))
+    }
+}
+
+/// Tells whether the solver looks for the shortest or the longest tour
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ObjectiveSense {
+    #[default]
+    Min,
+    Max,
+}
+
+impl ObjectiveSense {
+    /// Converts an objective value of the solver back to a tour length
+    pub fn tour_length(self, value: isize) -> f64 {
+        match self {
+            ObjectiveSense::Min => -value as f64 / SCALE as f64,
+            ObjectiveSense::Max =>  value as f64 / SCALE as f64,
+        }
+    }
+}
+
+impl Display for ObjectiveSense {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ObjectiveSense::Min => write!(f, "min"),
+            ObjectiveSense::Max => write!(f, "max"),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct TspModel {
     pub instance: Instance,
+    pub sense: ObjectiveSense,
+    /// The destination where the tours start and end
+    pub depot: usize,
+    /// When set, only one orientation of each tour is explored: the first
+    /// destination after the depot (in index order) must be visited before the
+    /// second one. This is only valid for symmetric instances where a tour and its
+    /// reverse have the same cost.
+    pub break_symmetry: bool,
+    /// The two destinations ordered when breaking the symmetry
+    symmetry_pair: (u8, u8),
+    /// The (scaled) value of each edge as seen by the solver, stored row by row.
+    /// Unreachable pairs hold the UNREACHABLE sentinel.
+    values: Vec<isize>,
+    /// The cluster of each destination. Each destination is its own cluster
+    /// unless the tours only visit one destination per cluster (generalized TSP):
+    /// the `must_visit` and `might_visit` sets of the states then hold clusters.
+    cluster_of: Vec<u8>,
+    /// The destinations of each cluster
+    members: Vec<Set64>,
+    /// The name of each cluster (only when the destinations are grouped)
+    cluster_names: Option<Vec<String>>,
+    /// The cheapest (scaled) cost of an edge entering each cluster
+    min_incoming: Vec<isize>,
+    /// The most expensive (scaled) cost of an edge entering each cluster
+    max_incoming: Vec<isize>,
+}
+
+impl TspModel {
+    /// Creates a new model for the given instance. This fails when the instance is
+    /// empty or when the distances are so large that the scaled cost of a tour
+    /// could overflow an isize.
+    ///
+    /// The tours start and end at the depot of the instance. The trivial instances
+    /// are well defined: with a single destination the tour is "0" and has length
+    /// 0, and with two destinations the tour is "1 0" (when the depot is 0) whose
+    /// length is the sum of both legs between the depot and the other destination.
+    pub fn new(instance: Instance, sense: ObjectiveSense) -> Result<Self, String> {
+        Self::with_rounding(instance, sense, Rounding::Nearest)
+    }
+
+    /// Creates a new model like `new`, the scaled distances being rounded to the
+    /// integer costs with the given strategy
+    pub fn with_rounding(instance: Instance, sense: ObjectiveSense, rounding: Rounding) -> Result<Self, String> {
+        let n = instance.destinations.len();
+        let distances = &instance.distances;
+        if n == 0 {
+            return Err("the instance has no destination".to_string());
+        }
+        if n > MAX_DESTINATIONS {
+            return Err(format!("the solver handles at most {MAX_DESTINATIONS} destinations but the instance has {n}"));
+        }
+        if instance.depot >= n {
+            return Err(format!("the depot {} is not one of the {n} destinations", instance.depot));
+        }
+
+        let longest = distances.iter().flatten()
+            .filter(|d| d.is_finite())
+            .fold(0.0_f64, |m, d| m.max(d.abs() as f64));
+        if longest * SCALE as f64 * n as f64 >= (isize::MAX / 4) as f64 {
+            return Err(format!(
+                "the distances are too large: the longest edge ({longest}) scaled by {SCALE} over {n} legs does not fit in the integer costs of the solver"));
+        }
+
+        // the costs are scaled once and for all so that the cost of a tour is
+        // exactly the sum of the costs of its legs
+        let mut costs = distances.iter().flatten()
+            .map(|d| if d.is_finite() { Some(rounding.round(*d as f64 * SCALE as f64) as isize) } else { None })
+            .collect::<Vec<_>>();
+        // the arcs which are forbidden, or which contradict a forced arc, are
+        // treated like unreachable pairs
+        if !instance.forbidden_arcs.is_empty() || !instance.forced_arcs.is_empty() {
+            for from in 0..n {
+                for to in (0..n).filter(|to| *to != from && !instance.is_allowed(from, *to)) {
+                    costs[from * n + to] = None;
+                }
+            }
+        }
+        // a tour of a single destination is the trivial tour "0" which costs
+        // nothing, whatever the diagonal of the matrix says
+        let values = costs.iter().enumerate()
+            .map(|(i, c)| if n == 1 && i == 0 { &Some(0) } else { c })
+            .map(|c| match (c, sense) {
+                (None, _)                      => UNREACHABLE,
+                (Some(c), ObjectiveSense::Min) => -c,
+                (Some(c), ObjectiveSense::Max) => *c,
+            })
+            .collect();
+
+        let costs = &costs;
+        let incoming = |to: usize| (0..n).filter(move |from| *from != to)
+            .filter_map(move |from| costs[from * n + to]);
+        let min_incoming = (0..n).map(|to| incoming(to).min().unwrap_or(0)).collect();
+        let max_incoming = (0..n).map(|to| incoming(to).max().unwrap_or(0)).collect();
+        let symmetric = (0..n).all(|i| (0..i).all(|j| costs[i * n + j] == costs[j * n + i]));
+        let break_symmetry = symmetric && n >= 3;
+        let depot = instance.depot;
+        let mut others = (0..n as u8).filter(|d| *d as usize != depot);
+        let symmetry_pair = (others.next().unwrap_or(0), others.next().unwrap_or(0));
+        let cluster_of = (0..n as u8).collect();
+        let members = (0..n as u8).map(Set64::singleton).collect();
+        Ok(TspModel { instance, sense, depot, break_symmetry, symmetry_pair, cluster_of, members, cluster_names: None, values, min_incoming, max_incoming })
+    }
+
+    /// Turns this model into a generalized TSP where the tours visit exactly one
+    /// destination of each cluster. `membership` gives the name of the cluster of
+    /// each destination. The depot is always visited: it stands for its whole
+    /// cluster, whose other destinations are never visited.
+    pub fn with_clusters(mut self, membership: &[String]) -> Result<Self, String> {
+        let n = self.instance.destinations.len();
+        if membership.len() != n {
+            return Err(format!("the membership lists {} destinations but the instance has {n}", membership.len()));
+        }
+        if self.cluster_names.is_some() {
+            return Err("the destinations are already grouped in clusters".to_string());
+        }
+        let mut names: Vec<String> = vec![];
+        let mut cluster_of = vec![];
+        let mut members: Vec<Set64> = vec![];
+        for (d, name) in membership.iter().enumerate() {
+            let cluster = match names.iter().position(|c| c == name) {
+                Some(cluster) => cluster,
+                None => {
+                    names.push(name.clone());
+                    members.push(Set64::empty());
+                    names.len() - 1
+                }
+            };
+            cluster_of.push(cluster as u8);
+            members[cluster] = members[cluster].insert(d as u8);
+        }
+        // the bound of a cluster is that of its most favourable member
+        let bound = |of: &[isize], best: fn(isize, isize) -> isize| members.iter()
+            .map(|m| m.iter().map(|d| of[d as usize]).reduce(best).unwrap_or(0))
+            .collect::<Vec<_>>();
+        self.min_incoming = bound(&self.min_incoming, isize::min);
+        self.max_incoming = bound(&self.max_incoming, isize::max);
+        self.cluster_of = cluster_of;
+        self.members = members;
+        self.cluster_names = Some(names);
+        self.break_symmetry = false;
+        Ok(self)
+    }
+
+    /// Returns true iff the tours visit one destination per cluster
+    pub fn is_clustered(&self) -> bool {
+        self.cluster_names.is_some()
+    }
+
+    /// Returns the name of the cluster of the given destination when the tours
+    /// visit one destination per cluster
+    pub fn cluster_name(&self, destination: usize) -> Option<&str> {
+        let names = self.cluster_names.as_ref()?;
+        Some(names[self.cluster_of[destination] as usize].as_str())
+    }
+
+    /// The largest number of values a decision can take: the first one chooses
+    /// among all the destinations which are not in the cluster of the depot
+    pub fn largest_domain(&self) -> usize {
+        let depot = self.cluster_of[self.depot] as usize;
+        self.members.iter().enumerate()
+            .filter(|(cluster, _)| *cluster != depot)
+            .map(|(_, members)| members.len())
+            .sum()
+    }
+
+    /// Returns true iff the instance has so few destinations that it admits a
+    /// single tour
+    pub fn is_trivial(&self) -> bool {
+        self.instance.destinations.len() <= 2
+    }
+
+    /// Returns the (scaled) value of the edge from `from` to `to` as it is seen by
+    /// the solver, which always maximizes: the cost is negated when looking for
+    /// the shortest tour. Returns None when `to` is unreachable from `from`.
+    pub fn edge_value(&self, from: usize, to: usize) -> Option<isize> {
+        let value = self.values[from * self.instance.destinations.len() + to];
+        if value == UNREACHABLE { None } else { Some(value) }
+    }
+
+    /// Returns true iff the destination `to` can be reached from at least one of
+    /// the destinations in the `current` set of the given state
+    fn is_reachable(&self, state: &TspState, to: usize) -> bool {
+        state.current().iter().any(|from| self.edge_value(from as usize, to).is_some())
+    }
 }
 
 impl Problem for TspModel {
     type State = TspState;
 
     fn nb_variables(&self) -> usize {
-        self.instance.destinations.len()
+        self.members.len()
     }
 
     fn initial_state(&self) -> Self::State {
@@ -29,12 +356,7 @@ impl Problem for TspModel {
             must = must.insert(i as u8);
         }
 
-        TspState {
-            depth: 0,
-            current: Set64::singleton(0),
-            must_visit: must,
-            might_visit: Set64::empty(),
-        }
+        TspState(Position::At { depth: 0, current: self.depot as u8, must_visit: must, might_visit: Set64::empty() })
     }
 
     fn initial_value(&self) -> isize {
@@ -42,39 +364,46 @@ impl Problem for TspModel {
     }
 
     fn transition(&self, state: &Self::State, decision: ddo::Decision) -> Self::State {
-        TspState{
-            depth       : state.depth + 1,
-            current     : Set64::singleton(decision.value as u8),
-            must_visit  : state.must_visit.remove(decision.value as u8),
-            might_visit : state.might_visit.remove(decision.value as u8),
-        }
+        let cluster = self.cluster_of[decision.value as usize];
+        TspState(Position::At {
+            depth       : state.depth() as u8 + 1,
+            current     : decision.value as u8,
+            must_visit  : state.must_visit().remove(cluster),
+            might_visit : state.might_visit().remove(cluster),
+        })
     }
 
     fn transition_cost(&self, state: &Self::State, decision: ddo::Decision) -> isize {
+        // when the vehicle might be at several places, the most optimistic edge is used
         let to = decision.value as usize;
-        state.current.iter()
-            .map(|from| self.instance.distances[from as usize][to])
-            .map(|cost| (cost * 100_000.0).round() as isize)
-            .min()
-            .map(|v| -v) // it is a minimization problem
+        state.current().iter()
+            .filter_map(|from| self.edge_value(from as usize, to))
+            .max()
             .unwrap_or(0)
     }
 
     fn next_variable(&self, next_layer: &mut dyn Iterator<Item = &Self::State>)
         -> Option<ddo::Variable> {
         next_layer.next()
-            .map(|s| s.depth)
+            .map(|s| s.depth())
             .filter(|d| *d < self.nb_variables())
             .map(Variable)
     }
 
     fn for_each_in_domain(&self, var: ddo::Variable, state: &Self::State, f: &mut dyn ddo::DecisionCallback) {
-        let dest = state.must_visit.union(state.might_visit);
+        let dest = state.must_visit().union(state.might_visit());
         if dest.len() == 1 {
-            f.apply(Decision{variable: var, value: 0});
+            if self.is_reachable(state, self.depot) {
+                f.apply(Decision{variable: var, value: self.depot as isize});
+            }
         } else {
-            for to in dest.iter() {
-                if to == 0 {continue;}
+            let (first, second) = self.symmetry_pair;
+            let depot = self.cluster_of[self.depot];
+            for to in dest.iter().filter(|c| *c != depot).flat_map(|c| self.members[c as usize].iter()) {
+                if !self.is_reachable(state, to as usize) {continue;}
+                // a tour and its reverse only differ in whether the first destination
+                // of the pair comes before the second one
+                if self.break_symmetry && to == second && state.must_visit().contains(first) {continue;}
                 
                 f.apply(Decision{variable: var, value: to as isize});
             }
@@ -82,42 +411,189 @@ impl Problem for TspModel {
     }
 }
 
-pub struct TspRelax;
+/// Tells how the relaxation sets the cost of the arcs redirected to a merged node
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RelaxVariant {
+    /// The arcs keep their cost, unless the merge lost some of their value
+    #[default]
+    Plain,
+    /// The cost of an arc is also lowered by what the merge is sure to give back
+    /// on the next arc (see `TspRelax::relax`)
+    Corrected,
+}
+
+impl Display for RelaxVariant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RelaxVariant::Plain     => write!(f, "plain"),
+            RelaxVariant::Corrected => write!(f, "corrected"),
+        }
+    }
+}
+
+/// Tells where the vehicle might be in a merged state
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MergePolicy {
+    /// The vehicle might be at any place where it is in one of the merged states
+    #[default]
+    UnionCurrent,
+    /// The vehicle is at the place where it is in most of the merged states (the
+    /// representative). The value lost by the other states is added to the cost
+    /// of the arcs entering the merged node instead. The union is used when some
+    /// merged state reaches a destination the representative cannot reach.
+    RepresentativeCurrent,
+}
+
+impl Display for MergePolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MergePolicy::UnionCurrent          => write!(f, "union-current"),
+            MergePolicy::RepresentativeCurrent => write!(f, "representative-current"),
+        }
+    }
+}
+
+pub struct TspRelax<'a> {
+    pub model: &'a TspModel,
+    pub variant: RelaxVariant,
+    pub merge_policy: MergePolicy,
+}
+
+impl TspRelax<'_> {
+    /// Calls `f` with each destination which may be visited next from the given
+    /// state (those of `for_each_in_domain`, reachable or not)
+    fn for_each_next(&self, state: &TspState, mut f: impl FnMut(usize)) {
+        let left = state.must_visit().union(state.might_visit());
+        if left.len() == 1 {
+            f(self.model.depot);
+        } else {
+            let depot = self.model.cluster_of[self.model.depot];
+            for to in left.iter().filter(|c| *c != depot).flat_map(|c| self.model.members[c as usize].iter()) {
+                f(to as usize);
+            }
+        }
+    }
+
+    /// The most valuable edge from one of the places in `current` to `to`
+    fn best_edge(&self, current: Set64, to: usize) -> Option<isize> {
+        current.iter().filter_map(|from| self.model.edge_value(from as usize, to)).max()
+    }
+
+    /// Returns the largest loss, over the next decisions of the given exact state,
+    /// of leaving the merged state rather than the exact one. It is negative when
+    /// leaving the merged state is always more valuable, which is the case when
+    /// the vehicle might be at more places in the merged state: the value of the
+    /// edge leaving it is the best over all these places.
+    fn largest_loss(&self, exact: &TspState, merged: &TspState) -> isize {
+        if merged.current() == exact.current() {
+            return 0;
+        }
+        let mut loss = None;
+        self.for_each_next(exact, |to| {
+            if let Some(exact_edge) = self.best_edge(exact.current(), to) {
+                let merged_edge = self.best_edge(merged.current(), to).unwrap_or(exact_edge);
+                loss = loss.max(Some(exact_edge - merged_edge));
+            }
+        });
+        loss.unwrap_or(0)
+    }
+
+    /// The place where the vehicle is in most of the given states, provided that
+    /// it reaches every next destination of these states which they can reach
+    fn representative(&self, states: &[&TspState]) -> Option<u8> {
+        let mut counts = [0_usize; MAX_DESTINATIONS];
+        for state in states.iter() {
+            state.current().iter().for_each(|d| counts[d as usize] += 1);
+        }
+        let representative = (0..MAX_DESTINATIONS).max_by_key(|d| (counts[*d], std::cmp::Reverse(*d)))? as u8;
+        let mut valid = true;
+        for state in states.iter() {
+            self.for_each_next(state, |to| {
+                if self.model.is_reachable(state, to) && self.model.edge_value(representative as usize, to).is_none() {
+                    valid = false;
+                }
+            });
+        }
+        valid.then_some(representative)
+    }
+}
 
-impl Relaxation for TspRelax {
+impl Relaxation for TspRelax<'_> {
     type State = TspState;
 
+    /// The merged state requires the destinations required by all the states and
+    /// allows those allowed by any of them, hence each completion of a merged
+    /// state is a completion of the merged one. With the representative policy,
+    /// the first arc of such a completion may be worth less from the merged state,
+    /// which `relax` makes up for.
     fn merge(&self, states: &mut dyn Iterator<Item = &Self::State>) -> Self::State {
+        let states = states.collect::<Vec<_>>();
         let mut depth = 0;
         let mut curr  = Set64::empty();
         let mut must  = Set64::full();
         let mut might = Set64::empty();
 
-        for state in states {
-            depth = depth.max(state.depth);
-            curr  = curr.union(state.current);
-            must  = must.inter(state.must_visit);
-            might = might.union(state.must_visit); 
-            might = might.union(state.might_visit); 
+        for state in states.iter() {
+            depth = depth.max(state.depth() as u8);
+            curr  = curr.union(state.current());
+            must  = must.inter(state.must_visit());
+            might = might.union(state.must_visit()); 
+            might = might.union(state.might_visit()); 
         }
-
-        TspState {
-            depth,
-            current: curr,
-            must_visit: must,
-            might_visit: might.diff(must)
+        if self.merge_policy == MergePolicy::RepresentativeCurrent {
+            if let Some(representative) = self.representative(&states) {
+                curr = Set64::singleton(representative);
+            }
         }
+
+        TspState::new(depth, curr, must, might.diff(must))
     }
 
+    /// The cost of the arc is raised by the largest value lost on the next arc by
+    /// leaving the merged state rather than the exact one (`dest`), which only
+    /// happens with the representative policy. This makes the relaxation valid:
+    /// every completion of `dest` is a completion of the merged node, whose first
+    /// arc is worth at most that loss less from the merged node, and whose next
+    /// arcs are worth the same since the vehicle is then at a single place. Hence
+    /// the longest path through the merged node still bounds any path through
+    /// `dest`.
+    ///
+    /// The corrected variant also lowers the cost when the loss is negative (the
+    /// merged state gains at least that much on any next arc), which is valid by
+    /// the same argument and tightens the bound.
     fn relax(
         &self,
         _: &Self::State,
-        _: &Self::State,
-        _: &Self::State,
+        dest: &Self::State,
+        merged: &Self::State,
         _: Decision,
         cost: isize,
     ) -> isize {
-        cost
+        if self.variant == RelaxVariant::Plain && dest.current().diff(merged.current()) == Set64::empty() {
+            return cost;
+        }
+        let loss = self.largest_loss(dest, merged);
+        match self.variant {
+            RelaxVariant::Plain     => cost + loss.max(0),
+            RelaxVariant::Corrected => cost + loss,
+        }
+    }
+
+    /// Each destination that still needs to be visited is entered exactly once
+    /// in any completion of the state. When minimizing, this costs at least the
+    /// cheapest edge entering that destination. When maximizing, any destination
+    /// that might still be visited contributes at most its most expensive edge.
+    fn fast_upper_bound(&self, state: &Self::State) -> isize {
+        match self.model.sense {
+            ObjectiveSense::Min => -state.must_visit().iter()
+                .map(|v| self.model.min_incoming[v as usize])
+                .sum::<isize>(),
+            ObjectiveSense::Max => state.must_visit().union(state.might_visit()).iter()
+                .map(|v| self.model.max_incoming[v as usize])
+                .sum::<isize>(),
+        }
     }
 }
 
@@ -127,9 +603,74 @@ impl StateRanking for TspRanking {
     type State = TspState;
 
     fn compare(&self, a: &Self::State, b: &Self::State) -> std::cmp::Ordering {
-        a.must_visit.len().cmp(&b.must_visit.len())
-            .then_with(|| a.might_visit.len().cmp(&b.might_visit.len()))
-            .then_with(|| a.current.len().cmp(&b.current.len()))
+        a.must_visit().len().cmp(&b.must_visit().len())
+            .then_with(|| a.might_visit().len().cmp(&b.might_visit().len()))
+            .then_with(|| a.current().len().cmp(&b.current().len()))
+            // total order on the states: ties are broken the same way on every run
+            .then_with(|| a.depth().cmp(&b.depth()))
+            .then_with(|| a.current().iter().cmp(b.current().iter()))
+            .then_with(|| a.must_visit().iter().cmp(b.must_visit().iter()))
+            .then_with(|| a.might_visit().iter().cmp(b.might_visit().iter()))
             .reverse()
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use osrm_client::Location;
+    use smallbitset::Set64;
+
+    use crate::instance::{Instance, Rounding, Unit};
+
+    use super::{ObjectiveSense, Position, TspModel, TspState};
+
+    /// An instance of two destinations, `length` apart in both directions
+    fn pair(length: f32) -> Instance {
+        let destinations = (0..2).map(|i| Location { longitude: i as f32, latitude: 0.0 }).collect();
+        Instance::from_distances(destinations, vec![vec![0.0, length], vec![length, 0.0]].into(), Unit::Kilometers).unwrap()
+    }
+
+    #[test]
+    fn a_state_is_smaller_than_its_four_fields() {
+        assert!(std::mem::size_of::<TspState>() < 32);
+    }
+
+    #[test]
+    fn a_merged_state_at_a_single_place_equals_the_exact_state() {
+        let must = Set64::empty().insert(1).insert(4);
+        let exact = TspState(Position::At { depth: 2, current: 3, must_visit: must, might_visit: Set64::empty() });
+        assert_eq!(TspState::new(2, Set64::singleton(3), must, Set64::empty()), exact);
+    }
+
+    #[test]
+    fn a_merged_state_survives_a_round_trip_through_json() {
+        let current = Set64::empty().insert(2).insert(3);
+        let state = TspState::new(2, current, Set64::singleton(1), Set64::singleton(4));
+        assert_eq!(state.current(), current);
+        let json = serde_json::to_string(&state).unwrap();
+        assert_eq!(json, r#"{"depth":2,"current":[2,3],"must_visit":[1],"might_visit":[4]}"#);
+        assert_eq!(serde_json::from_str::<TspState>(&json).unwrap(), state);
+    }
+
+    #[test]
+    fn a_huge_distance_overflows_the_costs_of_the_solver() {
+        // 1e14 scaled by 1e5 over 2 legs is far beyond isize::MAX / 4 (about 2.3e18)
+        let error = TspModel::with_rounding(pair(1e14), ObjectiveSense::Min, Rounding::Nearest).unwrap_err();
+        assert!(error.starts_with("the distances are too large"));
+    }
+
+    #[test]
+    fn a_distance_just_under_the_limit_fits_in_the_costs_of_the_solver() {
+        // 1e13 scaled by 1e5 over 2 legs is 2e18, just under isize::MAX / 4
+        let model = TspModel::with_rounding(pair(1e13), ObjectiveSense::Min, Rounding::Nearest).unwrap();
+        assert_eq!(model.edge_value(0, 1), Some(-(1e13_f32 as f64 * 1e5) as isize));
+    }
+
+    #[test]
+    fn the_scaled_distances_are_rounded_as_asked() {
+        // 6e-6 scaled by 1e5 is 0.6
+        let cost = |rounding| TspModel::with_rounding(pair(6e-6), ObjectiveSense::Min, rounding).unwrap().edge_value(0, 1);
+        assert_eq!(cost(Rounding::Floor), Some(0));
+        assert_eq!(cost(Rounding::Nearest), Some(-1));
+        assert_eq!(cost(Rounding::Ceil), Some(-1));
+    }
+}
@@ -0,0 +1,364 @@
+//! This module defines the options of the solver. They are the single source of
+//! truth for the default settings: the flags of the solve command convert into
+//! these options and library users build them with `SolveOptions::builder()`.
+
+use std::time::Duration;
+
+use ddo::{Cutoff, FixedWidth, NoCutoff, Problem, TimeBudget, WidthHeuristic};
+use serde::Serialize;
+
+use crate::instance::{Rounding, Unit};
+
+use super::{FrontierKind, model::{MergePolicy, ObjectiveSense, RelaxVariant, TspModel, TspState}, width::{WidthPolicy, WidthSchedule, StateSizeWidth}};
+
+/// The settings of a solver run, which are needed to reproduce it
+#[derive(Debug, Clone, Serialize)]
+pub struct SolveOptions {
+    pub objective: ObjectiveSense,
+    pub width: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width_schedule: Option<String>,
+    pub width_policy: WidthPolicy,
+    pub min_width: usize,
+    pub max_width: usize,
+    /// The time limit of the search in seconds (0 means none), the preprocessing
+    /// is not bounded
+    pub timeout: u64,
+    pub deterministic: bool,
+    pub frontier: FrontierKind,
+    /// How the scaled distances are rounded to the integer costs of the solver
+    pub rounding: Rounding,
+    /// How the relaxation sets the cost of the arcs entering a merged node
+    pub relax: RelaxVariant,
+    /// Where the vehicle might be in a merged state
+    pub merge_policy: MergePolicy,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub departure: Option<String>,
+    /// Overrides the unit of the distance matrix declared by the instance
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unit: Option<Unit>,
+    /// The destination where the tours start and end, overriding the depot of
+    /// the instance
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start: Option<usize>,
+    /// The longest leg allowed in a tour (in the unit of the matrix)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_leg: Option<f64>,
+    /// The file giving the cluster of each destination when exactly one
+    /// destination per cluster is visited (generalized TSP)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gtsp: Option<String>,
+    /// Drops the destinations of the instance file which have no distances
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub truncate_to_matrix: bool,
+    /// The settings which were adjusted to the instance (see `adapt`)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub adjustments: Vec<String>,
+}
+
+/// Builds validated solve options, starting from the default settings
+#[derive(Debug, Clone)]
+pub struct SolveOptionsBuilder {
+    options: SolveOptions,
+}
+
+impl Default for SolveOptions {
+    fn default() -> Self {
+        SolveOptions {
+            objective: ObjectiveSense::Min,
+            width: 100,
+            width_schedule: None,
+            width_policy: WidthPolicy::Fixed,
+            min_width: 1,
+            max_width: 10_000,
+            timeout: 60,
+            deterministic: false,
+            frontier: FrontierKind::Simple,
+            rounding: Rounding::Nearest,
+            relax: RelaxVariant::Plain,
+            merge_policy: MergePolicy::UnionCurrent,
+            departure: None,
+            unit: None,
+            start: None,
+            gtsp: None,
+            max_leg: None,
+            truncate_to_matrix: false,
+            adjustments: vec![],
+        }
+    }
+}
+
+impl SolveOptions {
+    /// Starts from the default options
+    pub fn builder() -> SolveOptionsBuilder {
+        SolveOptionsBuilder { options: SolveOptions::default() }
+    }
+
+    /// The state-size width heuristic, when it is selected and not overridden
+    /// by a width schedule
+    pub(crate) fn state_size_width(&self) -> Option<StateSizeWidth> {
+        if self.width_schedule.is_some() || self.width_policy != WidthPolicy::StateSize {
+            return None;
+        }
+        Some(StateSizeWidth{per_destination: self.width, min: self.min_width, max: self.max_width})
+    }
+
+    /// The maximum width of the decision diagrams compiled at the root of a
+    /// problem with `nb_variables` destinations
+    pub(super) fn root_width(&self, nb_variables: usize) -> usize {
+        if let Some(spec) = self.width_schedule.as_ref() {
+            if let Ok(schedule) = WidthSchedule::parse(spec) {
+                return schedule.width_at(0);
+            }
+        }
+        match self.state_size_width() {
+            Some(policy) => policy.width_of(nb_variables),
+            None => self.width,
+        }
+    }
+
+    /// Checks the settings against the given problem, once it is known: the
+    /// settings which are bad for it are adjusted when it is safe (each change is
+    /// warned about and recorded in `adjustments`), otherwise they are only
+    /// warned about. A diagram narrower than the largest domain cannot even hold
+    /// all the children of the root, hence the width is raised to that domain.
+    pub(super) fn adapt(&mut self, problem: &TspModel) {
+        let domain = problem.largest_domain().max(1);
+        if self.width_schedule.is_some() {
+            if self.root_width(problem.nb_variables()) < domain {
+                eprintln!("warning: the width schedule starts below the largest domain ({domain}), the diagrams will be very coarse");
+            }
+        } else if self.width_policy == WidthPolicy::Fixed && self.width < domain {
+            self.adjustments.push(format!("the width {} is smaller than the largest domain ({domain}), it is raised to {domain}", self.width));
+            self.width = domain;
+        } else if self.width_policy == WidthPolicy::StateSize && self.max_width < domain {
+            self.adjustments.push(format!("the largest width {} is smaller than the largest domain ({domain}), it is raised to {domain}", self.max_width));
+            self.max_width = domain;
+        }
+        for change in self.adjustments.iter() {
+            eprintln!("warning: {change}");
+        }
+    }
+
+    /// The cutoff enforcing the time limit of the search. The budget starts
+    /// running when this is called.
+    pub(super) fn cutoff(&self) -> Box<dyn Cutoff + Send + Sync> {
+        if self.timeout == 0 {
+            Box::new(NoCutoff)
+        } else {
+            Box::new(TimeBudget::new(Duration::from_secs(self.timeout)))
+        }
+    }
+
+    /// The width heuristic bounding the diagrams compiled for a problem with
+    /// `nb_variables` destinations
+    pub(super) fn width_heuristic(&self, nb_variables: usize, quiet: bool) -> Result<Box<dyn WidthHeuristic<TspState> + Send + Sync>, String> {
+        if let Some(spec) = self.width_schedule.as_ref() {
+            let schedule = WidthSchedule::parse(spec)?;
+            schedule.check(nb_variables)?;
+            if !schedule.covers(nb_variables) {
+                eprintln!("warning: the width schedule does not cover all depths, its last value is used for the deeper layers");
+            }
+            if !quiet {
+                eprintln!("width schedule {schedule}");
+            }
+            Ok(Box::new(schedule))
+        } else if let Some(policy) = self.state_size_width() {
+            Ok(Box::new(policy))
+        } else {
+            Ok(Box::new(FixedWidth(self.width)))
+        }
+    }
+}
+
+impl SolveOptionsBuilder {
+    pub fn objective(mut self, objective: ObjectiveSense) -> Self {
+        self.options.objective = objective;
+        self
+    }
+    pub fn width(mut self, width: usize) -> Self {
+        self.options.width = width;
+        self
+    }
+    pub fn width_schedule(mut self, spec: Option<String>) -> Self {
+        self.options.width_schedule = spec;
+        self
+    }
+    pub fn width_policy(mut self, policy: WidthPolicy) -> Self {
+        self.options.width_policy = policy;
+        self
+    }
+    pub fn min_width(mut self, width: usize) -> Self {
+        self.options.min_width = width;
+        self
+    }
+    pub fn max_width(mut self, width: usize) -> Self {
+        self.options.max_width = width;
+        self
+    }
+    pub fn timeout(mut self, seconds: u64) -> Self {
+        self.options.timeout = seconds;
+        self
+    }
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.options.deterministic = deterministic;
+        self
+    }
+    pub fn frontier(mut self, frontier: FrontierKind) -> Self {
+        self.options.frontier = frontier;
+        self
+    }
+    pub fn rounding(mut self, rounding: Rounding) -> Self {
+        self.options.rounding = rounding;
+        self
+    }
+    pub fn relax(mut self, variant: RelaxVariant) -> Self {
+        self.options.relax = variant;
+        self
+    }
+    pub fn merge_policy(mut self, policy: MergePolicy) -> Self {
+        self.options.merge_policy = policy;
+        self
+    }
+    pub fn departure(mut self, departure: Option<String>) -> Self {
+        self.options.departure = departure;
+        self
+    }
+    pub fn unit(mut self, unit: Option<Unit>) -> Self {
+        self.options.unit = unit;
+        self
+    }
+    pub fn start(mut self, start: Option<usize>) -> Self {
+        self.options.start = start;
+        self
+    }
+    pub fn max_leg(mut self, max_leg: Option<f64>) -> Self {
+        self.options.max_leg = max_leg;
+        self
+    }
+    pub fn gtsp(mut self, membership: Option<String>) -> Self {
+        self.options.gtsp = membership;
+        self
+    }
+    pub fn truncate_to_matrix(mut self, truncate: bool) -> Self {
+        self.options.truncate_to_matrix = truncate;
+        self
+    }
+
+    /// Checks the consistency of the options and returns them
+    pub fn build(self) -> Result<SolveOptions, String> {
+        let options = self.options;
+        if options.width == 0 {
+            return Err("the width must be at least 1".to_string());
+        }
+        if options.min_width == 0 || options.min_width > options.max_width {
+            return Err(format!("the width bounds [{}, {}] are invalid: they must satisfy 1 <= min <= max",
+                options.min_width, options.max_width));
+        }
+        if let Some(spec) = options.width_schedule.as_ref() {
+            WidthSchedule::parse(spec)?.check(1)?;
+        }
+        if options.max_leg.is_some_and(|max| max.is_nan() || max < 0.0) {
+            return Err("the maximum leg length must be a non negative number".to_string());
+        }
+        Ok(options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use osrm_client::Location;
+
+    use crate::instance::{Instance, Unit};
+    use crate::resolution::{exact::held_karp, solve_instance};
+
+    use super::{ObjectiveSense, SolveOptions, TspModel, WidthPolicy};
+
+    /// An asymmetric instance of `n` destinations with scattered distances
+    fn instance(n: usize) -> Instance {
+        let destinations = (0..n).map(|i| Location { longitude: i as f32, latitude: 0.0 }).collect();
+        let distances = (0..n)
+            .map(|i| (0..n).map(|j| if i == j { 0.0 } else { ((7 * i + 13 * j) % 17 + 1) as f32 }).collect())
+            .collect();
+        Instance::from_distances(destinations, distances, Unit::Kilometers).unwrap()
+    }
+
+    #[test]
+    fn a_run_without_timeout_proves_optimality() {
+        let options = SolveOptions::builder().timeout(0).build().unwrap();
+        assert!(!options.cutoff().must_stop());
+
+        let instance = instance(10);
+        let model = TspModel::new(instance.clone(), ObjectiveSense::Min).unwrap();
+        let (optimum, _) = held_karp(&model).unwrap();
+        let report = solve_instance("scattered", instance, &options).unwrap();
+        assert!(report.is_exact);
+        assert_eq!(report.value, Some(ObjectiveSense::Min.tour_length(optimum)));
+    }
+
+    #[test]
+    fn a_width_below_the_largest_domain_is_raised() {
+        let model = TspModel::new(instance(10), ObjectiveSense::Min).unwrap();
+        let domain = model.largest_domain();
+        let mut options = SolveOptions::builder().width(2).build().unwrap();
+        options.adapt(&model);
+        assert_eq!(options.width, domain);
+        assert_eq!(options.adjustments, vec![format!("the width 2 is smaller than the largest domain ({domain}), it is raised to {domain}")]);
+
+        // a wide enough width is left as it is
+        let mut options = SolveOptions::builder().width(domain).build().unwrap();
+        options.adapt(&model);
+        assert_eq!(options.width, domain);
+        assert!(options.adjustments.is_empty());
+    }
+
+    #[test]
+    fn a_width_of_0_is_rejected() {
+        assert_eq!(SolveOptions::builder().width(0).build().unwrap_err(), "the width must be at least 1");
+    }
+
+    #[test]
+    fn invalid_width_bounds_are_rejected() {
+        let error = SolveOptions::builder().min_width(0).build().unwrap_err();
+        assert!(error.starts_with("the width bounds [0, "));
+        let error = SolveOptions::builder().min_width(20).max_width(10).build().unwrap_err();
+        assert_eq!(error, "the width bounds [20, 10] are invalid: they must satisfy 1 <= min <= max");
+    }
+
+    #[test]
+    fn an_invalid_width_schedule_is_rejected() {
+        assert!(SolveOptions::builder().width_schedule(Some("10 +* d".to_string())).build().is_err());
+        assert!(SolveOptions::builder().width_schedule(Some("10 * (d + 1)".to_string())).build().is_ok());
+        assert!(SolveOptions::builder().width_schedule(Some("10 / d".to_string())).build().is_err());
+    }
+
+    #[test]
+    fn a_maximum_leg_which_is_not_a_non_negative_number_is_rejected() {
+        for max_leg in [f64::NAN, -1.0] {
+            let error = SolveOptions::builder().max_leg(Some(max_leg)).build().unwrap_err();
+            assert_eq!(error, "the maximum leg length must be a non negative number");
+        }
+        assert!(SolveOptions::builder().max_leg(Some(0.0)).build().is_ok());
+    }
+
+    #[test]
+    fn a_largest_state_size_width_below_the_largest_domain_is_raised() {
+        let model = TspModel::new(instance(10), ObjectiveSense::Min).unwrap();
+        let domain = model.largest_domain();
+        let mut options = SolveOptions::builder().width_policy(WidthPolicy::StateSize).min_width(1).max_width(3).build().unwrap();
+        options.adapt(&model);
+        assert_eq!(options.max_width, domain);
+        assert_eq!(options.adjustments, vec![format!("the largest width 3 is smaller than the largest domain ({domain}), it is raised to {domain}")]);
+    }
+
+    #[test]
+    fn a_narrow_width_schedule_is_kept() {
+        // the schedule is only warned about: raising it would not follow its shape
+        let model = TspModel::new(instance(10), ObjectiveSense::Min).unwrap();
+        let mut options = SolveOptions::builder().width(2).width_schedule(Some("2+d".to_string())).build().unwrap();
+        options.adapt(&model);
+        assert_eq!(options.width_schedule.as_deref(), Some("2+d"));
+        assert_eq!(options.width, 2);
+        assert!(options.adjustments.is_empty());
+    }
+}
@@ -0,0 +1,188 @@
+//! This module gathers a pool of good tours which are structurally different
+//! from one another (`--pool-size`), to give the dispatchers some options
+//! besides the best tour.
+//!
+//! The difference between two tours is the share of the legs of one which the
+//! other does not use (the legs being undirected when the matrix is symmetric).
+//! Each candidate tour is offered to the pool: it is inserted when it differs
+//! enough from all the members and the pool is not full, it replaces the
+//! members too close to it when it is better than all of them, or the worst
+//! member when the pool is full and it is better, and it is discarded otherwise.
+//!
+//! The solver does not notify the tours it finds during its search, so the
+//! candidates are the best tours of the runs and of additional searches. Each
+//! additional search forbids a share of the legs of every member (at least the
+//! minimum difference, and other legs at each attempt), which forces it towards
+//! a different tour. The additional searches share what the runs left of the
+//! timeout.
+
+use std::time::{Duration, Instant};
+
+use ddo::{Cutoff, NoCutoff, TimeBudget};
+use serde::Serialize;
+
+use super::{Prepared, SolveReport, options::SolveOptions, prepare_instance, search};
+
+/// A member of the pool
+#[derive(Debug, Clone, Serialize)]
+pub struct PoolMember {
+    /// The length of the tour (in the unit of the report)
+    pub value: Option<f64>,
+    /// The destinations in visit order, ending with the return to the depot
+    pub tour: Vec<usize>,
+    /// The value of the tour for the solver (the larger, the better)
+    #[serde(skip)]
+    score: isize,
+    /// The legs of the tour, sorted
+    #[serde(skip)]
+    legs: Vec<(usize, usize)>,
+}
+
+/// The tours of the pool, from the best to the worst
+#[derive(Debug, Clone, Serialize)]
+pub struct SolutionPool {
+    /// The minimum difference between two members
+    pub min_diff: f64,
+    pub members: Vec<PoolMember>,
+    /// The difference between each pair of members
+    pub differences: Vec<Vec<f64>>,
+    /// The number of candidate tours offered to the pool
+    pub nb_candidates: usize,
+}
+
+impl SolutionPool {
+    fn new(min_diff: f64) -> Self {
+        SolutionPool { min_diff, members: vec![], differences: vec![], nb_candidates: 0 }
+    }
+
+    /// Offers the given tour to the pool, which keeps at most `size` members.
+    /// Returns true iff it entered the pool.
+    fn offer(&mut self, size: usize, symmetric: bool, tour: &[usize], score: isize, value: Option<f64>) -> bool {
+        self.nb_candidates += 1;
+        let candidate = PoolMember { value, tour: tour.to_vec(), score, legs: legs(tour, symmetric) };
+        let close = self.members.iter()
+            .enumerate()
+            .filter(|(_, member)| difference(&candidate.legs, &member.legs) < self.min_diff)
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        if !close.is_empty() {
+            if close.iter().any(|i| self.members[*i].score >= score) {
+                return false;
+            }
+            close.iter().rev().for_each(|i| { self.members.remove(*i); });
+        } else if self.members.len() >= size {
+            if self.members.last().is_some_and(|worst| worst.score >= score) {
+                return false;
+            }
+            self.members.pop();
+        }
+        let position = self.members.partition_point(|member| member.score >= score);
+        self.members.insert(position, candidate);
+        true
+    }
+
+    /// Computes the differences between the members once the pool is complete
+    fn finish(mut self) -> Self {
+        self.differences = self.members.iter()
+            .map(|a| self.members.iter().map(|b| difference(&a.legs, &b.legs)).collect())
+            .collect();
+        self
+    }
+}
+
+/// The legs of the given closed tour, sorted (each one from its smaller end
+/// when the matrix is symmetric)
+fn legs(tour: &[usize], symmetric: bool) -> Vec<(usize, usize)> {
+    let mut legs = tour.iter().zip(tour.iter().cycle().skip(1))
+        .filter(|(from, to)| from != to)
+        .map(|(&from, &to)| if symmetric { (from.min(to), from.max(to)) } else { (from, to) })
+        .collect::<Vec<_>>();
+    legs.sort_unstable();
+    legs
+}
+
+/// The share of the legs of one tour which the other does not use, the legs of
+/// both being sorted
+fn difference(a: &[(usize, usize)], b: &[(usize, usize)]) -> f64 {
+    let (mut i, mut j, mut common) = (0, 0, 0);
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            std::cmp::Ordering::Less    => i += 1,
+            std::cmp::Ordering::Greater => j += 1,
+            std::cmp::Ordering::Equal   => {
+                common += 1;
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    let len = a.len().max(b.len());
+    if len == 0 { 0.0 } else { 1.0 - common as f64 / len as f64 }
+}
+
+/// Gathers a pool of at most `size` tours differing by at least `min_diff` from
+/// one another, starting from the best tours of the given runs. Up to twice as
+/// many additional searches as members are run until the pool is full. With a
+/// timeout, each search gets an even share of the time the longest run left,
+/// and the searches stop once that time is spent.
+pub(super) fn gather(instance: &str, options: &SolveOptions, prepared: &Prepared, runs: &[(SolveReport, Option<isize>)], size: usize, min_diff: f64, threads: Option<usize>) -> Result<SolutionPool, String> {
+    let solved = &prepared.problem.instance;
+    let n = solved.destinations.len();
+    let symmetric = (0..n).all(|i| (0..i).all(|j| solved.distances[i][j] == solved.distances[j][i]));
+    let mut pool = SolutionPool::new(min_diff);
+    for (report, score) in runs.iter() {
+        if let (Some(tour), Some(score)) = (report.tour.as_ref(), score) {
+            pool.offer(size, symmetric, tour, *score, report.value);
+        }
+    }
+
+    // forbidding one leg out of `step` of a member keeps the next tours at least
+    // `min_diff` away from it
+    let step = ((1.0 / min_diff).floor() as usize).max(1);
+    // the departure, the start and the longest leg are already applied
+    let options = SolveOptions { departure: None, start: None, max_leg: None, ..options.clone() };
+    let deadline = (options.timeout > 0).then(|| {
+        let spent = runs.iter().map(|(report, _)| report.time).fold(0.0, f64::max);
+        Instant::now() + Duration::from_secs_f64((options.timeout as f64 - spent).max(0.0))
+    });
+    let attempts = 2 * size;
+    for attempt in 0..attempts {
+        if pool.members.is_empty() || pool.members.len() >= size {
+            break;
+        }
+        let cutoff: Box<dyn Cutoff + Send + Sync> = match deadline {
+            None => Box::new(NoCutoff),
+            Some(deadline) => {
+                let left = deadline.saturating_duration_since(Instant::now());
+                if left.is_zero() {
+                    eprintln!("warning: the budget is spent, the pool holds {} of the {size} tours asked for", pool.members.len());
+                    break;
+                }
+                Box::new(TimeBudget::new(left / (attempts - attempt) as u32))
+            }
+        };
+        let mut restricted = solved.clone();
+        for member in pool.members.iter() {
+            let legs = member.tour.iter().zip(member.tour.iter().cycle().skip(1))
+                .filter(|(from, to)| from != to)
+                .enumerate()
+                .filter(|(i, _)| (i + attempt) % step == 0)
+                .map(|(_, (&from, &to))| (from, to));
+            for (from, to) in legs {
+                restricted.forbidden_arcs.push((from, to));
+                if symmetric {
+                    restricted.forbidden_arcs.push((to, from));
+                }
+            }
+        }
+        // no tour avoids these legs
+        let Ok(restricted) = prepare_instance(restricted, &options, Instant::now()) else {
+            continue;
+        };
+        let (report, score) = search(instance, &options, &restricted, cutoff.as_ref(), true, threads)?;
+        if let (Some(tour), Some(score)) = (report.tour.as_ref(), score) {
+            pool.offer(size, symmetric, tour, score, report.value);
+        }
+    }
+    Ok(pool.finish())
+}
@@ -0,0 +1,119 @@
+//! This module runs a portfolio of solver configurations concurrently on the
+//! same instance: different instances favour different settings, and the
+//! portfolio keeps the best tour found by any of them. A race runs any number of
+//! contenders in the same way, to take advantage of the variance of the parallel
+//! solver.
+//!
+//! The solver (ddo 1.0) keeps its incumbent to itself while it runs, so the
+//! configurations cannot tighten the pruning bound of one another: they only
+//! share the time budget, and all of them stop as soon as one proves optimality.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use ddo::Cutoff;
+use tracing::{Span, info, info_span};
+
+use super::{FrontierKind, Prepared, options::SolveOptions, report::{Contender, SolveReport}, search, width::WidthPolicy};
+
+/// The largest number of configurations run by a portfolio
+const MAX_CONFIGURATIONS: usize = 4;
+
+/// Returns the configurations of a portfolio derived from the given options,
+/// along with their names. The first one uses the options as given.
+fn configurations(options: &SolveOptions) -> Vec<(&'static str, SolveOptions)> {
+    let state_size = SolveOptions { width_policy: WidthPolicy::StateSize, width_schedule: None, ..options.clone() };
+    let wide = SolveOptions { width: options.width.saturating_mul(10), width_policy: WidthPolicy::Fixed, width_schedule: None, ..options.clone() };
+    let no_dup = SolveOptions { frontier: FrontierKind::NoDup, ..options.clone() };
+    vec![("given", options.clone()), ("state-size", state_size), ("wide", wide), ("no-dup", no_dup)]
+}
+
+/// Solves the prepared problem with 2 to 4 configurations at once, the available
+/// threads being split among them. Returns the report of the configuration which
+/// found the best tour, completed with the names of the configurations which
+/// found it and proved it optimal, along with the best value found.
+pub(super) fn solve_portfolio(instance: &str, options: &SolveOptions, prepared: &Prepared) -> Result<(SolveReport, Option<isize>), String> {
+    let mut configurations = configurations(options);
+    configurations.truncate(cores().clamp(2, MAX_CONFIGURATIONS));
+    run(instance, options, prepared, &configurations)
+}
+
+/// Races the given number of independent contenders on the prepared problem,
+/// the available threads being split among them. The contenders cycle through
+/// the configurations of the portfolio. Returns like `solve_portfolio`.
+pub(super) fn solve_race(instance: &str, options: &SolveOptions, prepared: &Prepared, contenders: usize) -> Result<(SolveReport, Option<isize>), String> {
+    if contenders == 0 {
+        return Err("a race needs at least one contender".to_string());
+    }
+    let configurations = configurations(options);
+    let contenders = (0..contenders)
+        .map(|i| configurations[i % configurations.len()].clone())
+        .collect::<Vec<_>>();
+    run(instance, options, prepared, &contenders)
+}
+
+/// The number of threads available to the solver
+pub(super) fn cores() -> usize {
+    std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+}
+
+/// Runs the given configurations concurrently until one of them completes its
+/// search or the time budget is exhausted
+fn run(instance: &str, options: &SolveOptions, prepared: &Prepared, configurations: &[(&'static str, SolveOptions)]) -> Result<(SolveReport, Option<isize>), String> {
+    let threads = (cores() / configurations.len()).max(1);
+    let budget = options.cutoff();
+    let proved = AtomicBool::new(false);
+    // the spans of the configurations are nested in that of the caller, although
+    // they run on threads of their own
+    let parent = Span::current();
+    let runs = std::thread::scope(|scope| {
+        let handles = configurations.iter()
+            .map(|(name, configuration)| {
+                let cutoff = Shared { budget: budget.as_ref(), proved: &proved };
+                let (proved, parent) = (&proved, &parent);
+                scope.spawn(move || {
+                    let _span = info_span!(parent: parent, "configuration", name).entered();
+                    let run = search(instance, configuration, prepared, &cutoff, true, Some(threads));
+                    if run.as_ref().is_ok_and(|(report, _)| report.is_exact) {
+                        info!("optimality proved");
+                        proved.store(true, Ordering::Relaxed);
+                    }
+                    run
+                })
+            })
+            .collect::<Vec<_>>();
+        handles.into_iter()
+            .map(|handle| handle.join().unwrap_or_else(|_| Err("a configuration of the portfolio crashed".to_string())))
+            .collect::<Result<Vec<_>, _>>()
+    })?;
+
+    // the configurations stopped by a proof found no better tour than its own
+    let best = (0..runs.len())
+        .filter(|i| runs[*i].1.is_some())
+        .max_by_key(|i| (runs[*i].1, runs[*i].0.is_exact))
+        .unwrap_or(0);
+    let proof = runs.iter().position(|(report, _)| report.is_exact);
+    let (mut report, value) = runs[best].clone();
+    report.found_by = value.map(|_| configurations[best].0.to_string());
+    report.proved_by = proof.map(|i| configurations[i].0.to_string());
+    if let Some(i) = proof {
+        report.is_exact = true;
+        report.bound = runs[i].0.bound;
+    }
+    report.contenders = Some(runs.iter().zip(configurations)
+        .map(|((run, _), (name, _))| Contender { configuration: name.to_string(), value: run.value, is_exact: run.is_exact, time: run.time })
+        .collect());
+    Ok((report, value))
+}
+
+/// The cutoff of a configuration of the portfolio: it stops when the time budget
+/// is exhausted or when another configuration has proved optimality
+struct Shared<'a> {
+    budget: &'a (dyn Cutoff + Send + Sync),
+    proved: &'a AtomicBool,
+}
+
+impl Cutoff for Shared<'_> {
+    fn must_stop(&self) -> bool {
+        self.proved.load(Ordering::Relaxed) || self.budget.must_stop()
+    }
+}
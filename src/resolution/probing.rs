@@ -0,0 +1,103 @@
+//! This module chooses the width of the decision diagrams before a solver run
+//! (`--width auto`). Short probes search the instance with a few candidate
+//! widths for the same time, and the width whose probe left the smallest gap
+//! between its best tour and its bound is kept: a narrow diagram explores more
+//! nodes per second while a wide one gives tighter bounds, and which of them
+//! wins depends on the instance.
+//!
+//! The probes are ranked by their gap rather than by the bound they proved per
+//! second. They all run for the same time, hence the gap already accounts for
+//! the speed of each width, and it also rewards the widths which find good
+//! tours: a bound alone says nothing of the tour the run will end with.
+
+use std::time::{Duration, Instant};
+
+use ddo::TimeBudget;
+use serde::Serialize;
+
+use super::{Prepared, SolveReport, options::SolveOptions, search, width::WidthPolicy};
+
+/// The widths tried by the probes
+const PROBE_WIDTHS: [usize; 3] = [100, 500, 2000];
+/// The longest time given to each probe in seconds
+const PROBE_TIME: f64 = 5.0;
+/// The largest share of a limited budget which the probes may spend altogether
+const PROBE_SHARE: f64 = 0.25;
+/// The shortest probe worth running in seconds: with a smaller budget the
+/// default width is kept without probing
+const MIN_PROBE_TIME: f64 = 0.5;
+
+/// The outcome of the probe of one width
+#[derive(Debug, Clone, Serialize)]
+pub struct Probe {
+    pub width: usize,
+    /// The length of the best tour found by the probe (in the unit of the report)
+    pub value: Option<f64>,
+    /// The best bound proved by the probe (in the unit of the report)
+    pub bound: Option<f64>,
+    /// True iff the probe completed its search
+    pub is_exact: bool,
+    /// The wall clock time of the probe in seconds
+    pub time: f64,
+}
+
+impl Probe {
+    /// The gap left between the best tour and the bound (none when no tour was
+    /// found)
+    fn gap(&self) -> Option<f64> {
+        match (self.value, self.bound) {
+            _ if self.is_exact        => Some(0.0),
+            (Some(value), Some(bound)) => Some((value - bound).abs()),
+            _                          => None,
+        }
+    }
+}
+
+/// The outcome of the probing
+pub(super) struct Probing {
+    /// The options of the actual run: the chosen width, and the budget left once
+    /// the probes are over
+    pub options: SolveOptions,
+    pub probes: Vec<Probe>,
+    /// The run of the probe which completed its search, if any: there is no
+    /// need to search again
+    pub solved: Option<(SolveReport, Option<isize>)>,
+}
+
+/// Probes the candidate widths on the prepared problem and returns the options
+/// of the actual run (a probe which completes its search ends the probing).
+/// With a limited budget, the probes spend a quarter of it at most, and none is
+/// run when that leaves them less than half a second each.
+pub(super) fn probe_width(instance: &str, options: &SolveOptions, prepared: &Prepared, threads: Option<usize>) -> Result<Probing, String> {
+    let time = if options.timeout == 0 {
+        PROBE_TIME
+    } else {
+        (options.timeout as f64 * PROBE_SHARE / PROBE_WIDTHS.len() as f64).min(PROBE_TIME)
+    };
+    let defaults = SolveOptions { width: PROBE_WIDTHS[0], width_policy: WidthPolicy::Fixed, width_schedule: None, ..options.clone() };
+    if time < MIN_PROBE_TIME {
+        eprintln!("warning: the budget is too small to probe the widths, the width {} is used", PROBE_WIDTHS[0]);
+        return Ok(Probing { options: defaults, probes: vec![], solved: None });
+    }
+
+    let start = Instant::now();
+    let mut probes: Vec<Probe> = vec![];
+    for width in PROBE_WIDTHS {
+        let probe = SolveOptions { width, ..defaults.clone() };
+        let cutoff = TimeBudget::new(Duration::from_secs_f64(time));
+        let (report, value) = search(instance, &probe, prepared, &cutoff, true, threads)?;
+        probes.push(Probe { width, value: report.value, bound: report.bound, is_exact: report.is_exact, time: report.time });
+        if report.is_exact {
+            return Ok(Probing { options: probe, probes, solved: Some((report, value)) });
+        }
+    }
+
+    // the narrowest width wins the ties
+    let best = probes.iter()
+        .filter_map(|probe| probe.gap().map(|gap| (gap, probe.width)))
+        .min_by(|a, b| a.0.total_cmp(&b.0))
+        .map_or(PROBE_WIDTHS[0], |(_, width)| width);
+    let spent = start.elapsed().as_secs().saturating_add(1);
+    let timeout = if options.timeout == 0 { 0 } else { options.timeout.saturating_sub(spent).max(1) };
+    Ok(Probing { options: SolveOptions { width: best, timeout, ..defaults }, probes, solved: None })
+}
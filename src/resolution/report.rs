@@ -0,0 +1,269 @@
+//! This module defines the report which summarizes the outcome of a solver run.
+
+use std::fmt::{self, Display, Formatter};
+
+use serde::Serialize;
+
+use super::{model::ObjectiveSense, options::SolveOptions, pool::SolutionPool, probing::Probe};
+
+/// The outcome of a solver run
+#[derive(Debug, Clone, Serialize)]
+pub struct SolveReport {
+    /// The path to the instance that was solved
+    pub instance: String,
+    /// The digest of the instance that was solved
+    pub digest: String,
+    /// Whether the shortest or the longest tour was looked for
+    pub objective: ObjectiveSense,
+    /// The settings of the solver
+    pub settings: SolveOptions,
+    /// True iff only one orientation of the tours was explored (symmetric instances)
+    pub symmetry_breaking: bool,
+    /// True iff the search was completed (the tour is then proved optimal)
+    pub is_exact: bool,
+    /// The length of the best tour (expressed in `unit`) if any was found
+    pub value: Option<f64>,
+    /// The best bound on the length of the optimal tour (expressed in `unit`)
+    /// proved by the solver if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bound: Option<f64>,
+    /// The unit in which `value` and `bound` are expressed
+    pub unit: String,
+    /// The distance of the best tour (expressed in `distance_unit`) when the
+    /// instance has distances, whichever of them or the travel times was optimized
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub distance: Option<f64>,
+    /// The unit in which `distance` is expressed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub distance_unit: Option<String>,
+    /// The travel time of the best tour (in hours) when the instance has durations
+    /// or when travel times were optimized
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration: Option<f64>,
+    /// The sequence of destinations visited by the best tour if any was found
+    pub tour: Option<Vec<usize>>,
+    /// The extra fields of the destinations of the tour, in visit order (when the
+    /// instance has some)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extras: Option<Vec<serde_json::Value>>,
+    /// The cluster of each destination of the tour (generalized TSP only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clusters: Option<Vec<String>>,
+    /// The portfolio configuration which found the best tour (portfolio runs only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub found_by: Option<String>,
+    /// The portfolio configuration which completed its search and thus proved
+    /// the result (portfolio runs only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proved_by: Option<String>,
+    /// The outcome of each configuration of a portfolio or contender of a race
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub contenders: Option<Vec<Contender>>,
+    /// The outcome of the probes which chose the width (`--width auto` only, empty
+    /// when the budget was too small to probe)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub probes: Option<Vec<Probe>>,
+    /// The pool of good tours which differ from one another (`--pool-size` only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pool: Option<SolutionPool>,
+    /// The number of nodes explored by the search, as counted by its cutoff (see
+    /// `TracedCutoff`): the same deterministic run always explores as many
+    pub explored_nodes: usize,
+    /// The number of open subproblems left in the frontier at the end of the
+    /// run (0 when the search was completed)
+    pub frontier_size: usize,
+    /// The peak resident set size of the process during the search in bytes,
+    /// when the platform tells it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub peak_rss: Option<u64>,
+    /// The resident set size of the process at the end of the search in bytes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub final_rss: Option<u64>,
+    /// The wall clock time spent loading and preprocessing the instance in seconds
+    pub preprocessing: f64,
+    /// The wall clock time of the search in seconds
+    pub time: f64,
+    /// The wall clock time of each phase of the run
+    pub phases: Phases,
+}
+
+/// The wall clock time of the phases of a run in seconds (they are also the
+/// spans traced with `--trace-format`)
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Phases {
+    /// Reading the instance file (0 when the instance was given in memory)
+    pub load: f64,
+    /// Building the model to solve from the instance
+    pub preprocess: f64,
+    /// The branch and bound
+    pub search: f64,
+}
+
+/// The outcome of one of the configurations run concurrently by a portfolio or
+/// a race
+#[derive(Debug, Clone, Serialize)]
+pub struct Contender {
+    /// The name of the configuration
+    pub configuration: String,
+    /// The length of the best tour it found (expressed in the unit of the report)
+    pub value: Option<f64>,
+    /// True iff it completed its search
+    pub is_exact: bool,
+    /// The wall clock time of its search in seconds
+    pub time: f64,
+}
+
+/// The outcome of several runs of the solver with the same settings. The runs
+/// differ by the order in which the threads explore the nodes, not by a seed:
+/// the solver draws no random number. The solver (ddo 1.0) neither counts the
+/// nodes it explores nor tells when it finds its incumbent, hence there are no
+/// statistics of the explored nodes nor of the time to the best tour.
+#[derive(Debug, Clone, Serialize)]
+pub struct RepeatedReport {
+    /// The statistics of the length of the best tour found by each run (over the
+    /// runs which found a tour)
+    pub value: Option<Aggregate>,
+    /// The statistics of the time taken by each run
+    pub time: Aggregate,
+    /// The number of runs which completed their search
+    pub nb_exact: usize,
+    /// The index of the run which found the best tour overall
+    pub best: usize,
+    /// The reports of all the runs
+    pub runs: Vec<SolveReport>,
+}
+
+/// Summary statistics of a sample
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Aggregate {
+    pub mean: f64,
+    pub median: f64,
+    pub stddev: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+/// The report in a human readable form
+impl Display for SolveReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "objective {}", self.objective)?;
+        writeln!(f, "symmetry breaking {}", self.symmetry_breaking)?;
+        writeln!(f, "relaxation {} with {} merges", self.settings.relax, self.settings.merge_policy)?;
+        for change in self.settings.adjustments.iter() {
+            writeln!(f, "adjusted: {change}")?;
+        }
+        writeln!(f, "is exact {}", self.is_exact)?;
+        match (self.value, self.tour.as_ref()) {
+            (Some(value), Some(tour)) => {
+                writeln!(f, "best value {value:.3} {}", self.unit)?;
+                // the distance is the best value unless travel times were optimized
+                if let Some((distance, unit)) = self.distance.zip(self.distance_unit.as_ref()).filter(|_| self.settings.departure.is_some()) {
+                    writeln!(f, "distance {distance:.3} {unit}")?;
+                }
+                if let Some(duration) = self.duration {
+                    writeln!(f, "duration {duration:.3} h")?;
+                }
+                let mut sol = String::new();
+                tour.iter().for_each(|v| sol.push_str(&format!("{v} ")));
+                writeln!(f, "solution: {sol}")?;
+                if let Some(clusters) = self.clusters.as_ref() {
+                    writeln!(f, "clusters: {}", clusters.join(" "))?;
+                }
+            }
+            _ if self.is_exact => {
+                writeln!(f, "the instance is infeasible: no tour can reach all the destinations")?;
+            }
+            _ => {
+                writeln!(f, "no solution found within the budget")?;
+            }
+        }
+        if let Some(bound) = self.bound.filter(|_| !self.is_exact) {
+            writeln!(f, "best bound {bound:.3} {}", self.unit)?;
+        }
+        if let Some(configuration) = self.found_by.as_ref() {
+            writeln!(f, "found by {configuration}")?;
+        }
+        if let Some(configuration) = self.proved_by.as_ref() {
+            writeln!(f, "proved by {configuration}")?;
+        }
+        for contender in self.contenders.iter().flatten() {
+            let value = contender.value.map_or("none".to_string(), |v| format!("{v:.3} {}", self.unit));
+            writeln!(f, "contender {} value {value} exact {} time {:.3} s", contender.configuration, contender.is_exact, contender.time)?;
+        }
+        for probe in self.probes.iter().flatten() {
+            let show = |x: Option<f64>| x.map_or("none".to_string(), |x| format!("{x:.3} {}", self.unit));
+            writeln!(f, "probe width {} value {} bound {} exact {} time {:.3} s", probe.width, show(probe.value), show(probe.bound), probe.is_exact, probe.time)?;
+        }
+        if self.probes.as_ref().is_some_and(|probes| !probes.is_empty()) {
+            writeln!(f, "width chosen by the probes {}", self.settings.width)?;
+        }
+        if let Some(pool) = self.pool.as_ref() {
+            writeln!(f, "pool of {} tours differing by at least {} ({} candidates)", pool.members.len(), pool.min_diff, pool.nb_candidates)?;
+            for (i, member) in pool.members.iter().enumerate() {
+                let value = member.value.map_or("none".to_string(), |v| format!("{v:.3} {}", self.unit));
+                let tour = member.tour.iter().map(|d| d.to_string()).collect::<Vec<_>>().join(" ");
+                writeln!(f, "pool {i} value {value}: {tour}")?;
+            }
+            for row in pool.differences.iter() {
+                let row = row.iter().map(|d| format!("{d:.3}")).collect::<Vec<_>>().join(" ");
+                writeln!(f, "pool differences {row}")?;
+            }
+        }
+        writeln!(f, "explored nodes {}", self.explored_nodes)?;
+        writeln!(f, "frontier size {}", self.frontier_size)?;
+        if let (Some(peak), Some(last)) = (self.peak_rss, self.final_rss) {
+            let megabytes = |bytes: u64| bytes as f64 / (1024.0 * 1024.0);
+            writeln!(f, "memory peak {:.1} MB final {:.1} MB", megabytes(peak), megabytes(last))?;
+        }
+        writeln!(f, "preprocessing time {:.3} s (load {:.3} s)", self.preprocessing, self.phases.load)?;
+        writeln!(f, "search time {:.3} s", self.time)
+    }
+}
+
+impl RepeatedReport {
+    /// Summarizes the given runs, among which `best` found the best tour
+    pub fn new(runs: Vec<SolveReport>, best: usize) -> Self {
+        let values = runs.iter().filter_map(|r| r.value).collect::<Vec<_>>();
+        let times = runs.iter().map(|r| r.time).collect::<Vec<_>>();
+        RepeatedReport {
+            value: Aggregate::of(&values),
+            time: Aggregate::of(&times).unwrap_or(Aggregate{mean: 0.0, median: 0.0, stddev: 0.0, min: 0.0, max: 0.0}),
+            nb_exact: runs.iter().filter(|r| r.is_exact).count(),
+            best,
+            runs,
+        }
+    }
+}
+
+/// The statistics over the runs followed by the best run
+impl Display for RepeatedReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let unit = &self.runs[self.best].unit;
+        writeln!(f, "runs {} (exact {})", self.runs.len(), self.nb_exact)?;
+        match self.value {
+            Some(v) => writeln!(f, "value mean {:.3} median {:.3} stddev {:.3} min {:.3} max {:.3} {unit}", v.mean, v.median, v.stddev, v.min, v.max)?,
+            None => writeln!(f, "value: no run found a tour")?,
+        }
+        let t = self.time;
+        writeln!(f, "time mean {:.3} median {:.3} stddev {:.3} min {:.3} max {:.3} s", t.mean, t.median, t.stddev, t.min, t.max)?;
+        writeln!(f, "best run {}", self.best)?;
+        write!(f, "{}", self.runs[self.best])
+    }
+}
+
+impl Aggregate {
+    /// Computes the statistics of the given sample, if it is not empty
+    pub fn of(sample: &[f64]) -> Option<Self> {
+        if sample.is_empty() {
+            return None;
+        }
+        let n = sample.len() as f64;
+        let mut sorted = sample.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        let mid = sorted.len() / 2;
+        let median = if sorted.len().is_multiple_of(2) { (sorted[mid - 1] + sorted[mid]) / 2.0 } else { sorted[mid] };
+        let mean = sample.iter().sum::<f64>() / n;
+        let stddev = (sample.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n).sqrt();
+        Some(Aggregate{mean, median, stddev, min: sorted[0], max: sorted[sorted.len() - 1]})
+    }
+}
@@ -0,0 +1,94 @@
+//! This module provides an asynchronous interface to the solver which is meant
+//! to be embedded in services: the solver runs on its own thread and its
+//! progress is observed as a stream of events.
+
+use std::{pin::Pin, sync::{Arc, atomic::{AtomicBool, Ordering}}, task::{Context, Poll}};
+
+use ddo::Cutoff;
+use futures::{Stream, channel::mpsc::{self, UnboundedReceiver}};
+use tracing::Span;
+
+use super::{options::SolveOptions, prepare, report::SolveReport, search};
+
+/// An event of a solver run observed through `solve_stream`
+#[derive(Debug, Clone)]
+pub enum SolveEvent {
+    /// A better tour was found
+    Incumbent {
+        /// The sequence of destinations visited by the tour
+        tour: Vec<usize>,
+        /// The length of the tour (expressed in the unit of the report)
+        value: f64,
+        /// The time elapsed since the beginning of the search in seconds
+        elapsed: f64,
+    },
+    /// The run is over, the report tells whether the search was completed and
+    /// gives the best bound that was proved
+    Completion(Box<SolveReport>),
+}
+
+/// The stream of the events of a solver run. Dropping it stops the solver.
+pub struct SolveStream {
+    events: UnboundedReceiver<Result<SolveEvent, String>>,
+    cancelled: Arc<AtomicBool>,
+}
+
+/// Solves the instance at the given path with the given options on a dedicated
+/// thread and returns the stream of the events of the run. The stream ends with
+/// a `Completion` event, or with an error when the instance cannot be solved.
+///
+/// The solver (ddo 1.0) does not notify the improvements of its incumbent while
+/// it runs: the best tour is yielded once the search is over, right before the
+/// completion event.
+pub fn solve_stream(instance: &str, options: &SolveOptions) -> SolveStream {
+    let (sender, events) = mpsc::unbounded();
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let (instance, options, flag) = (instance.to_string(), options.clone(), cancelled.clone());
+    let span = Span::current();
+    std::thread::spawn(move || {
+        let _entered = span.enter();
+        let outcome = prepare(&instance, &options).map_err(String::from).and_then(|prepared| {
+            let cutoff = Cancellable { inner: options.cutoff(), cancelled: flag };
+            search(&instance, &options, &prepared, &cutoff, true, None).map(|(report, _)| report)
+        });
+        // the receiver is gone when the stream was dropped, nobody is listening then
+        match outcome {
+            Ok(report) => {
+                if let (Some(tour), Some(value)) = (report.tour.clone(), report.value) {
+                    let _ = sender.unbounded_send(Ok(SolveEvent::Incumbent { tour, value, elapsed: report.time }));
+                }
+                let _ = sender.unbounded_send(Ok(SolveEvent::Completion(Box::new(report))));
+            }
+            Err(e) => {
+                let _ = sender.unbounded_send(Err(e));
+            }
+        }
+    });
+    SolveStream { events, cancelled }
+}
+
+impl Stream for SolveStream {
+    type Item = Result<SolveEvent, String>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.events).poll_next(cx)
+    }
+}
+
+impl Drop for SolveStream {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+/// A cutoff which also stops the solver once its stream is dropped
+struct Cancellable {
+    inner: Box<dyn Cutoff + Send + Sync>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Cutoff for Cancellable {
+    fn must_stop(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed) || self.inner.must_stop()
+    }
+}
@@ -0,0 +1,378 @@
+//! This module provides the width heuristics that can be used to bound the size
+//! of the layers in the decision diagrams compiled while solving a tsp instance.
+
+use std::{fmt::Display, fs::read_to_string, path::Path, str::FromStr};
+
+use clap::ValueEnum;
+use ddo::{WidthHeuristic, SubProblem};
+use serde::{Serialize, Deserialize};
+
+use super::model::TspState;
+
+/// Tells how the maximum width of the decision diagrams is chosen when no
+/// width schedule is given
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WidthPolicy {
+    /// The same width is used for all the diagrams
+    #[default]
+    Fixed,
+    /// The width is proportional to the number of destinations that are left to
+    /// visit from the root state of the diagram
+    StateSize,
+}
+
+impl Display for WidthPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WidthPolicy::Fixed     => write!(f, "fixed"),
+            WidthPolicy::StateSize => write!(f, "state-size"),
+        }
+    }
+}
+
+/// The width given on the command line: a number of nodes, or `auto` to let
+/// short probes choose it before the search (see `probing`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WidthChoice {
+    Auto,
+    Given(usize),
+}
+
+impl FromStr for WidthChoice {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "auto" => Ok(WidthChoice::Auto),
+            text => text.parse().map(WidthChoice::Given).map_err(|_| format!("'{text}' is neither a width nor auto")),
+        }
+    }
+}
+
+impl Display for WidthChoice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WidthChoice::Auto         => write!(f, "auto"),
+            WidthChoice::Given(width) => write!(f, "{width}"),
+        }
+    }
+}
+
+/// Gives `per_destination` nodes per destination that must or might still be
+/// visited from the root state of a subproblem, clipped to `[min, max]`. Unlike a
+/// schedule, it looks at the states themselves: the width naturally shrinks near
+/// the leaves and for merged states whose remaining sets are small.
+#[derive(Debug, Clone, Copy)]
+pub struct StateSizeWidth {
+    pub per_destination: usize,
+    pub min: usize,
+    pub max: usize,
+}
+
+impl StateSizeWidth {
+    /// Returns the width of a diagram whose root state has the given number of
+    /// destinations left to visit
+    pub fn width_of(&self, remaining: usize) -> usize {
+        self.per_destination.saturating_mul(remaining).clamp(self.min, self.max.max(self.min)).max(1)
+    }
+}
+
+impl WidthHeuristic<TspState> for StateSizeWidth {
+    fn max_width(&self, sub: &SubProblem<TspState>) -> usize {
+        self.width_of(sub.state.must_visit().union(sub.state.might_visit()).len())
+    }
+}
+
+/// A width schedule gives the maximum width of the decision diagrams that are
+/// compiled for a subproblem as a function of the depth of its root state.
+#[derive(Debug, Clone)]
+pub enum WidthSchedule {
+    /// One width per depth (the last value is used for all deeper layers)
+    Explicit(Vec<usize>),
+    /// An arithmetic expression of the depth `d` (ie `min(50+10*d, 2000)`)
+    Expression(Expr),
+}
+
+impl WidthSchedule {
+    /// Parses a schedule specification. When the spec is the path to an existing
+    /// file, that file is expected to list one width per depth. Otherwise, the
+    /// spec is understood as an expression of the depth `d`.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        if Path::new(spec).is_file() {
+            let text = read_to_string(spec).map_err(|e| format!("cannot read {spec}: {e}"))?;
+            let widths = text.split_whitespace()
+                .map(|tok| tok.parse::<usize>().ok().filter(|w| *w >= 1).ok_or_else(|| format!("invalid width '{tok}' in {spec}, the widths must be positive integers")))
+                .collect::<Result<Vec<_>, _>>()?;
+            if widths.is_empty() {
+                return Err(format!("the width schedule in {spec} is empty"));
+            }
+            Ok(WidthSchedule::Explicit(widths))
+        } else {
+            Ok(WidthSchedule::Expression(Expr::parse(spec)?))
+        }
+    }
+
+    /// Returns the maximum width of a diagram whose root is at the given depth
+    pub fn width_at(&self, depth: usize) -> usize {
+        match self {
+            WidthSchedule::Explicit(widths) => widths[depth.min(widths.len() - 1)],
+            WidthSchedule::Expression(expr) => expr.eval(depth as f64).round().max(1.0) as usize,
+        }
+    }
+
+    /// Checks that the schedule gives a usable width at each depth of a problem
+    /// with `nb_variables` destinations: an expression must evaluate to a finite
+    /// and positive number (ie it must not divide by zero).
+    pub fn check(&self, nb_variables: usize) -> Result<(), String> {
+        if let WidthSchedule::Expression(expr) = self {
+            for depth in 0..nb_variables.max(1) {
+                let width = expr.eval(depth as f64);
+                if !width.is_finite() || width <= 0.0 {
+                    return Err(format!("the width expression {expr} gives {width} at depth {depth}, the widths must be positive numbers"));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns true iff the schedule explicitly gives a width for all the depths
+    /// up to `nb_variables`.
+    pub fn covers(&self, nb_variables: usize) -> bool {
+        match self {
+            WidthSchedule::Explicit(widths) => widths.len() >= nb_variables,
+            WidthSchedule::Expression(_) => true,
+        }
+    }
+}
+
+impl WidthHeuristic<TspState> for WidthSchedule {
+    fn max_width(&self, sub: &SubProblem<TspState>) -> usize {
+        self.width_at(sub.state.depth())
+    }
+}
+
+impl Display for WidthSchedule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WidthSchedule::Explicit(widths) => write!(f, "{widths:?}"),
+            WidthSchedule::Expression(expr) => write!(f, "{expr}"),
+        }
+    }
+}
+
+/// A (very) small arithmetic expression language over the depth `d`. It supports
+/// numbers, `+ - * /`, parentheses and the functions `min` and `max`.
+#[derive(Debug, Clone)]
+pub enum Expr {
+    Depth,
+    Number(f64),
+    Neg(Box<Expr>),
+    Binary(char, Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+}
+
+impl Expr {
+    /// Parses the given text into an expression
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let tokens = tokenize(text)?;
+        let mut pos = 0;
+        let expr = parse_sum(&tokens, &mut pos)?;
+        if pos != tokens.len() {
+            return Err(format!("unexpected '{}' in width expression '{text}'", tokens[pos]));
+        }
+        Ok(expr)
+    }
+
+    /// Evaluates the expression for the given depth
+    pub fn eval(&self, d: f64) -> f64 {
+        match self {
+            Expr::Depth => d,
+            Expr::Number(x) => *x,
+            Expr::Neg(e) => -e.eval(d),
+            Expr::Binary('+', a, b) => a.eval(d) + b.eval(d),
+            Expr::Binary('-', a, b) => a.eval(d) - b.eval(d),
+            Expr::Binary('*', a, b) => a.eval(d) * b.eval(d),
+            Expr::Binary(_, a, b) => a.eval(d) / b.eval(d),
+            Expr::Call(name, args) => {
+                let values = args.iter().map(|a| a.eval(d));
+                if name == "min" {
+                    values.fold(f64::INFINITY, f64::min)
+                } else {
+                    values.fold(f64::NEG_INFINITY, f64::max)
+                }
+            }
+        }
+    }
+}
+
+impl Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expr::Depth => write!(f, "d"),
+            Expr::Number(x) => write!(f, "{x}"),
+            Expr::Neg(e) => write!(f, "-{e}"),
+            Expr::Binary(op, a, b) => write!(f, "({a}{op}{b})"),
+            Expr::Call(name, args) => {
+                let args = args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+                write!(f, "{name}({})", args.join(", "))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Symbol(char),
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Number(x) => write!(f, "{x}"),
+            Token::Ident(s) => write!(f, "{s}"),
+            Token::Symbol(c) => write!(f, "{c}"),
+        }
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let chars = text.chars().collect::<Vec<_>>();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() || c == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            let number = chars[start..i].iter().collect::<String>();
+            let number = number.parse::<f64>().map_err(|_| format!("invalid number '{number}' in width expression"))?;
+            tokens.push(Token::Number(number));
+        } else if c.is_ascii_alphabetic() {
+            let start = i;
+            while i < chars.len() && chars[i].is_ascii_alphanumeric() {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else if "+-*/(),".contains(c) {
+            tokens.push(Token::Symbol(c));
+            i += 1;
+        } else {
+            return Err(format!("unexpected character '{c}' in width expression '{text}'"));
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_sum(tokens: &[Token], pos: &mut usize) -> Result<Expr, String> {
+    let mut lhs = parse_product(tokens, pos)?;
+    while let Some(Token::Symbol(op @ ('+' | '-'))) = tokens.get(*pos) {
+        *pos += 1;
+        let rhs = parse_product(tokens, pos)?;
+        lhs = Expr::Binary(*op, Box::new(lhs), Box::new(rhs));
+    }
+    Ok(lhs)
+}
+
+fn parse_product(tokens: &[Token], pos: &mut usize) -> Result<Expr, String> {
+    let mut lhs = parse_factor(tokens, pos)?;
+    while let Some(Token::Symbol(op @ ('*' | '/'))) = tokens.get(*pos) {
+        *pos += 1;
+        let rhs = parse_factor(tokens, pos)?;
+        lhs = Expr::Binary(*op, Box::new(lhs), Box::new(rhs));
+    }
+    Ok(lhs)
+}
+
+fn parse_factor(tokens: &[Token], pos: &mut usize) -> Result<Expr, String> {
+    let token = tokens.get(*pos).ok_or("unexpected end of width expression")?;
+    *pos += 1;
+    match token {
+        Token::Number(x) => Ok(Expr::Number(*x)),
+        Token::Symbol('-') => Ok(Expr::Neg(Box::new(parse_factor(tokens, pos)?))),
+        Token::Symbol('(') => {
+            let expr = parse_sum(tokens, pos)?;
+            expect(tokens, pos, ')')?;
+            Ok(expr)
+        }
+        Token::Ident(name) if name == "d" => Ok(Expr::Depth),
+        Token::Ident(name) if name == "min" || name == "max" => {
+            expect(tokens, pos, '(')?;
+            let mut args = vec![parse_sum(tokens, pos)?];
+            while tokens.get(*pos) == Some(&Token::Symbol(',')) {
+                *pos += 1;
+                args.push(parse_sum(tokens, pos)?);
+            }
+            expect(tokens, pos, ')')?;
+            Ok(Expr::Call(name.clone(), args))
+        }
+        other => Err(format!("unexpected '{other}' in width expression")),
+    }
+}
+
+fn expect(tokens: &[Token], pos: &mut usize, symbol: char) -> Result<(), String> {
+    if tokens.get(*pos) == Some(&Token::Symbol(symbol)) {
+        *pos += 1;
+        Ok(())
+    } else {
+        Err(format!("expected '{symbol}' in width expression"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Expr, WidthSchedule};
+
+    fn eval(text: &str, d: f64) -> f64 {
+        Expr::parse(text).unwrap().eval(d)
+    }
+
+    #[test]
+    fn products_take_precedence_over_sums() {
+        assert_eq!(eval("1 + 2 * 3", 0.0), 7.0);
+        assert_eq!(eval("10 - 6 / 2 - 1", 0.0), 6.0);
+        assert_eq!(eval("-d * 2 + 20", 3.0), 14.0);
+        assert_eq!(Expr::parse("1 + 2 * 3").unwrap().to_string(), "(1+(2*3))");
+    }
+
+    #[test]
+    fn parentheses_group_the_operations() {
+        assert_eq!(eval("(1 + 2) * 3", 0.0), 9.0);
+        assert_eq!(eval("min(50 + 10 * d, 2 * (d + 100))", 10.0), 150.0);
+        assert_eq!(eval("max(d, 3, (1))", 2.0), 3.0);
+        assert!(Expr::parse("(1 + 2").is_err());
+        assert!(Expr::parse("1 + 2)").is_err());
+        assert!(Expr::parse("min 1, 2").is_err());
+    }
+
+    #[test]
+    fn unknown_variables_are_rejected() {
+        let err = Expr::parse("10 * x").unwrap_err();
+        assert!(err.contains("'x'"), "{err}");
+        assert!(Expr::parse("depth + 1").is_err());
+        assert!(Expr::parse("sqrt(d)").is_err());
+    }
+
+    #[test]
+    fn a_division_by_zero_is_rejected() {
+        let schedule = WidthSchedule::parse("100 / (2 - d)").unwrap();
+        assert!(schedule.check(2).is_ok());
+        let err = schedule.check(3).unwrap_err();
+        assert!(err.contains("at depth 2"), "{err}");
+        assert!(WidthSchedule::parse("0 / 0").unwrap().check(1).is_err());
+    }
+
+    #[test]
+    fn a_width_which_is_not_positive_is_rejected() {
+        let schedule = WidthSchedule::parse("10 - d").unwrap();
+        assert!(schedule.check(10).is_ok());
+        assert!(schedule.check(11).is_err());
+        assert!(WidthSchedule::parse("d").unwrap().check(1).is_err());
+    }
+}
@@ -0,0 +1,298 @@
+//! This module provides the sinks where the results of the solver runs are
+//! collected for later analysis. The kind of sink is chosen after the extension
+//! of the results file: `.csv` files are always supported while `.parquet` files
+//! require the `parquet` feature.
+
+use std::{fs::{File, OpenOptions}, io::Write, path::Path};
+
+use serde::Serialize;
+
+use crate::{generation::now, resolution::SolveReport};
+
+/// One row of a results file: the outcome of a single solver run
+#[derive(Debug, Clone, Serialize)]
+pub struct ResultRow {
+    pub instance: String,
+    pub digest: String,
+    pub objective: String,
+    pub width: u64,
+    pub width_schedule: Option<String>,
+    pub width_policy: String,
+    pub min_width: Option<u64>,
+    pub max_width: Option<u64>,
+    pub timeout: u64,
+    pub deterministic: bool,
+    pub frontier: String,
+    pub relax: String,
+    pub merge_policy: String,
+    pub departure: Option<String>,
+    pub is_exact: bool,
+    pub value: Option<f64>,
+    pub bound: Option<f64>,
+    /// The relative gap between the value and the bound
+    pub gap: Option<f64>,
+    pub unit: String,
+    /// The distance of the tour (expressed in `distance_unit`)
+    pub distance: Option<f64>,
+    pub distance_unit: Option<String>,
+    /// The travel time of the tour in hours
+    pub duration: Option<f64>,
+    /// The wall clock time of the preprocessing in seconds
+    pub preprocessing: f64,
+    /// The wall clock time of the search in seconds
+    pub time: f64,
+    pub frontier_size: u64,
+    /// The peak and final resident set size of the process in bytes
+    pub peak_rss: Option<u64>,
+    pub final_rss: Option<u64>,
+    /// The unix timestamp at which the row was written
+    pub timestamp: u64,
+}
+
+/// The names of the columns of a results file
+const COLUMNS: [&str; 28] = [
+    "instance", "digest", "objective", "width", "width_schedule", "width_policy", "min_width", "max_width",
+    "timeout", "deterministic", "frontier", "relax", "merge_policy", "departure", "is_exact", "value", "bound",
+    "gap", "unit", "distance", "distance_unit", "duration", "preprocessing", "time", "frontier_size", "peak_rss",
+    "final_rss", "timestamp",
+];
+
+impl From<&SolveReport> for ResultRow {
+    fn from(report: &SolveReport) -> Self {
+        let name = |value: serde_json::Value| value.as_str().unwrap_or_default().to_string();
+        let settings = &report.settings;
+        let gap = report.value.zip(report.bound)
+            .filter(|(value, _)| *value != 0.0)
+            .map(|(value, bound)| ((value - bound) / value).abs());
+        ResultRow {
+            instance: report.instance.clone(),
+            digest: report.digest.clone(),
+            objective: report.objective.to_string(),
+            width: settings.width as u64,
+            width_schedule: settings.width_schedule.clone(),
+            width_policy: name(serde_json::to_value(settings.width_policy).unwrap()),
+            min_width: settings.state_size_width().map(|w| w.min as u64),
+            max_width: settings.state_size_width().map(|w| w.max as u64),
+            timeout: settings.timeout,
+            deterministic: settings.deterministic,
+            frontier: name(serde_json::to_value(settings.frontier).unwrap()),
+            relax: settings.relax.to_string(),
+            merge_policy: settings.merge_policy.to_string(),
+            departure: settings.departure.clone(),
+            is_exact: report.is_exact,
+            value: report.value,
+            bound: report.bound,
+            gap,
+            unit: report.unit.clone(),
+            distance: report.distance,
+            distance_unit: report.distance_unit.clone(),
+            duration: report.duration,
+            preprocessing: report.preprocessing,
+            time: report.time,
+            frontier_size: report.frontier_size as u64,
+            peak_rss: report.peak_rss,
+            final_rss: report.final_rss,
+            timestamp: now(),
+        }
+    }
+}
+
+/// A destination for the results of the solver runs
+pub trait ResultsSink {
+    /// Writes the given rows. They are durably stored once this returns.
+    fn write(&mut self, rows: &[ResultRow]) -> Result<(), String>;
+    /// Completes the file, no row can be written afterwards
+    fn close(&mut self) -> Result<(), String>;
+}
+
+/// Opens the sink of the given results file after its extension
+pub fn open(path: &str) -> Result<Box<dyn ResultsSink + Send>, String> {
+    match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+        Some("csv") => Ok(Box::new(CsvSink::open(path)?)),
+        #[cfg(feature = "parquet")]
+        Some("parquet") => Ok(Box::new(parquet::ParquetSink::open(path)?)),
+        #[cfg(not(feature = "parquet"))]
+        Some("parquet") => Err("writing parquet files requires the parquet feature".to_string()),
+        _ => Err(format!("unknown results file format for {path} (expected .csv or .parquet)")),
+    }
+}
+
+/// Appends the rows to a csv file. The header is only written when the file is
+/// created, and the file is locked while the rows are appended so that several
+/// processes can share it.
+pub struct CsvSink {
+    path: String,
+    file: File,
+}
+
+impl CsvSink {
+    pub fn open(path: &str) -> Result<Self, String> {
+        let file = OpenOptions::new().create(true).append(true).open(path)
+            .map_err(|e| format!("cannot open {path}: {e}"))?;
+        Ok(CsvSink { path: path.to_string(), file })
+    }
+}
+
+impl ResultsSink for CsvSink {
+    fn write(&mut self, rows: &[ResultRow]) -> Result<(), String> {
+        let error = |e: std::io::Error| format!("cannot write {}: {e}", self.path);
+        self.file.lock().map_err(error)?;
+        let mut text = String::new();
+        if self.file.metadata().map_err(error)?.len() == 0 {
+            text.push_str(&COLUMNS.join(","));
+            text.push('\n');
+        }
+        for row in rows {
+            let fields = serde_json::to_value(row).unwrap();
+            let fields = COLUMNS.iter()
+                .map(|column| match &fields[*column] {
+                    serde_json::Value::Null      => String::new(),
+                    serde_json::Value::String(s) => csv_field(s),
+                    value                        => value.to_string(),
+                })
+                .collect::<Vec<_>>();
+            text.push_str(&fields.join(","));
+            text.push('\n');
+        }
+        let written = self.file.write_all(text.as_bytes()).and_then(|_| self.file.flush());
+        self.file.unlock().map_err(error)?;
+        written.map_err(error)
+    }
+
+    fn close(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Quotes a csv field when it holds a separator, a quote or a line break
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(feature = "parquet")]
+mod parquet {
+    use std::{fs::{File, OpenOptions, rename}, path::Path, sync::Arc};
+
+    use arrow::{array::{ArrayRef, BooleanArray, Float64Array, StringArray, UInt64Array}, datatypes::{DataType, Field, Schema, SchemaRef}, record_batch::RecordBatch};
+    use parquet::arrow::{ArrowWriter, arrow_reader::ParquetRecordBatchReaderBuilder};
+
+    use super::{ResultRow, ResultsSink, COLUMNS};
+
+    /// Writes the rows to a parquet file, one row group per call to `write`.
+    /// Parquet files cannot be appended to: the rows of an existing file are
+    /// copied to a temporary file which replaces it when the sink is closed. A
+    /// lock file prevents several processes from writing the same file at once.
+    pub struct ParquetSink {
+        path: String,
+        temporary: String,
+        schema: SchemaRef,
+        writer: Option<ArrowWriter<File>>,
+        _lock: File,
+    }
+
+    impl ParquetSink {
+        pub fn open(path: &str) -> Result<Self, String> {
+            let lock = OpenOptions::new().create(true).truncate(false).write(true).open(format!("{path}.lock"))
+                .map_err(|e| format!("cannot lock {path}: {e}"))?;
+            lock.lock().map_err(|e| format!("cannot lock {path}: {e}"))?;
+
+            let schema = schema();
+            let temporary = format!("{path}.tmp");
+            let file = File::create(&temporary).map_err(|e| format!("cannot create {temporary}: {e}"))?;
+            let mut writer = ArrowWriter::try_new(file, schema.clone(), None).map_err(|e| format!("cannot write {path}: {e}"))?;
+            if Path::new(path).exists() {
+                let file = File::open(path).map_err(|e| format!("cannot read {path}: {e}"))?;
+                let reader = ParquetRecordBatchReaderBuilder::try_new(file)
+                    .and_then(|builder| builder.build())
+                    .map_err(|e| format!("cannot read {path}: {e}"))?;
+                for batch in reader {
+                    let batch = batch.map_err(|e| format!("cannot read {path}: {e}"))?;
+                    writer.write(&batch).map_err(|e| format!("cannot copy the rows of {path}: {e}"))?;
+                }
+            }
+            Ok(ParquetSink { path: path.to_string(), temporary, schema, writer: Some(writer), _lock: lock })
+        }
+    }
+
+    impl ResultsSink for ParquetSink {
+        fn write(&mut self, rows: &[ResultRow]) -> Result<(), String> {
+            let writer = self.writer.as_mut().ok_or_else(|| format!("{} is closed", self.path))?;
+            let batch = batch(self.schema.clone(), rows).map_err(|e| format!("cannot write {}: {e}", self.path))?;
+            writer.write(&batch).map_err(|e| format!("cannot write {}: {e}", self.path))?;
+            writer.flush().map_err(|e| format!("cannot write {}: {e}", self.path))
+        }
+
+        fn close(&mut self) -> Result<(), String> {
+            if let Some(writer) = self.writer.take() {
+                writer.close().map_err(|e| format!("cannot write {}: {e}", self.path))?;
+                rename(&self.temporary, &self.path).map_err(|e| format!("cannot write {}: {e}", self.path))?;
+            }
+            Ok(())
+        }
+    }
+
+    impl Drop for ParquetSink {
+        fn drop(&mut self) {
+            if let Err(e) = self.close() {
+                eprintln!("warning: {e}");
+            }
+        }
+    }
+
+    fn schema() -> SchemaRef {
+        let fields = COLUMNS.iter().map(|column| {
+            let (kind, nullable) = match *column {
+                "instance" | "digest" | "objective" | "width_policy" | "frontier" | "relax" | "merge_policy" | "unit" => (DataType::Utf8, false),
+                "width_schedule" | "departure" | "distance_unit"                                                      => (DataType::Utf8, true),
+                "width" | "timeout" | "frontier_size" | "timestamp"                                                   => (DataType::UInt64, false),
+                "min_width" | "max_width" | "peak_rss" | "final_rss"                                                  => (DataType::UInt64, true),
+                "deterministic" | "is_exact"                                                                          => (DataType::Boolean, false),
+                "preprocessing" | "time"                                                                              => (DataType::Float64, false),
+                _                                                                                                     => (DataType::Float64, true),
+            };
+            Field::new(*column, kind, nullable)
+        });
+        Arc::new(Schema::new(fields.collect::<Vec<_>>()))
+    }
+
+    fn batch(schema: SchemaRef, rows: &[ResultRow]) -> Result<RecordBatch, arrow::error::ArrowError> {
+        let strings = |f: fn(&ResultRow) -> Option<&str>| Arc::new(rows.iter().map(f).collect::<StringArray>()) as ArrayRef;
+        let integers = |f: fn(&ResultRow) -> Option<u64>| Arc::new(rows.iter().map(f).collect::<UInt64Array>()) as ArrayRef;
+        let floats = |f: fn(&ResultRow) -> Option<f64>| Arc::new(rows.iter().map(f).collect::<Float64Array>()) as ArrayRef;
+        let booleans = |f: fn(&ResultRow) -> Option<bool>| Arc::new(rows.iter().map(f).collect::<BooleanArray>()) as ArrayRef;
+        RecordBatch::try_new(schema, vec![
+            strings(|r| Some(&r.instance)),
+            strings(|r| Some(&r.digest)),
+            strings(|r| Some(&r.objective)),
+            integers(|r| Some(r.width)),
+            strings(|r| r.width_schedule.as_deref()),
+            strings(|r| Some(&r.width_policy)),
+            integers(|r| r.min_width),
+            integers(|r| r.max_width),
+            integers(|r| Some(r.timeout)),
+            booleans(|r| Some(r.deterministic)),
+            strings(|r| Some(&r.frontier)),
+            strings(|r| Some(&r.relax)),
+            strings(|r| Some(&r.merge_policy)),
+            strings(|r| r.departure.as_deref()),
+            booleans(|r| Some(r.is_exact)),
+            floats(|r| r.value),
+            floats(|r| r.bound),
+            floats(|r| r.gap),
+            strings(|r| Some(&r.unit)),
+            floats(|r| r.distance),
+            strings(|r| r.distance_unit.as_deref()),
+            floats(|r| r.duration),
+            floats(|r| Some(r.preprocessing)),
+            floats(|r| Some(r.time)),
+            integers(|r| Some(r.frontier_size)),
+            integers(|r| r.peak_rss),
+            integers(|r| r.final_rss),
+            integers(|r| Some(r.timestamp)),
+        ])
+    }
+}
@@ -0,0 +1,154 @@
+//! This module implements the command which prints a few statistics about an
+//! instance.
+
+use clap::Args;
+use serde::Serialize;
+
+use crate::{instance::{Instance, Matrix, Rounding}, status::fail};
+
+/// This command prints a few statistics about an instance: its size, whether its
+/// matrix is symmetric and metric (before and after it is rounded to integers)
+/// and, when it holds several durations snapshots, how much the travel times
+/// depend on the departure time.
+#[derive(Debug, Args)]
+pub struct Stats {
+    /// The path to the instance file
+    #[clap(short, long)]
+    pub instance: String,
+    /// Drops the destinations of the instance which have no row in its distance
+    /// matrix rather than rejecting it
+    #[clap(long)]
+    pub truncate_to_matrix: bool,
+    /// How the distances are rounded when checking the rounded matrix
+    #[clap(long, value_enum, default_value = "nearest")]
+    pub rounding: Rounding,
+    /// The factor applied to the distances before they are rounded (like the
+    /// multiplier of the TSPLIB export)
+    #[clap(long, default_value_t = 1.0)]
+    pub multiplier: f64,
+}
+
+/// The statistics of an instance
+#[derive(Debug, Clone, Serialize)]
+pub struct Statistics {
+    pub nb_destinations: usize,
+    pub unit: String,
+    /// True iff the distance from i to j always equals the distance from j to i
+    pub symmetric: bool,
+    /// The number of ordered pairs of distinct destinations that cannot be reached
+    pub nb_unreachable: usize,
+    /// True iff the distances satisfy the triangle inequality
+    pub metric: bool,
+    /// True iff the distances still satisfy the triangle inequality once they are
+    /// multiplied and rounded to integers
+    pub rounded_metric: bool,
+    /// The labels of the durations snapshots
+    pub departures: Vec<String>,
+    /// The largest relative difference between the travel times of a same leg in
+    /// two snapshots: (slowest - fastest) / fastest
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_relative_difference: Option<f64>,
+}
+
+impl Stats {
+    /// Executes this command. In quiet mode, the statistics are printed as json.
+    pub async fn execute(&self, quiet: bool) {
+        let stats = Instance::load_with(&self.instance, self.truncate_to_matrix)
+            .map(|instance| statistics(&instance, self.rounding, self.multiplier));
+        match stats {
+            Ok(stats) if quiet => println!("{}", serde_json::to_string(&stats).unwrap()),
+            Ok(stats) => {
+                println!("destinations        {}", stats.nb_destinations);
+                println!("unit                {}", stats.unit);
+                println!("symmetric           {}", stats.symmetric);
+                println!("unreachable pairs   {}", stats.nb_unreachable);
+                println!("metric              {}", stats.metric);
+                println!("metric when rounded {}", stats.rounded_metric);
+                if !stats.departures.is_empty() {
+                    println!("departures          {}", stats.departures.join(", "));
+                }
+                if let Some(diff) = stats.max_relative_difference {
+                    println!("max difference      {:.2}% between departures", 100.0 * diff);
+                }
+            }
+            Err(e) => fail(e),
+        }
+    }
+}
+
+/// Computes the statistics of the given instance, the rounded matrix being
+/// obtained by multiplying the distances by `multiplier` and rounding them
+pub fn statistics(instance: &Instance, rounding: Rounding, multiplier: f64) -> Statistics {
+    let n = instance.distances.len();
+    let d = &instance.distances;
+    let snapshots = instance.durations_by_time.as_deref().unwrap_or_default();
+    let matrices = snapshots.iter().map(|(_, m)| m).collect::<Vec<_>>();
+    Statistics {
+        nb_destinations: n,
+        unit: format!("{:?}", instance.metadata.unit).to_lowercase(),
+        symmetric: (0..n).all(|i| (0..i).all(|j| d[i][j] == d[j][i])),
+        nb_unreachable: (0..n).map(|i| (0..n).filter(|j| *j != i && !d[i][*j].is_finite()).count()).sum(),
+        metric: is_metric(n, |i, j| d[i][j] as f64, 1e-6),
+        rounded_metric: is_metric(n, |i, j| rounding.round(d[i][j] as f64 * multiplier), 0.0),
+        departures: snapshots.iter().map(|(label, _)| label.clone()).collect(),
+        max_relative_difference: max_relative_difference(&matrices),
+    }
+}
+
+/// Returns true iff the distances given by `d` satisfy the triangle inequality
+/// d(i, k) <= d(i, j) + d(j, k) up to the given relative tolerance. The
+/// unreachable pairs (infinite distances) are only allowed when every detour is
+/// unreachable as well.
+fn is_metric(n: usize, d: impl Fn(usize, usize) -> f64, tolerance: f64) -> bool {
+    (0..n).all(|i| (0..n).filter(|j| *j != i).all(|j| (0..n).filter(|k| *k != i && *k != j).all(|k| {
+        d(i, k) <= (d(i, j) + d(j, k)) * (1.0 + tolerance)
+    })))
+}
+
+/// Returns the largest relative difference between the finite positive values
+/// of a same entry in the given matrices (None when there are less than two)
+fn max_relative_difference(matrices: &[&Matrix]) -> Option<f64> {
+    if matrices.len() < 2 {
+        return None;
+    }
+    let n = matrices[0].len();
+    let mut max = 0.0_f64;
+    for i in 0..n {
+        for j in 0..n {
+            let values = matrices.iter().map(|m| m[i][j] as f64).filter(|v| v.is_finite() && *v > 0.0);
+            let (min, max_value) = values.fold((f64::INFINITY, 0.0_f64), |(lo, hi), v| (lo.min(v), hi.max(v)));
+            if min.is_finite() {
+                max = max.max((max_value - min) / min);
+            }
+        }
+    }
+    Some(max)
+}
+
+#[cfg(test)]
+mod tests {
+    use osrm_client::Location;
+
+    use crate::instance::{Instance, Rounding, Unit};
+
+    use super::statistics;
+
+    #[test]
+    fn a_matrix_may_stay_metric_when_floored_but_not_when_rounded_to_the_nearest() {
+        // 0.7 <= 0.4 + 0.4, but the nearest integers give 1 > 0 + 0
+        let destinations = (0..3).map(|i| Location { longitude: i as f32, latitude: 0.0 }).collect();
+        let distances = vec![
+            vec![0.0, 0.4, 0.7],
+            vec![0.4, 0.0, 0.4],
+            vec![0.7, 0.4, 0.0],
+        ];
+        let instance = Instance::from_distances(destinations, distances.into(), Unit::Kilometers).unwrap();
+
+        let floored = statistics(&instance, Rounding::Floor, 1.0);
+        assert!(floored.metric);
+        assert!(floored.rounded_metric);
+        let nearest = statistics(&instance, Rounding::Nearest, 1.0);
+        assert!(nearest.metric);
+        assert!(!nearest.rounded_metric);
+    }
+}
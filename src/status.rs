@@ -0,0 +1,109 @@
+//! This module defines the exit statuses of the commands, so that the scripts
+//! calling them can tell the outcomes apart.
+
+use std::fmt::Display;
+
+/// The exit status of a command. When solve is given several instances, its
+/// status is the largest of those of the instances.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ExitStatus {
+    /// The command succeeded (the tour found by solve is proven optimal)
+    Success = 0,
+    /// The versions compared by diff --exit-code differ beyond the thresholds,
+    /// solve --reference disagrees with the reference solver, or the certificate
+    /// checked by check-certificate does not hold
+    Mismatch = 1,
+    /// Solve found a tour but did not prove it optimal (timeout or cutoff)
+    Feasible = 2,
+    /// Solve found no tour within its budget
+    NoSolution = 3,
+    /// Solve proved that no tour visits all the destinations
+    Infeasible = 4,
+    /// The input is invalid (or any other error prevented the command from
+    /// completing)
+    InputError = 5,
+}
+
+/// The description of the exit statuses printed by `--help`
+pub const HELP: &str = "\
+Exit statuses:
+  0  success (solve: the tour is proven optimal)
+  1  diff --exit-code: the instances differ, solve --reference: the results disagree,
+     check-certificate: the certificate does not hold
+  2  solve: a tour was found but is not proven optimal
+  3  solve: no tour was found within the budget
+  4  solve: the instance is infeasible
+  5  invalid input or any other error
+With several instances, solve exits with the largest status of the instances.";
+
+impl ExitStatus {
+    /// The exit status of a solver run after its outcome
+    pub fn of_run(found: bool, is_exact: bool) -> Self {
+        match (found, is_exact) {
+            (true, true)   => ExitStatus::Success,
+            (true, false)  => ExitStatus::Feasible,
+            (false, true)  => ExitStatus::Infeasible,
+            (false, false) => ExitStatus::NoSolution,
+        }
+    }
+
+    /// Terminates the process with this status
+    pub fn exit(self) -> ! {
+        std::process::exit(self as i32)
+    }
+}
+
+/// An error which stops a command, telling which exit status it calls for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Failure {
+    /// No tour can visit all the destinations, which is known without searching
+    /// (the reason is given)
+    Infeasible(String),
+    /// The input is invalid, or any other error prevented the command from
+    /// completing
+    Input(String),
+}
+
+impl Failure {
+    /// The exit status of the command stopped by this error
+    pub fn status(&self) -> ExitStatus {
+        match self {
+            Failure::Infeasible(_) => ExitStatus::Infeasible,
+            Failure::Input(_)      => ExitStatus::InputError,
+        }
+    }
+
+    /// Prints this error on stderr and terminates the process with its status
+    pub fn exit(&self) -> ! {
+        eprintln!("{self}");
+        self.status().exit()
+    }
+}
+
+impl Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Failure::Infeasible(reason) => write!(f, "the instance is infeasible: {reason}"),
+            Failure::Input(error)       => write!(f, "{error}"),
+        }
+    }
+}
+
+impl From<String> for Failure {
+    fn from(error: String) -> Self {
+        Failure::Input(error)
+    }
+}
+
+impl From<Failure> for String {
+    fn from(failure: Failure) -> Self {
+        failure.to_string()
+    }
+}
+
+/// Prints the given error on stderr and terminates the process with the status
+/// telling an invalid input
+pub fn fail<E: Display>(error: E) -> ! {
+    eprintln!("{error}");
+    ExitStatus::InputError.exit()
+}
@@ -0,0 +1,61 @@
+//! This module opens the files read and written by the commands. The path `-`
+//! stands for stdin when it is read and for stdout when it is written, which
+//! lets the commands be chained with pipes.
+
+use std::{fs::File, io::{BufRead, BufReader, Read, Write}};
+
+/// The path standing for stdin or stdout
+pub const STDIO: &str = "-";
+
+/// Returns true iff the given path stands for stdin or stdout
+pub fn is_stdio(path: &str) -> bool {
+    path == STDIO
+}
+
+/// The name of the given input in the messages
+pub fn input_name(path: &str) -> &str {
+    if is_stdio(path) { "stdin" } else { path }
+}
+
+/// The name of the given output in the messages
+pub fn output_name(path: &str) -> &str {
+    if is_stdio(path) { "stdout" } else { path }
+}
+
+/// Opens the file at the given path (or stdin) for reading
+pub fn open_input(path: &str) -> Result<Box<dyn BufRead>, String> {
+    if is_stdio(path) {
+        Ok(Box::new(std::io::stdin().lock()))
+    } else {
+        let file = File::open(path).map_err(|e| format!("cannot open {path}: {e}"))?;
+        Ok(Box::new(BufReader::new(file)))
+    }
+}
+
+/// Reads the whole text file at the given path (or stdin)
+pub fn read_input(path: &str) -> Result<String, String> {
+    let mut text = String::new();
+    open_input(path)?.read_to_string(&mut text).map_err(|e| format!("cannot read {}: {e}", input_name(path)))?;
+    Ok(text)
+}
+
+/// Creates the file at the given path (or opens stdout) for writing
+pub fn open_output(path: &str) -> Result<Box<dyn Write>, String> {
+    if is_stdio(path) {
+        Ok(Box::new(std::io::stdout().lock()))
+    } else {
+        let file = File::create(path).map_err(|e| format!("cannot write {path}: {e}"))?;
+        Ok(Box::new(file))
+    }
+}
+
+/// Writes the given text in the file at the given path (or on stdout)
+pub fn write_output(path: &str, text: &str) -> Result<(), String> {
+    open_output(path)?.write_all(text.as_bytes()).map_err(|e| format!("cannot write {}: {e}", output_name(path)))
+}
+
+/// Returns true iff one of the given outputs is stdout: the human readable
+/// messages of the command must then be printed on stderr
+pub fn carries_data(outputs: &[Option<&str>]) -> bool {
+    outputs.iter().flatten().any(|path| is_stdio(path))
+}
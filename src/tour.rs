@@ -0,0 +1,250 @@
+//! This module provides the facilities to read the tours produced by this crate
+//! or by external tools (LKH, Concorde) and to map them onto an instance.
+//!
+//! Internally, a tour is the sequence of the destinations (0-based indices) in
+//! visit order. It starts at the depot (0 unless the instance says otherwise),
+//! visits every destination exactly once and implicitly returns to the depot at
+//! the end.
+
+use std::path::Path;
+
+use serde::{Deserialize, de::IgnoredAny};
+
+use crate::stdio::{input_name, is_stdio, read_input};
+
+/// The fields of a json solve report that are relevant to read its tour
+#[derive(Debug, Deserialize)]
+struct TourRecord {
+    tour: Option<Vec<usize>>,
+    digest: Option<String>,
+    /// Only present when an instance was given instead of a solve report
+    destinations: Option<IgnoredAny>,
+}
+
+/// Reads a tour either from a file (when `spec` is the path of an existing file),
+/// from stdin (when `spec` is `-`) or from the inline list of destinations given
+/// in `spec`
+pub fn read_tour(spec: &str) -> Result<Vec<usize>, String> {
+    read_tour_with_digest(spec).map(|(tour, _)| tour)
+}
+
+/// Reads a tour like `read_tour` and also returns the digest of the instance it
+/// was computed for, when the tour comes from a json solve report
+pub fn read_tour_with_digest(spec: &str) -> Result<(Vec<usize>, Option<String>), String> {
+    if is_stdio(spec) || Path::new(spec).is_file() {
+        let text = read_input(spec)?;
+        parse_tour(&text).map_err(|e| format!("{}: {e}", input_name(spec)))
+    } else {
+        parse_tour(spec)
+    }
+}
+
+/// Parses a tour which is either given as a json solve report, in the TSPLIB
+/// format (1-based indices in a TOUR_SECTION terminated by -1) or as a plain
+/// whitespace separated list of 0-based destination indices
+pub fn parse_tour(text: &str) -> Result<(Vec<usize>, Option<String>), String> {
+    if text.trim_start().starts_with('{') {
+        let record: TourRecord = serde_json::from_str(text).map_err(|e| format!("invalid solve report: {e}"))?;
+        if record.tour.is_none() && record.destinations.is_some() {
+            return Err("this is an instance, not a tour or a solve report".to_string());
+        }
+        let tour = record.tour.ok_or("the solve report holds no tour")?;
+        Ok((tour, record.digest))
+    } else if text.contains("TOUR_SECTION") {
+        parse_tsplib_tour(text).map(|tour| (tour, None))
+    } else {
+        text.split_whitespace()
+            .map(|tok| tok.parse::<usize>().map_err(|_| format!("'{tok}' is not a destination index")))
+            .collect::<Result<Vec<_>, _>>()
+            .map(|tour| (tour, None))
+    }
+}
+
+/// Parses a tour in the TSPLIB format and converts it to 0-based indices. The
+/// TOUR_SECTION must be terminated by -1. When the file declares a DIMENSION,
+/// the nodes are checked to be numbered within it and the tour to match it.
+fn parse_tsplib_tour(text: &str) -> Result<Vec<usize>, String> {
+    let mut dimension = None;
+    let mut lines = text.lines();
+    for line in lines.by_ref() {
+        let line = line.trim();
+        if line.starts_with("TOUR_SECTION") {
+            break;
+        }
+        if let Some((key, value)) = line.split_once(':') {
+            if key.trim() == "DIMENSION" {
+                let value = value.trim();
+                dimension = Some(value.parse::<usize>().map_err(|_| format!("invalid dimension '{value}'"))?);
+            }
+        }
+    }
+
+    let mut tour = vec![];
+    let mut terminated = false;
+    'section: for line in lines {
+        for tok in line.split_whitespace() {
+            if tok == "-1" {
+                terminated = true;
+                break 'section;
+            }
+            if tok == "EOF" {
+                break 'section;
+            }
+            let node = tok.parse::<usize>().map_err(|_| format!("'{tok}' is not a node of the tour"))?;
+            if node == 0 {
+                return Err("the nodes of a TSPLIB tour are numbered from 1".to_string());
+            }
+            if dimension.is_some_and(|dimension| node > dimension) {
+                return Err(format!("node {node} is out of the {} nodes of the tour", dimension.unwrap_or_default()));
+            }
+            tour.push(node - 1);
+        }
+    }
+    if !terminated {
+        return Err("the TOUR_SECTION is not terminated by -1".to_string());
+    }
+
+    if let Some(dimension) = dimension {
+        if dimension != tour.len() {
+            return Err(format!("the tour declares {dimension} nodes but lists {}", tour.len()));
+        }
+    }
+    Ok(tour)
+}
+
+/// Renders a tour in the TSPLIB TOUR format: the 0-based destinations become the
+/// 1-based nodes of the TOUR_SECTION, which starts at the depot and ends with -1.
+/// The tour may skip some destinations (ie when one destination per cluster is
+/// visited), but it must visit each of them at most once.
+pub fn tsplib_tour(name: &str, tour: &[usize], depot: usize, comment: Option<&str>) -> Result<String, String> {
+    let mut tour = tour.to_vec();
+    if tour.len() > 1 && tour.first() == tour.last() {
+        tour.pop();
+    }
+    let mut seen = tour.clone();
+    seen.sort_unstable();
+    if let Some(d) = seen.windows(2).find(|w| w[0] == w[1]) {
+        return Err(format!("the tour visits destination {} more than once", d[0]));
+    }
+    if let Some(start) = tour.iter().position(|d| *d == depot) {
+        tour.rotate_left(start);
+    }
+    let mut text = format!("NAME : {name}\n");
+    if let Some(comment) = comment {
+        text.push_str(&format!("COMMENT : {comment}\n"));
+    }
+    text.push_str(&format!("TYPE : TOUR\nDIMENSION : {}\nTOUR_SECTION\n", tour.len()));
+    for node in tour.iter() {
+        text.push_str(&format!("{}\n", node + 1));
+    }
+    text.push_str("-1\nEOF\n");
+    Ok(text)
+}
+
+/// Checks that a tour which was computed for the instance with the given digest
+/// (if known) can be used with the instance having digest `expected`
+pub fn check_digest(digest: Option<&String>, expected: &str) -> Result<(), String> {
+    match digest {
+        Some(digest) if digest != expected => Err(format!(
+            "the tour was computed for another instance (digest {digest}, expected {expected})")),
+        _ => Ok(()),
+    }
+}
+
+/// Checks that the given tour visits each of the `n` destinations of an
+/// instance exactly once (a final return to the starting destination is
+/// tolerated) and rotates it so that it starts at the given depot.
+pub fn normalize_tour(tour: &[usize], n: usize, depot: usize) -> Result<Vec<usize>, String> {
+    let mut tour = tour.to_vec();
+    if tour.len() == n + 1 && tour.first() == tour.last() {
+        tour.pop();
+    }
+    if tour.len() != n {
+        return Err(format!("the tour visits {} destinations but the instance has {n}", tour.len()));
+    }
+    let mut seen = vec![false; n];
+    for &d in tour.iter() {
+        if d >= n {
+            return Err(format!("the tour visits destination {d} but the instance only has {n} destinations"));
+        }
+        if seen[d] {
+            return Err(format!("the tour visits destination {d} more than once"));
+        }
+        seen[d] = true;
+    }
+    if let Some(start) = tour.iter().position(|d| *d == depot) {
+        tour.rotate_left(start);
+    }
+    Ok(tour)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_written_tour_is_read_back_unchanged() {
+        let tour = vec![0, 3, 1, 4, 2];
+        let text = tsplib_tour("roundtrip", &tour, 0, Some("a comment")).unwrap();
+        let (read, digest) = parse_tour(&text).unwrap();
+        assert_eq!(read, tour);
+        assert_eq!(digest, None);
+    }
+
+    #[test]
+    fn a_written_tour_is_read_back_from_the_depot() {
+        let text = tsplib_tour("roundtrip", &[1, 4, 2, 0, 3], 2, None).unwrap();
+        let (read, _) = parse_tour(&text).unwrap();
+        assert_eq!(read, vec![2, 0, 3, 1, 4]);
+        assert_eq!(normalize_tour(&read, 5, 2).unwrap(), read);
+    }
+
+    /// A TSPLIB tour file listing the given section
+    fn tsplib(section: &str) -> String {
+        format!("NAME : test\nTYPE : TOUR\nTOUR_SECTION\n{section}\nEOF\n")
+    }
+
+    #[test]
+    fn the_nodes_of_a_tsplib_tour_are_read_from_1() {
+        assert_eq!(parse_tour(&tsplib("3 1\n2\n-1")).unwrap().0, vec![2, 0, 1]);
+    }
+
+    #[test]
+    fn a_tour_section_without_terminator_is_rejected() {
+        let err = parse_tour(&tsplib("1\n2\n3")).unwrap_err();
+        assert!(err.contains("not terminated by -1"), "{err}");
+    }
+
+    #[test]
+    fn a_node_out_of_the_dimension_is_rejected() {
+        let text = "NAME : test\nDIMENSION : 3\nTOUR_SECTION\n1\n5\n2\n-1\n";
+        let err = parse_tour(text).unwrap_err();
+        assert!(err.contains("node 5"), "{err}");
+    }
+
+    #[test]
+    fn a_node_out_of_the_instance_is_rejected() {
+        let (tour, _) = parse_tour(&tsplib("1\n2\n5\n-1")).unwrap();
+        let err = normalize_tour(&tour, 3, 0).unwrap_err();
+        assert!(err.contains("destination 4"), "{err}");
+    }
+
+    #[test]
+    fn a_node_0_is_rejected() {
+        let err = parse_tour(&tsplib("0\n1\n2\n-1")).unwrap_err();
+        assert!(err.contains("numbered from 1"), "{err}");
+    }
+
+    #[test]
+    fn a_tour_not_matching_its_dimension_is_rejected() {
+        let text = "NAME : test\nDIMENSION : 4\nTOUR_SECTION\n1\n2\n3\n-1\n";
+        let err = parse_tour(text).unwrap_err();
+        assert!(err.contains("declares 4 nodes but lists 3"), "{err}");
+    }
+
+    #[test]
+    fn a_node_which_is_not_a_number_is_rejected() {
+        let err = parse_tour(&tsplib("1\nx\n-1")).unwrap_err();
+        assert!(err.contains("'x'"), "{err}");
+    }
+}
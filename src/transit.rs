@@ -0,0 +1,350 @@
+//! This module computes the travel times between places by public transport,
+//! from the timetables of a GTFS feed. The journeys leave at a fixed time of
+//! day: they walk to a stop, ride one or more trips (changing at a stop or along
+//! the transfers declared by the feed) and walk from their last stop to their
+//! destination. Their earliest arrivals are found by scanning the connections of
+//! the feed in departure order (the connection scan algorithm).
+
+use std::{collections::{HashMap, HashSet}, fs::read_to_string, path::Path};
+
+use osrm_client::Location;
+
+use crate::{geometry::haversine, instance::Matrix};
+
+/// The walking speed in metres per second
+pub const WALKING_SPEED: f64 = 1.3;
+
+/// A vehicle going from one stop to the next one of its trip
+#[derive(Debug, Clone, Copy)]
+struct Connection {
+    from: usize,
+    to: usize,
+    /// The departure time from `from` in seconds after midnight
+    departure: u32,
+    /// The arrival time at `to` in seconds after midnight
+    arrival: u32,
+    trip: usize,
+}
+
+/// The timetables of a GTFS feed
+#[derive(Debug, Clone)]
+pub struct Feed {
+    stops: Vec<Location>,
+    /// The connections of all the trips, sorted by departure time
+    connections: Vec<Connection>,
+    /// The transfers leaving each stop: the stop they lead to and their duration
+    /// in seconds
+    transfers: Vec<Vec<(usize, u32)>>,
+    nb_trips: usize,
+}
+
+/// A csv table of the feed whose columns are looked up by name
+struct Table {
+    path: String,
+    header: Vec<String>,
+    text: String,
+}
+
+impl Table {
+    /// Reads the given table of the feed in the given directory, none when the
+    /// feed does not have it
+    fn read(dir: &str, name: &str) -> Result<Option<Self>, String> {
+        let path = Path::new(dir).join(name).to_string_lossy().to_string();
+        if !Path::new(&path).exists() {
+            return Ok(None);
+        }
+        let text = read_to_string(&path).map_err(|e| format!("cannot read {path}: {e}"))?;
+        let header = text.lines().next().map(|line| fields(line.trim_start_matches('\u{feff}'))).unwrap_or_default();
+        Ok(Some(Table { path, header, text }))
+    }
+
+    /// Reads the given table of the feed, which must have it
+    fn require(dir: &str, name: &str) -> Result<Self, String> {
+        Self::read(dir, name)?.ok_or_else(|| format!("the GTFS feed in {dir} has no {name}"))
+    }
+
+    /// The index of the given column
+    fn column(&self, name: &str) -> Result<usize, String> {
+        self.header.iter().position(|h| h == name).ok_or_else(|| format!("{} has no {name} column", self.path))
+    }
+
+    /// The rows of the table
+    fn rows(&self) -> impl Iterator<Item = Row<'_>> + '_ {
+        self.text.lines().enumerate().skip(1)
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| Row { table: self, line: i + 1, fields: fields(line) })
+    }
+}
+
+/// A row of a table of the feed
+struct Row<'a> {
+    table: &'a Table,
+    /// The number of the line holding the row in the file
+    line: usize,
+    fields: Vec<String>,
+}
+
+impl Row<'_> {
+    /// The field of the given column, which the row may lack
+    fn get(&self, column: usize) -> Option<&str> {
+        self.fields.get(column).map(String::as_str)
+    }
+
+    /// The field of the given column, which the row must have
+    fn field(&self, column: usize) -> Result<&str, String> {
+        self.get(column).ok_or_else(|| format!("{}: line {} has no {} field ({} fields for {} columns)",
+            self.table.path, self.line, self.table.header[column], self.fields.len(), self.table.header.len()))
+    }
+}
+
+/// Splits a csv line into its fields, which may be quoted (a quote being
+/// escaped by doubling it)
+fn fields(line: &str) -> Vec<String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields.iter().map(|f| f.trim().to_string()).collect()
+}
+
+/// Parses a time of day given as `HH:MM` or `HH:MM:SS` in seconds after
+/// midnight. The hours may exceed 23 for the trips which end after midnight.
+pub fn parse_time(text: &str) -> Result<u32, String> {
+    let parts = text.trim().split(':').map(|p| p.parse::<u32>()).collect::<Result<Vec<_>, _>>()
+        .map_err(|_| format!("invalid time '{text}'"))?;
+    match parts.as_slice() {
+        [h, m] if *m < 60         => Ok(h * 3600 + m * 60),
+        [h, m, s] if *m < 60 && *s < 60 => Ok(h * 3600 + m * 60 + s),
+        _ => Err(format!("invalid time '{text}', expected HH:MM or HH:MM:SS")),
+    }
+}
+
+/// Returns the day of the week (0 for monday) of the given date (YYYYMMDD)
+fn weekday(date: &str) -> Result<usize, String> {
+    let invalid = || format!("invalid date '{date}', expected YYYYMMDD");
+    if date.len() != 8 {
+        return Err(invalid());
+    }
+    let year = date[0..4].parse::<i64>().map_err(|_| invalid())?;
+    let month = date[4..6].parse::<i64>().map_err(|_| invalid())?;
+    let day = date[6..8].parse::<i64>().map_err(|_| invalid())?;
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days_in_month = match month {
+        2 if leap      => 29,
+        2              => 28,
+        4 | 6 | 9 | 11 => 30,
+        1..=12         => 31,
+        _              => return Err(invalid()),
+    };
+    if !(1..=days_in_month).contains(&day) {
+        return Err(invalid());
+    }
+    // the number of days since 1970-01-01 (a thursday), after the days from
+    // civil algorithm of Howard Hinnant
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + doe - 719_468;
+    Ok((days + 3).rem_euclid(7) as usize)
+}
+
+/// The time taken to walk the given distance (in metres) in seconds
+fn walking_time(distance: f64) -> u32 {
+    (distance / WALKING_SPEED).ceil() as u32
+}
+
+impl Feed {
+    /// Reads the GTFS feed in the given directory (an unzipped feed). When a date
+    /// (YYYYMMDD) is given, only the trips of the services running that day are
+    /// kept, otherwise all the trips of the feed are.
+    pub fn load(dir: &str, date: Option<&str>) -> Result<Self, String> {
+        let table = Table::require(dir, "stops.txt")?;
+        let (id, lat, lon) = (table.column("stop_id")?, table.column("stop_lat")?, table.column("stop_lon")?);
+        let mut index = HashMap::new();
+        let mut stops = vec![];
+        for row in table.rows() {
+            let coordinate = |i: usize| row.get(i).and_then(|v| v.parse::<f32>().ok());
+            // the entrances and generic nodes of the stations may have no coordinates
+            if let (Some(latitude), Some(longitude)) = (coordinate(lat), coordinate(lon)) {
+                index.insert(row.field(id)?.to_string(), stops.len());
+                stops.push(Location { longitude, latitude });
+            }
+        }
+
+        let services = date.map(|date| Self::services(dir, date)).transpose()?;
+        let trips = match services.as_ref() {
+            None => None,
+            Some(services) => {
+                let table = Table::require(dir, "trips.txt")?;
+                let (trip, service) = (table.column("trip_id")?, table.column("service_id")?);
+                let mut trips = HashSet::new();
+                for row in table.rows() {
+                    if services.contains(row.field(service)?) {
+                        trips.insert(row.field(trip)?.to_string());
+                    }
+                }
+                Some(trips)
+            }
+        };
+
+        let table = Table::require(dir, "stop_times.txt")?;
+        let (trip, stop) = (table.column("trip_id")?, table.column("stop_id")?);
+        let (arrival, departure, sequence) = (table.column("arrival_time")?, table.column("departure_time")?, table.column("stop_sequence")?);
+        let mut calls: HashMap<String, Vec<(u32, usize, u32, u32)>> = HashMap::new();
+        for row in table.rows() {
+            let trip = row.field(trip)?;
+            if trips.as_ref().is_some_and(|trips| !trips.contains(trip)) {
+                continue;
+            }
+            // the stops without times are only passed by, they are skipped
+            let (Ok(arrival), Ok(departure)) = (parse_time(row.field(arrival)?), parse_time(row.field(departure)?)) else {
+                continue;
+            };
+            let stop = row.field(stop)?;
+            let stop = *index.get(stop).ok_or_else(|| format!("{}: line {}: unknown stop {stop}", table.path, row.line))?;
+            let sequence = row.field(sequence)?;
+            let sequence = sequence.parse::<u32>().map_err(|_| format!("{}: line {}: invalid stop sequence '{sequence}'", table.path, row.line))?;
+            calls.entry(trip.to_string()).or_default().push((sequence, stop, arrival, departure));
+        }
+        let nb_trips = calls.len();
+        let mut connections = vec![];
+        for (trip, mut calls) in calls.into_values().enumerate() {
+            calls.sort_unstable_by_key(|call| call.0);
+            for pair in calls.windows(2) {
+                connections.push(Connection { from: pair[0].1, to: pair[1].1, departure: pair[0].3, arrival: pair[1].2.max(pair[0].3), trip });
+            }
+        }
+        connections.sort_by_key(|c| (c.departure, c.arrival));
+
+        let mut transfers = vec![vec![]; stops.len()];
+        if let Some(table) = Table::read(dir, "transfers.txt")? {
+            let (from, to) = (table.column("from_stop_id")?, table.column("to_stop_id")?);
+            let (kind, time) = (table.column("transfer_type").ok(), table.column("min_transfer_time").ok());
+            for row in table.rows() {
+                let (Some(&a), Some(&b)) = (index.get(row.field(from)?), index.get(row.field(to)?)) else {
+                    continue;
+                };
+                // a transfer of type 3 is not possible
+                if a == b || kind.and_then(|k| row.get(k)).is_some_and(|k| k == "3") {
+                    continue;
+                }
+                let duration = time.and_then(|t| row.get(t)).and_then(|t| t.parse::<u32>().ok())
+                    .unwrap_or_else(|| walking_time(haversine(stops[a], stops[b])));
+                transfers[a].push((b, duration));
+            }
+        }
+        Ok(Feed { stops, connections, transfers, nb_trips })
+    }
+
+    /// Returns the services running on the given date (YYYYMMDD) after the
+    /// calendar of the feed and its exceptions
+    fn services(dir: &str, date: &str) -> Result<HashSet<String>, String> {
+        let day = ["monday", "tuesday", "wednesday", "thursday", "friday", "saturday", "sunday"][weekday(date)?];
+        let mut services = HashSet::new();
+        let calendar = Table::read(dir, "calendar.txt")?;
+        let exceptions = Table::read(dir, "calendar_dates.txt")?;
+        if calendar.is_none() && exceptions.is_none() {
+            return Err(format!("the GTFS feed in {dir} has neither calendar.txt nor calendar_dates.txt"));
+        }
+        if let Some(table) = calendar {
+            let (service, runs) = (table.column("service_id")?, table.column(day)?);
+            let (start, end) = (table.column("start_date")?, table.column("end_date")?);
+            for row in table.rows() {
+                if row.field(runs)? == "1" && row.field(start)? <= date && date <= row.field(end)? {
+                    services.insert(row.field(service)?.to_string());
+                }
+            }
+        }
+        if let Some(table) = exceptions {
+            let (service, on, kind) = (table.column("service_id")?, table.column("date")?, table.column("exception_type")?);
+            for row in table.rows() {
+                if row.field(on)? != date {
+                    continue;
+                }
+                match row.field(kind)? {
+                    "1" => { services.insert(row.field(service)?.to_string()); }
+                    "2" => { services.remove(row.field(service)?); }
+                    _ => {}
+                }
+            }
+        }
+        Ok(services)
+    }
+
+    /// Returns the number of stops and of trips of the feed
+    pub fn size(&self) -> (usize, usize) {
+        (self.stops.len(), self.nb_trips)
+    }
+
+    /// Computes the travel times (in seconds) of the earliest journeys between the
+    /// given places which leave at `departure` (in seconds after midnight). A
+    /// journey walks at most `max_walk` metres to its first stop, from its last
+    /// stop, or straight to its destination. The places which no journey connects
+    /// are unreachable from one another (their travel time is infinite).
+    pub fn travel_times(&self, places: &[Location], departure: u32, max_walk: f64) -> Matrix {
+        // the stops within walking distance of each place, along with the time
+        // taken to walk to them
+        let nearby = places.iter()
+            .map(|place| self.stops.iter().enumerate()
+                .map(|(s, stop)| (s, haversine(*place, *stop)))
+                .filter(|(_, distance)| *distance <= max_walk)
+                .map(|(s, distance)| (s, walking_time(distance)))
+                .collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let first = self.connections.partition_point(|c| c.departure < departure);
+
+        let mut times = Matrix::from(vec![vec![f32::INFINITY; places.len()]; places.len()]);
+        for (i, origin) in places.iter().enumerate() {
+            let mut arrival = vec![u32::MAX; self.stops.len()];
+            let mut boarded = vec![false; self.nb_trips];
+            for &(stop, walk) in nearby[i].iter() {
+                self.reach(&mut arrival, stop, departure + walk);
+            }
+            for c in self.connections[first..].iter() {
+                if boarded[c.trip] || arrival[c.from] <= c.departure {
+                    boarded[c.trip] = true;
+                    self.reach(&mut arrival, c.to, c.arrival);
+                }
+            }
+
+            for (j, destination) in places.iter().enumerate() {
+                let distance = haversine(*origin, *destination);
+                let walk = (distance <= max_walk).then(|| departure + walking_time(distance));
+                let ride = nearby[j].iter()
+                    .filter(|(stop, _)| arrival[*stop] != u32::MAX)
+                    .map(|(stop, walk)| arrival[*stop] + walk)
+                    .min();
+                if let Some(end) = walk.into_iter().chain(ride).min() {
+                    times[i][j] = if i == j { 0.0 } else { (end - departure) as f32 };
+                }
+            }
+        }
+        times
+    }
+
+    /// Records that the given stop is reached at the given time, and the stops
+    /// its transfers lead to a bit later, when this improves their arrival
+    fn reach(&self, arrival: &mut [u32], stop: usize, time: u32) {
+        if time >= arrival[stop] {
+            return;
+        }
+        arrival[stop] = time;
+        for &(other, duration) in self.transfers[stop].iter() {
+            arrival[other] = arrival[other].min(time + duration);
+        }
+    }
+}
@@ -0,0 +1,365 @@
+//! This module implements the command which runs the solver with all the
+//! combinations of a grid of settings and ranks the resulting configurations.
+
+use std::{fs::{OpenOptions, read_to_string}, io::Write, path::Path, sync::{Mutex, atomic::{AtomicUsize, Ordering}}, time::Instant};
+
+use clap::{Args, ValueEnum};
+use serde::{Deserialize, Serialize};
+
+use crate::{resolution::{self, SolveOptions, SolveReport, WidthPolicy, FrontierKind, MergePolicy, RelaxVariant}, results::{self, ResultRow}, status::fail};
+
+/// The header of the csv file where the results of the runs are written
+/// (the width schedule comes last since its expressions may hold commas)
+const HEADER: &str = "instance,width,width_policy,frontier,relax,merge_policy,run,is_exact,value,bound,unit,time,width_schedule";
+
+/// This command runs the solver on one instance (or all the instances of a
+/// directory) with every combination of the settings of a grid. The results of
+/// the runs are appended to a csv file, and the combinations which already
+/// appear in that file are skipped so that an interrupted sweep can be resumed.
+#[derive(Debug, Args)]
+pub struct Tune {
+    /// The path to the instance file or to a directory of instances
+    #[clap(short, long)]
+    pub instance: String,
+    /// The path to a json file giving the values to try for each setting, ie
+    /// `{"width": [100, 500, 2000], "width_policy": ["fixed", "state-size"], "width_schedule": [null, "min(50+10*d, 2000)"], "frontier": ["simple", "no-dup"], "relax": ["plain", "corrected"], "merge_policy": ["union-current", "representative-current"]}`.
+    /// The settings that are left out keep their default value. A width schedule
+    /// (an expression or a file, see solve) overrides the width and its policy,
+    /// `null` stands for no schedule.
+    #[clap(short, long)]
+    pub grid: String,
+    /// The time limit of each run (in seconds, 0 means no time limit)
+    #[clap(short, long, default_value="60")]
+    pub timeout: u64,
+    /// The number of times each configuration is run (the parallel solver does
+    /// not explore the nodes in the same order from one run to the next)
+    #[clap(long, default_value="1")]
+    pub repeats: usize,
+    /// The number of runs executed at the same time
+    #[clap(long, default_value="1")]
+    pub parallel_runs: usize,
+    /// The csv file where the results of the runs are written
+    #[clap(short, long, default_value="tune.csv")]
+    pub output: String,
+    /// If present, a csv or parquet file where the full results of the runs are
+    /// appended
+    #[clap(long)]
+    pub results_file: Option<String>,
+}
+
+/// The values to try for each setting of the solver
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Grid {
+    #[serde(default = "default_widths")]
+    width: Vec<usize>,
+    #[serde(default = "default_width_policies")]
+    width_policy: Vec<WidthPolicy>,
+    #[serde(default = "default_width_schedules")]
+    width_schedule: Vec<Option<String>>,
+    #[serde(default = "default_frontiers")]
+    frontier: Vec<FrontierKind>,
+    #[serde(default = "default_relaxations")]
+    relax: Vec<RelaxVariant>,
+    #[serde(default = "default_merge_policies")]
+    merge_policy: Vec<MergePolicy>,
+}
+
+fn default_widths() -> Vec<usize> {
+    vec![100]
+}
+fn default_width_policies() -> Vec<WidthPolicy> {
+    vec![WidthPolicy::Fixed]
+}
+fn default_width_schedules() -> Vec<Option<String>> {
+    vec![None]
+}
+fn default_frontiers() -> Vec<FrontierKind> {
+    vec![FrontierKind::Simple]
+}
+fn default_relaxations() -> Vec<RelaxVariant> {
+    vec![RelaxVariant::Plain]
+}
+fn default_merge_policies() -> Vec<MergePolicy> {
+    vec![MergePolicy::UnionCurrent]
+}
+
+/// One combination of settings to try on an instance
+#[derive(Debug, Clone, PartialEq)]
+struct Configuration {
+    instance: String,
+    width: usize,
+    width_policy: WidthPolicy,
+    width_schedule: Option<String>,
+    frontier: FrontierKind,
+    relax: RelaxVariant,
+    merge_policy: MergePolicy,
+    /// The index of the run among the repeats of the configuration
+    run: usize,
+}
+
+/// The outcome of the run of one configuration (one row of the csv file)
+#[derive(Debug, Clone, Serialize)]
+struct TuneRecord {
+    instance: String,
+    width: usize,
+    width_policy: String,
+    frontier: String,
+    relax: String,
+    merge_policy: String,
+    run: usize,
+    is_exact: bool,
+    value: Option<f64>,
+    bound: Option<f64>,
+    unit: String,
+    /// The wall clock time of the run in seconds
+    time: f64,
+    /// The width schedule (empty when there is none)
+    width_schedule: String,
+}
+
+impl Tune {
+    /// Executes this command. In quiet mode, the best configuration of each
+    /// instance is printed as json.
+    pub async fn execute(&self, quiet: bool) {
+        match self.tune() {
+            Ok(best) if quiet => println!("{}", serde_json::to_string(&best).unwrap()),
+            Ok(best) => {
+                for record in best {
+                    let schedule = Some(&record.width_schedule).filter(|s| !s.is_empty()).map(|s| format!(" width-schedule {s}")).unwrap_or_default();
+                    println!("best configuration for {}: width {} width-policy {}{schedule} frontier {} relax {} merge-policy {} (exact {}, value {}, {:.3} s)",
+                        record.instance, record.width, record.width_policy, record.frontier, record.relax, record.merge_policy, record.is_exact,
+                        record.value.map(|v| format!("{v:.3} {}", record.unit)).unwrap_or("none".to_string()), record.time);
+                }
+            }
+            Err(e) => fail(e),
+        }
+    }
+
+    fn tune(&self) -> Result<Vec<TuneRecord>, String> {
+        if self.parallel_runs == 0 || self.repeats == 0 {
+            return Err("the number of parallel runs and of repeats must be positive".to_string());
+        }
+        let text = read_to_string(&self.grid).map_err(|e| format!("cannot read {}: {e}", self.grid))?;
+        let grid: Grid = serde_json::from_str(&text).map_err(|e| format!("invalid grid {}: {e}", self.grid))?;
+
+        let mut records = self.read_records()?;
+        let configurations = self.configurations(&grid)?;
+        let total = configurations.len();
+        let todo = configurations.into_iter()
+            .filter(|c| !records.iter().any(|r| c.matches(r)))
+            .collect::<Vec<_>>();
+        if todo.len() < total {
+            eprintln!("skipping the {} configurations already in {}", total - todo.len(), self.output);
+        }
+
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.output)
+            .map_err(|e| format!("cannot open {}: {e}", self.output))?;
+        if records.is_empty() && file.metadata().map(|m| m.len() == 0).unwrap_or(true) {
+            writeln!(file, "{HEADER}").map_err(|e| format!("cannot write {}: {e}", self.output))?;
+        }
+
+        let results = self.results_file.as_deref().map(results::open).transpose()?;
+        let next = AtomicUsize::new(0);
+        let sink = Mutex::new((file, Vec::new(), results));
+        std::thread::scope(|scope| {
+            for _ in 0..self.parallel_runs.min(todo.len()) {
+                scope.spawn(|| {
+                    while let Some(configuration) = todo.get(next.fetch_add(1, Ordering::Relaxed)) {
+                        let (record, report) = match self.run(configuration) {
+                            Ok(outcome) => outcome,
+                            Err(e) => {
+                                eprintln!("warning: {}: {e}", configuration.instance);
+                                continue;
+                            }
+                        };
+                        let mut sink = sink.lock().unwrap();
+                        if let Err(e) = writeln!(sink.0, "{}", record.to_csv()) {
+                            eprintln!("warning: cannot write {}: {e}", self.output);
+                        }
+                        sink.1.push(record);
+                        if let Some(Err(e)) = sink.2.as_mut().map(|r| r.write(&[ResultRow::from(&report)])) {
+                            eprintln!("warning: {e}");
+                        }
+                    }
+                });
+            }
+        });
+        let (_, done, results) = sink.into_inner().unwrap();
+        records.extend(done);
+        if let Some(mut results) = results {
+            results.close()?;
+        }
+
+        Ok(best_records(records))
+    }
+
+    /// Returns all the combinations of the settings of the grid for all the
+    /// instances to tune
+    fn configurations(&self, grid: &Grid) -> Result<Vec<Configuration>, String> {
+        let mut configurations = vec![];
+        for instance in self.instances()? {
+            for width in grid.width.iter() {
+                for width_policy in grid.width_policy.iter() {
+                    for width_schedule in grid.width_schedule.iter() {
+                        for frontier in grid.frontier.iter() {
+                            for relax in grid.relax.iter() {
+                                for merge_policy in grid.merge_policy.iter() {
+                                    for run in 0..self.repeats {
+                                        configurations.push(Configuration {
+                                            instance: instance.clone(),
+                                            width: *width,
+                                            width_policy: *width_policy,
+                                            width_schedule: width_schedule.clone(),
+                                            frontier: *frontier,
+                                            relax: *relax,
+                                            merge_policy: *merge_policy,
+                                            run,
+                                        });
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Ok(configurations)
+    }
+
+    /// Returns the paths to the instances to tune: the instance itself or all the
+    /// json files of the directory
+    fn instances(&self) -> Result<Vec<String>, String> {
+        if !Path::new(&self.instance).is_dir() {
+            return Ok(vec![self.instance.clone()]);
+        }
+        let entries = std::fs::read_dir(&self.instance).map_err(|e| format!("cannot read {}: {e}", self.instance))?;
+        let mut instances = entries.filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.extension().is_some_and(|ext| ext == "json"))
+            .map(|p| p.to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        instances.sort();
+        Ok(instances)
+    }
+
+    /// Solves the instance with the given configuration
+    fn run(&self, configuration: &Configuration) -> Result<(TuneRecord, SolveReport), String> {
+        let options = SolveOptions::builder()
+            .width(configuration.width)
+            .width_policy(configuration.width_policy)
+            .width_schedule(configuration.width_schedule.clone())
+            .frontier(configuration.frontier)
+            .relax(configuration.relax)
+            .merge_policy(configuration.merge_policy)
+            .timeout(self.timeout)
+            .build()?;
+
+        let start = Instant::now();
+        let report = resolution::solve(&configuration.instance, &options)?;
+        let record = TuneRecord {
+            instance: configuration.instance.clone(),
+            width: configuration.width,
+            width_policy: name(configuration.width_policy),
+            frontier: name(configuration.frontier),
+            relax: name(configuration.relax),
+            merge_policy: name(configuration.merge_policy),
+            run: configuration.run,
+            is_exact: report.is_exact,
+            value: report.value,
+            bound: report.bound,
+            unit: report.unit.clone(),
+            time: start.elapsed().as_secs_f64(),
+            width_schedule: configuration.width_schedule.clone().unwrap_or_default(),
+        };
+        Ok((record, report))
+    }
+
+    /// Reads the records already present in the output file, if any
+    fn read_records(&self) -> Result<Vec<TuneRecord>, String> {
+        if !Path::new(&self.output).exists() {
+            return Ok(vec![]);
+        }
+        let text = read_to_string(&self.output).map_err(|e| format!("cannot read {}: {e}", self.output))?;
+        text.lines()
+            .skip_while(|line| line.starts_with("instance,"))
+            .filter(|line| !line.trim().is_empty())
+            .enumerate()
+            .map(|(i, line)| TuneRecord::from_csv(line).ok_or_else(|| format!("invalid record on line {} of {}", i + 2, self.output)))
+            .collect()
+    }
+}
+
+impl Configuration {
+    /// Returns true iff the given record is the outcome of this configuration
+    fn matches(&self, record: &TuneRecord) -> bool {
+        self.instance == record.instance
+            && self.width == record.width
+            && name(self.width_policy) == record.width_policy
+            && self.width_schedule.as_deref().unwrap_or_default() == record.width_schedule
+            && name(self.frontier) == record.frontier
+            && name(self.relax) == record.relax
+            && name(self.merge_policy) == record.merge_policy
+            && self.run == record.run
+    }
+}
+
+impl TuneRecord {
+    fn to_csv(&self) -> String {
+        let optional = |v: Option<f64>| v.map(|v| v.to_string()).unwrap_or_default();
+        format!("{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            self.instance, self.width, self.width_policy, self.frontier, self.relax, self.merge_policy, self.run, self.is_exact,
+            optional(self.value), optional(self.bound), self.unit, self.time, self.width_schedule)
+    }
+
+    /// Parses a row of the csv file. The rows written before the relaxation, the
+    /// merge policy and the width schedule were tuned lack their columns: they
+    /// used the defaults. The width schedule is the rest of the row.
+    fn from_csv(line: &str) -> Option<Self> {
+        let mut fields = line.splitn(13, ',').collect::<Vec<_>>();
+        if fields.len() == 10 {
+            fields.insert(4, "plain");
+        }
+        if fields.len() == 11 {
+            fields.insert(5, "union-current");
+        }
+        if fields.len() == 12 {
+            fields.push("");
+        }
+        if fields.len() != 13 {
+            return None;
+        }
+        let optional = |v: &str| if v.is_empty() { Some(None) } else { v.parse().ok().map(Some) };
+        Some(TuneRecord {
+            instance: fields[0].to_string(),
+            width: fields[1].parse().ok()?,
+            width_policy: fields[2].to_string(),
+            frontier: fields[3].to_string(),
+            relax: fields[4].to_string(),
+            merge_policy: fields[5].to_string(),
+            run: fields[6].parse().ok()?,
+            is_exact: fields[7].parse().ok()?,
+            value: optional(fields[8])?,
+            bound: optional(fields[9])?,
+            unit: fields[10].to_string(),
+            time: fields[11].parse().ok()?,
+            width_schedule: fields[12].to_string(),
+        })
+    }
+}
+
+/// Returns the name of a setting as it is given on the command line
+fn name<T: ValueEnum>(value: T) -> String {
+    value.to_possible_value().map(|v| v.get_name().to_string()).unwrap_or_default()
+}
+
+/// Returns the best record of each instance: the exact runs come first, then
+/// the shortest tours and then the fastest runs
+fn best_records(mut records: Vec<TuneRecord>) -> Vec<TuneRecord> {
+    records.sort_by(|a, b| a.instance.cmp(&b.instance)
+        .then_with(|| b.is_exact.cmp(&a.is_exact))
+        .then_with(|| a.value.unwrap_or(f64::INFINITY).total_cmp(&b.value.unwrap_or(f64::INFINITY)))
+        .then_with(|| a.time.total_cmp(&b.time)));
+    records.dedup_by(|a, b| a.instance == b.instance);
+    records
+}
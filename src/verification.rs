@@ -0,0 +1,72 @@
+//! This module implements the command which checks that a tour is a valid
+//! solution of an instance and reports its actual length.
+
+use clap::Args;
+use serde_json::json;
+
+use crate::{instance::{Instance, Unit}, status::fail, tour::{read_tour_with_digest, normalize_tour, check_digest}};
+
+/// This command checks that a tour (in TSPLIB format or as a list of 0-based
+/// destination indices) is a valid solution of an instance and prints its length.
+#[derive(Debug, Args)]
+pub struct Verify {
+    /// The path to the instance file
+    #[clap(short, long)]
+    pub instance: String,
+    /// The tour to verify (path to a tour file or inline list of destinations)
+    #[clap(short, long)]
+    pub tour: String,
+    /// Overrides the unit of the distance matrix declared by the instance
+    #[clap(long, value_enum)]
+    pub unit: Option<Unit>,
+    /// Drops the destinations of the instance which have no row in its distance
+    /// matrix rather than rejecting it
+    #[clap(long)]
+    pub truncate_to_matrix: bool,
+}
+
+impl Verify {
+    /// Executes this command. In quiet mode, the outcome is printed as json.
+    pub async fn execute(&self, quiet: bool) {
+        if let Err(e) = self.verify(quiet) {
+            fail(e);
+        }
+    }
+
+    fn verify(&self, quiet: bool) -> Result<(), String> {
+        let instance = Instance::load_with(&self.instance, self.truncate_to_matrix)?;
+        let (tour, digest) = read_tour_with_digest(&self.tour)?;
+        check_digest(digest.as_ref(), &instance.digest())?;
+        let tour = normalize_tour(&tour, instance.distances.len(), instance.depot)?;
+        if instance.distances.len() <= 2 {
+            eprintln!("warning: the instance is trivial, it has {} destination(s) and thus a single tour", instance.distances.len());
+        }
+
+        if let Some((from, to)) = instance.disallowed_leg(&tour) {
+            return Err(format!("the tour uses the leg from {from} to {to} which is forbidden or contradicts a forced arc"));
+        }
+        let length = instance.tour_length(&tour);
+        if !length.is_finite() {
+            return Err("the tour uses a leg between two destinations that cannot reach one another".to_string());
+        }
+        let unit = self.unit.unwrap_or(instance.metadata.unit);
+        let (length, symbol) = unit.report(length);
+        let (distance, duration) = instance.tour_totals(&tour, unit);
+        if quiet {
+            let mut outcome = json!({"valid": true, "length": length, "unit": symbol});
+            if let Some(distance) = distance {
+                outcome["distance"] = json!(distance);
+            }
+            if let Some(duration) = duration {
+                outcome["duration"] = json!(duration);
+            }
+            println!("{outcome}");
+        } else {
+            println!("valid tour of length {length:.3} {symbol}");
+            if let Some(duration) = duration {
+                println!("duration {duration:.3} h");
+            }
+        }
+        Ok(())
+    }
+}
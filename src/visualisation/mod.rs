@@ -1,14 +1,15 @@
 //! This module implements the visualisation facilities that can be used to generate an
 //! html file depicting the instance (and a possible solution of that instance).
-use std::{io::{BufReader, Write}, fs::File};
+use std::{io::Write, fs::{File, read_to_string}, collections::BTreeMap, path::Path};
 
 use clap::Args;
 use handlebars::no_escape;
-use osrm_client::{Route, RouteRequestBuilder, Geometries, OverviewRequest, Client};
+use osrm_client::{Route, RouteRequestBuilder, Geometries, OverviewRequest, Client, GeoJsonGeometry, GeoJsonPoint, Location};
 use rand_distr::num_traits::ToPrimitive;
-use serde_json::json;
+use serde::{Serialize, Deserialize};
+use serde_json::{json, Value};
 
-use crate::instance::Instance;
+use crate::{instance::Instance, status::fail, stdio::{STDIO, is_stdio, write_output}, tour::{read_tour, normalize_tour}};
 
 /// This command lets you generate an html file to visualize a given instance
 /// and an optional solution.
@@ -17,79 +18,289 @@ pub struct Visualize {
     /// The path to the instance file
     #[clap(short, long)]
     pub instance: String,
-    /// A possible solution (sequence of destination identifiers 0..n)
+    /// A possible solution: a tour file (TSPLIB format or list of destinations) or
+    /// an inline sequence of destination identifiers 0..n, optionally prefixed by
+    /// a name as in `name=path`. Can be repeated: each tour gets its own layer.
     #[clap(short, long)]
-    pub solution: Option<String>,
+    pub solution: Vec<String>,
+    /// Adds a layer highlighting the edges used by exactly one of the two named
+    /// solutions (given as `a,b`)
+    #[clap(short, long)]
+    pub difference: Option<String>,
     /// If present, the path where to write the output html
     #[clap(short, long)]
     pub output: Option<String>,
     /// URL of the osrm server to use (optional)
     #[clap(short, long)]
     pub url_osrm: Option<String>,
+    /// Route each leg of the solution separately and cache the road geometries of
+    /// the legs in this file. The legs that cannot be routed are drawn as straight
+    /// lines instead of failing the whole visualisation.
+    #[clap(long)]
+    pub leg_cache: Option<String>,
+    /// A csv trace of the search (time, incumbent and bound columns) to chart below
+    /// the map. The trace may start with `# digest: <digest>` comment lines.
+    #[clap(long)]
+    pub trace: Option<String>,
+    /// Use a logarithmic scale for the values of the trace chart
+    #[clap(long)]
+    pub trace_log: bool,
+}
+
+/// The road geometry of a leg between two destinations
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Leg {
+    distance: f32,
+    duration: f32,
+    geometry: GeoJsonGeometry,
 }
+
+/// A solution drawn on the map
+#[derive(Debug, Clone)]
+struct DrawnTour {
+    name: String,
+    /// The closed tour (the depot is repeated at the end)
+    tour: Vec<usize>,
+    /// The road distance (in metres) of the drawn route
+    distance: f32,
+    /// The road duration (in seconds) of the drawn route
+    duration: f32,
+    /// The geojson geometry of the drawn route
+    route: Value,
+    /// The solve report the tour was read from (if any)
+    report: Option<Value>,
+}
+
+/// The summary of a solution drawn on the map
+#[derive(Debug, Serialize)]
+struct DrawnSummary {
+    name: String,
+    /// The road distance (in metres) of the drawn route
+    distance: f32,
+    /// The road duration (in seconds) of the drawn route
+    duration: f32,
+}
+
+/// The colors of the successive solution layers
+const PALETTE: [&str; 8] = ["red", "#1b9e77", "#7570b3", "#e7298a", "#66a61e", "#e6ab02", "#a6761d", "#666666"];
+
 impl Visualize {
-    /// Executes this command
-    pub async fn execute(&self) {
-        let instance = serde_json::from_reader(BufReader::new(File::open(&self.instance).unwrap())).unwrap();
-        
-        let html = if let Some(solution) = self.solution.as_ref() {
+    /// Executes this command. The html is the only output on stdout. When it is
+    /// written to a file, the drawn solutions are summarised instead (as json in
+    /// quiet mode).
+    pub async fn execute(&self, quiet: bool) {
+        let output = self.output.as_deref().unwrap_or(STDIO);
+        match self.render(output).await {
+            Ok(_) if is_stdio(output) => {}
+            Ok(drawn) if quiet => println!("{}", json!({"output": output, "solutions": drawn})),
+            Ok(drawn) => {
+                println!("wrote the visualisation to {output}");
+                for tour in drawn {
+                    println!("{}: {:.2} km, {}", tour.name, tour.distance / 1000.0, format_duration(tour.duration));
+                }
+            }
+            Err(e) => fail(e),
+        }
+    }
+
+    /// Renders the visualisation to the given output and returns the summaries
+    /// of the drawn solutions
+    async fn render(&self, output: &str) -> Result<Vec<DrawnSummary>, String> {
+        let instance = Instance::load(&self.instance)?;
+        if !instance.has_coordinates() {
+            return Err(format!("{} has no coordinates to draw on a map (it is anonymized or was built from a bare matrix)", self.instance));
+        }
+
+        let trace = self.trace.as_deref().map(|path| self.trace_chart(path, &instance)).transpose()?;
+        let mut drawn = vec![];
+        let html = if self.solution.is_empty() {
+            self.visualize(&instance, trace).await
+        } else {
             let mut client = osrm_client::Client::default();
             if let Some(url) = self.url_osrm.as_ref() {
                 client = client.base_url(url.clone());
             }
-            let solution = solution.split_whitespace().into_iter().map(|tok| tok.parse::<usize>().unwrap()).collect::<Vec<_>>();
-            let route = self.solution_route(&client, &instance, &solution).await;
-            self.visualize_solution(&instance, &route).await
-        } else {
-            self.visualize(&instance).await
+            let mut tours = vec![];
+            for (i, spec) in self.solution.iter().enumerate() {
+                let (name, spec) = match spec.split_once('=') {
+                    Some((name, spec)) => (name.to_string(), spec),
+                    None if self.solution.len() == 1 => ("solution".to_string(), spec.as_str()),
+                    None => (format!("solution {}", i + 1), spec.as_str()),
+                };
+                if tours.iter().any(|t: &DrawnTour| t.name == name) {
+                    return Err(format!("there are several solutions named '{name}'"));
+                }
+                let mut tour = read_tour(spec)
+                    .and_then(|tour| normalize_tour(&tour, instance.destinations.len(), instance.depot))
+                    .map_err(|e| format!("{name}: {e}"))?;
+                tour.push(tour[0]);
+                let mut drawn = self.draw_tour(&client, &instance, name, tour).await?;
+                drawn.report = read_report(spec);
+                tours.push(drawn);
+            }
+            let difference = self.difference.as_deref().map(|names| difference(&instance, &tours, names)).transpose()?;
+            drawn = tours.iter().map(|t| DrawnSummary { name: t.name.clone(), distance: t.distance, duration: t.duration }).collect();
+            self.visualize_solutions(&instance, &tours, difference, trace).await
         };
-        
-        if let Some(output) = self.output.as_ref() {
-            File::create(output).unwrap().write_all(html.as_bytes()).unwrap();
-        } else {
-            println!("{html}");
-        }
+
+        write_output(output, &format!("{html}\n"))?;
+        Ok(drawn)
     }
 
     /// Bare bones visualisation: only shows the locations on the map
-    pub async fn visualize(&self, instance: &Instance) -> String {
+    pub async fn visualize(&self, instance: &Instance, trace: Option<String>) -> String {
         let template = include_str!("./visual_template.hbs");
-        let destinations = serde_json::to_string_pretty(&instance.geojson()).unwrap();
-        let handlebars = handlebars::Handlebars::new();
+        let stops = serde_json::to_string(&stops(instance, None)).unwrap();
+        let mut handlebars = handlebars::Handlebars::new();
+        handlebars.register_escape_fn(no_escape);
         handlebars.render_template(template, &json!({
-            "destinations": destinations
+            "summary": self.summary(instance, &[]),
+            "stops": stops,
+            "trace": trace,
         })).unwrap()
     }
 
-    /// More elaborate visualisation: shows locations as well as a route to join all these cities
-    pub async fn visualize_solution(&self, instance: &Instance, route: &Route) -> String {
+    /// More elaborate visualisation: shows locations (numbered in the visit order of
+    /// the first solution) as well as one toggleable layer per solution (with its
+    /// length in the legend), and the difference layer if any
+    async fn visualize_solutions(&self, instance: &Instance, tours: &[DrawnTour], difference: Option<Value>, trace: Option<String>) -> String {
         let template = include_str!("./visual_template.hbs");
-        let total_distance = route.distance;
-        let total_duration = route.duration;
-        let destinations = serde_json::to_string(&instance.geojson()).unwrap();
-        let route = serde_json::to_string(&route.geometry).unwrap();
-
-        let hours = total_duration / 3600.0;
-        let minutes = (hours - hours.floor()) * 60.0;
-        let seconds = (minutes - minutes.floor()) * 60.0;
-
-        let hours = hours.floor().to_u8().unwrap();
-        let minutes = minutes.floor().to_u8().unwrap();
-        let seconds = seconds.floor().to_u8().unwrap();
-
+        let unit = instance.metadata.unit;
+        let solutions = tours.iter().enumerate()
+            .map(|(i, t)| {
+                let color = PALETTE[i % PALETTE.len()];
+                let (length, symbol) = unit.report(instance.tour_length(&t.tour[1..]));
+                json!({
+                    "legend": format!("<span style=\"color: {color}\">&#9632;</span> {} ({length:.2} {symbol})", t.name),
+                    "color": color,
+                    "route": t.route,
+                    "totalDistance": format!("{:.2}", t.distance / 1000.0),   // in kilometers
+                    "totalDuration": format_duration(t.duration),
+                })
+            })
+            .collect::<Vec<_>>();
 
         let mut handlebars = handlebars::Handlebars::new();
         handlebars.register_escape_fn(no_escape);
         handlebars.render_template(template, &json!({
-            "destinations": destinations,
-            "route": route,
-            "totalDistance": format!("{:.2}", total_distance / 1000.0),   // in kilometers
-            "totalDuration": format!("{hours} hours {minutes} minutes {seconds} seconds"), // in hours
+            "summary": self.summary(instance, tours),
+            "stops": serde_json::to_string(&stops(instance, tours.first().map(|t| t.tour.as_slice()))).unwrap(),
+            "solutions": serde_json::to_string(&solutions).unwrap(),
+            "difference": difference.map(|d| serde_json::to_string(&d).unwrap()),
+            "trace": trace,
         })).unwrap()
     }
 
+    /// Returns the html of the summary panel describing the instance, how it was
+    /// generated and each of the solutions (with the settings of the solver which
+    /// produced it, when it was read from a solve report)
+    fn summary(&self, instance: &Instance, tours: &[DrawnTour]) -> String {
+        let mut sections = vec![];
+        let name = Path::new(&self.instance).file_name().map(|f| f.to_string_lossy().to_string()).unwrap_or_default();
+        let metadata = &instance.metadata;
+        let mut rows = vec![
+            ("instance", name),
+            ("destinations", instance.destinations.len().to_string()),
+            ("unit", format!("{:?}", metadata.unit).to_lowercase()),
+        ];
+        if let Some(seed) = metadata.seed {
+            rows.push(("seed", seed.to_string()));
+        }
+        if let Some(router) = metadata.router.as_ref() {
+            rows.push(("router", router.clone()));
+        }
+        if let Some(area) = metadata.area.as_ref().or(metadata.boundary.as_ref()) {
+            rows.push(("area", area.clone()));
+        }
+        sections.push(("Instance".to_string(), rows));
+
+        for tour in tours {
+            let (length, symbol) = metadata.unit.report(instance.tour_length(&tour.tour[1..]));
+            let mut rows = vec![
+                ("length", format!("{length:.3} {symbol}")),
+                ("road distance", format!("{:.2} km", tour.distance / 1000.0)),
+                ("road duration", format_duration(tour.duration)),
+            ];
+            if let (_, Some(duration)) = instance.tour_totals(&tour.tour[1..], metadata.unit) {
+                rows.push(("duration", format_duration((duration * 3600.0) as f32)));
+            }
+            if let Some(report) = tour.report.as_ref() {
+                let field = |v: &Value| match v {
+                    Value::String(s) => s.clone(),
+                    v => v.to_string(),
+                };
+                rows.push(("objective", field(&report["objective"])));
+                rows.push(("exact", field(&report["is_exact"])));
+                if let Some(settings) = report["settings"].as_object() {
+                    for (key, value) in settings {
+                        rows.push((key.as_str(), field(value)));
+                    }
+                }
+            }
+            sections.push((tour.name.clone(), rows));
+        }
+
+        sections.iter()
+            .map(|(title, rows)| {
+                let rows = rows.iter()
+                    .map(|(key, value)| format!("<tr><th>{}</th><td>{}</td></tr>", escape(key), escape(value)))
+                    .collect::<String>();
+                format!("<h3>{}</h3><table>{rows}</table>", escape(title))
+            })
+            .collect()
+    }
+
+    /// Reads the trace of a search and returns an inline svg chart of the incumbent
+    /// and the bound versus time. A warning is printed when the trace was recorded
+    /// for another instance.
+    fn trace_chart(&self, path: &str, instance: &Instance) -> Result<String, String> {
+        let text = read_to_string(path).map_err(|e| format!("cannot read {path}: {e}"))?;
+        let mut lines = text.lines().map(str::trim).filter(|l| !l.is_empty()).peekable();
+        while let Some(comment) = lines.next_if(|l| l.starts_with('#')) {
+            if let Some((key, value)) = comment.trim_start_matches('#').split_once(':') {
+                let expected = instance.digest();
+                if key.trim() == "digest" && value.trim() != expected {
+                    eprintln!("warning: the trace {path} was recorded for another instance (digest {}, expected {expected})", value.trim());
+                }
+            }
+        }
+
+        let header = lines.next().ok_or_else(|| format!("{path} holds no trace"))?;
+        let columns = header.split(',').map(str::trim).collect::<Vec<_>>();
+        let column = |name: &str| columns.iter().position(|c| *c == name)
+            .ok_or_else(|| format!("there is no column '{name}' in {path}"));
+        let (time, incumbent, bound) = (column("time")?, column("incumbent")?, column("bound")?);
+
+        let mut series = [vec![], vec![]];
+        for line in lines {
+            let row = line.split(',').map(str::trim).collect::<Vec<_>>();
+            let value = |c: usize| row.get(c).and_then(|v| v.parse::<f64>().ok()).filter(|v| v.is_finite());
+            let Some(t) = value(time) else { continue };
+            for (serie, c) in series.iter_mut().zip([incumbent, bound]) {
+                if let Some(v) = value(c).filter(|v| !self.trace_log || *v > 0.0) {
+                    serie.push((t, if self.trace_log { v.log10() } else { v }));
+                }
+            }
+        }
+        Ok(svg_chart(&series, ["incumbent", "bound"], ["red", "#3366ff"], self.trace_log))
+    }
+
+    /// Computes the route followed by the given closed tour
+    async fn draw_tour(&self, client: &Client, instance: &Instance, name: String, tour: Vec<usize>) -> Result<DrawnTour, String> {
+        if let Some(cache) = self.leg_cache.as_ref() {
+            let legs = self.solution_legs(client, instance, &tour, cache).await?;
+            let distance = legs.iter().map(|l| l.distance).sum();
+            let duration = legs.iter().map(|l| l.duration).sum();
+            let geometries = legs.into_iter().map(|l| l.geometry).collect::<Vec<_>>();
+            Ok(DrawnTour { name, tour, distance, duration, route: json!(geometries), report: None })
+        } else {
+            let route = self.solution_route(client, instance, &tour).await?;
+            Ok(DrawnTour { name, tour, distance: route.distance, duration: route.duration, route: json!(route.geometry), report: None })
+        }
+    }
+
     /// Computes the actual route based on the locations ordering
-    async fn solution_route(&self, client: &Client, instance: &Instance, solution: &[usize]) -> Route {
+    async fn solution_route(&self, client: &Client, instance: &Instance, solution: &[usize]) -> Result<Route, String> {
         let path = solution.iter().copied()
                 .map(|i| instance.destinations[i])
                 .collect();
@@ -100,7 +311,185 @@ impl Visualize {
             .build()
             .unwrap()
             .send(client).await
-            .unwrap();
-        response.routes[0].clone()
+            .map_err(|_| "cannot fetch the route of the solution".to_string())?;
+        response.routes.into_iter().next().ok_or_else(|| "there is no route through the destinations of the solution".to_string())
+    }
+
+    /// Computes the road geometry of each leg of the solution. The legs are read
+    /// from the cache file when possible and routed otherwise. A leg which cannot
+    /// be routed is replaced by a straight line (which is not cached).
+    async fn solution_legs(&self, client: &Client, instance: &Instance, solution: &[usize], cache: &str) -> Result<Vec<Leg>, String> {
+        let mut cached: BTreeMap<String, Leg> = if Path::new(cache).exists() {
+            let text = read_to_string(cache).map_err(|e| format!("cannot read {cache}: {e}"))?;
+            serde_json::from_str(&text).map_err(|e| format!("invalid leg cache {cache}: {e}"))?
+        } else {
+            BTreeMap::new()
+        };
+
+        let mut legs = vec![];
+        let mut nb_straight = 0;
+        for pair in solution.windows(2) {
+            let (from, to) = (instance.destinations[pair[0]], instance.destinations[pair[1]]);
+            let key = format!("{:.6},{:.6};{:.6},{:.6}", from.longitude, from.latitude, to.longitude, to.latitude);
+            if let Some(leg) = cached.get(&key) {
+                legs.push(leg.clone());
+                continue;
+            }
+            match self.leg_route(client, from, to).await {
+                Some(route) => {
+                    let leg = Leg { distance: route.distance, duration: route.duration, geometry: route.geometry };
+                    cached.insert(key, leg.clone());
+                    legs.push(leg);
+                }
+                None => {
+                    nb_straight += 1;
+                    let coordinates = vec![GeoJsonPoint::from(from), GeoJsonPoint::from(to)];
+                    legs.push(Leg { distance: 0.0, duration: 0.0, geometry: GeoJsonGeometry::LineString { coordinates } });
+                }
+            }
+        }
+        if nb_straight > 0 {
+            eprintln!("warning: {nb_straight} legs could not be routed, they are drawn as straight lines and left out of the totals");
+        }
+
+        let text = serde_json::to_string(&cached).unwrap();
+        File::create(cache).and_then(|mut f| f.write_all(text.as_bytes()))
+            .map_err(|e| format!("cannot write {cache}: {e}"))?;
+        Ok(legs)
+    }
+
+    /// Computes the route between two locations (None when it cannot be routed)
+    async fn leg_route(&self, client: &Client, from: Location, to: Location) -> Option<Route> {
+        let response = RouteRequestBuilder::default()
+            .coordinates(osrm_client::Coordinates::Multi(vec![from, to]))
+            .geometries(Geometries::GeoJson)
+            .overview(OverviewRequest::Full)
+            .build()
+            .ok()?
+            .send(client).await
+            .ok()?;
+        response.routes.into_iter().next()
+    }
+}
+
+/// Returns the markers of the destinations: their position, popup and, when a
+/// (closed) tour is given, their position in the visit order. The depot is
+/// flagged so that it can be drawn differently.
+fn stops(instance: &Instance, tour: Option<&[usize]>) -> Vec<Value> {
+    let unit = instance.metadata.unit;
+    let name = |d: usize| match instance.labels.as_ref() {
+        Some(labels) => format!("{d} &ndash; {}", escape(&labels[d])),
+        None => format!("destination {d}"),
+    };
+    let marker = |d: usize, order: Option<usize>, popup: String| {
+        let Location { longitude, latitude } = instance.destinations[d];
+        json!({"lon": longitude, "lat": latitude, "depot": d == instance.depot, "order": order, "popup": popup})
+    };
+
+    let Some(tour) = tour else {
+        return (0..instance.destinations.len()).map(|d| marker(d, None, format!("<b>{}</b>", name(d)))).collect();
+    };
+    let mut stops = vec![];
+    let mut cumulative = 0.0;
+    let mut last_leg = 0.0;
+    for (order, pair) in tour.windows(2).enumerate() {
+        if order > 0 {
+            let (leg, symbol) = unit.report(last_leg);
+            let (total, _) = unit.report(cumulative);
+            let popup = format!("<b>{}</b><br/>stop {order}<br/>leg {leg:.3} {symbol}<br/>cumulative {total:.3} {symbol}", name(pair[0]));
+            stops.push(marker(pair[0], Some(order), popup));
+        }
+        last_leg = instance.distances[pair[0]][pair[1]] as f64;
+        cumulative += last_leg;
+    }
+    let (leg, symbol) = unit.report(last_leg);
+    let (total, _) = unit.report(cumulative);
+    let popup = format!("<b>{}</b><br/>start<br/>end: leg {leg:.3} {symbol}, cumulative {total:.3} {symbol}", name(tour[0]));
+    stops.insert(0, marker(tour[0], Some(0), popup));
+    stops
+}
+
+/// Draws the given series of (x, y) points as polylines in an inline svg chart
+fn svg_chart(series: &[Vec<(f64, f64)>], names: [&str; 2], colors: [&str; 2], log: bool) -> String {
+    const WIDTH: f64 = 800.0;
+    const HEIGHT: f64 = 200.0;
+    const MARGIN: f64 = 50.0;
+    let points = series.iter().flatten();
+    let (x_min, x_max, y_min, y_max) = points.fold((f64::INFINITY, f64::NEG_INFINITY, f64::INFINITY, f64::NEG_INFINITY),
+        |(x0, x1, y0, y1), (x, y)| (x0.min(*x), x1.max(*x), y0.min(*y), y1.max(*y)));
+    if !x_min.is_finite() {
+        return "<p>the trace is empty</p>".to_string();
+    }
+    let span = |min: f64, max: f64| if max > min { max - min } else { 1.0 };
+    let x = |v: f64| MARGIN + (v - x_min) / span(x_min, x_max) * (WIDTH - 2.0 * MARGIN);
+    let y = |v: f64| HEIGHT - MARGIN / 2.0 - (v - y_min) / span(y_min, y_max) * (HEIGHT - MARGIN);
+    let value = |v: f64| if log { format!("{:.4}", 10_f64.powf(v)) } else { format!("{v:.4}") };
+
+    let mut svg = format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{WIDTH}\" height=\"{HEIGHT}\" font-size=\"11\" font-family=\"sans-serif\">");
+    svg.push_str(&format!("<line x1=\"{MARGIN}\" y1=\"{0}\" x2=\"{1}\" y2=\"{0}\" stroke=\"black\"/>", HEIGHT - MARGIN / 2.0, WIDTH - MARGIN));
+    svg.push_str(&format!("<line x1=\"{MARGIN}\" y1=\"{0}\" x2=\"{MARGIN}\" y2=\"{1}\" stroke=\"black\"/>", HEIGHT - MARGIN / 2.0, MARGIN / 2.0));
+    svg.push_str(&format!("<text x=\"{MARGIN}\" y=\"{HEIGHT}\">{x_min:.1} s</text>"));
+    svg.push_str(&format!("<text x=\"{0}\" y=\"{HEIGHT}\" text-anchor=\"end\">{x_max:.1} s</text>", WIDTH - MARGIN));
+    svg.push_str(&format!("<text x=\"2\" y=\"{0}\">{1}</text>", HEIGHT - MARGIN / 2.0, value(y_min)));
+    svg.push_str(&format!("<text x=\"2\" y=\"{0}\">{1}</text>", MARGIN / 2.0, value(y_max)));
+    for (i, serie) in series.iter().enumerate() {
+        let path = serie.iter().map(|(a, b)| format!("{:.1},{:.1}", x(*a), y(*b))).collect::<Vec<_>>().join(" ");
+        svg.push_str(&format!("<polyline points=\"{path}\" fill=\"none\" stroke=\"{}\" stroke-width=\"2\"/>", colors[i]));
+        svg.push_str(&format!("<text x=\"{0}\" y=\"{1}\" fill=\"{2}\" text-anchor=\"end\">{3}</text>",
+            WIDTH - MARGIN, MARGIN / 2.0 + 12.0 * i as f64, colors[i], names[i]));
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+/// Reads the solve report a tour comes from, when the tour is read from a json file
+fn read_report(spec: &str) -> Option<Value> {
+    let text = read_to_string(spec).ok()?;
+    if text.trim_start().starts_with('{') { serde_json::from_str(&text).ok() } else { None }
+}
+
+/// Escapes the characters of a text which have a meaning in html
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// Formats a duration given in seconds as hours, minutes and seconds
+fn format_duration(duration: f32) -> String {
+    let hours = duration / 3600.0;
+    let minutes = (hours - hours.floor()) * 60.0;
+    let seconds = (minutes - minutes.floor()) * 60.0;
+
+    let hours = hours.floor().to_u32().unwrap();
+    let minutes = minutes.floor().to_u8().unwrap();
+    let seconds = seconds.floor().to_u8().unwrap();
+    format!("{hours} hours {minutes} minutes {seconds} seconds")
+}
+
+/// Returns the geojson features of the (undirected) edges used by exactly one of
+/// the two solutions named in `names` (as `a,b`), drawn as straight lines. Each
+/// feature records in its properties the solution which uses it.
+fn difference(instance: &Instance, tours: &[DrawnTour], names: &str) -> Result<Value, String> {
+    let (a, b) = names.split_once(',').ok_or("the difference needs two solutions, given as a,b")?;
+    let find = |name: &str| tours.iter().find(|t| t.name == name.trim())
+        .ok_or_else(|| format!("there is no solution named '{}'", name.trim()));
+    let (a, b) = (find(a)?, find(b)?);
+
+    let edges = |tour: &DrawnTour| {
+        let mut edges = tour.tour.windows(2).map(|e| (e[0].min(e[1]), e[0].max(e[1]))).collect::<Vec<_>>();
+        edges.sort_unstable();
+        edges
+    };
+    let (edges_a, edges_b) = (edges(a), edges(b));
+    let mut features = vec![];
+    for (tour, own, other) in [(a, &edges_a, &edges_b), (b, &edges_b, &edges_a)] {
+        for (x, y) in own.iter().filter(|e| other.binary_search(e).is_err()) {
+            let coordinates = vec![GeoJsonPoint::from(instance.destinations[*x]), GeoJsonPoint::from(instance.destinations[*y])];
+            features.push(json!({
+                "type": "Feature",
+                "properties": {"solution": tour.name},
+                "geometry": GeoJsonGeometry::LineString { coordinates },
+            }));
+        }
     }
-}
\ No newline at end of file
+    Ok(json!({"type": "FeatureCollection", "features": features, "a": a.name, "b": b.name}))
+}
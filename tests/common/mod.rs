@@ -0,0 +1,18 @@
+//! The helpers shared by the integration tests
+
+use osrm_client::Location;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaChaRng;
+
+use tsptools::instance::{Instance, Unit};
+
+/// A random asymmetric instance of `n` destinations whose distances are whole
+/// metres, drawn from the given seed
+pub fn random_instance(n: usize, seed: u64) -> Instance {
+    let mut rng = ChaChaRng::seed_from_u64(seed);
+    let destinations = (0..n).map(|i| Location { longitude: i as f32, latitude: 0.0 }).collect();
+    let distances = (0..n)
+        .map(|i| (0..n).map(|j| if i == j { 0.0 } else { rng.gen_range(1..1000) as f32 }).collect())
+        .collect();
+    Instance::from_distances(destinations, distances, Unit::Meters).unwrap()
+}
@@ -0,0 +1,35 @@
+//! Checks that the deterministic runs of the solver can be reproduced
+
+use osrm_client::Location;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaChaRng;
+
+use tsptools::{instance::{Instance, Unit}, resolution::{SolveOptions, solve_instance}};
+
+/// An asymmetric instance of `n` destinations whose distances take only a few
+/// values: many tours tie with the optimal one
+fn tied_instance(n: usize, seed: u64) -> Instance {
+    let mut rng = ChaChaRng::seed_from_u64(seed);
+    let destinations = (0..n).map(|i| Location { longitude: i as f32, latitude: 0.0 }).collect();
+    let distances = (0..n)
+        .map(|i| (0..n).map(|j| if i == j { 0.0 } else { rng.gen_range(1..4) as f32 }).collect())
+        .collect::<Vec<_>>();
+    Instance::from_distances(destinations, distances.into(), Unit::Meters).unwrap()
+}
+
+#[test]
+fn a_deterministic_run_is_reproduced() {
+    // a narrow width makes the search branch on many nodes
+    let options = SolveOptions::builder().width(12).deterministic(true).timeout(0).build().unwrap();
+    let instance = tied_instance(16, 108);
+    let first = solve_instance("tied", instance.clone(), &options).unwrap();
+    let second = solve_instance("tied", instance, &options).unwrap();
+
+    assert!(first.tour.is_some());
+    assert!(first.explored_nodes > 1);
+    assert_eq!(first.explored_nodes, second.explored_nodes);
+    assert_eq!(first.tour, second.tour);
+    assert_eq!(first.value, second.value);
+    assert_eq!(first.bound, second.bound);
+    assert_eq!(first.is_exact, second.is_exact);
+}
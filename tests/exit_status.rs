@@ -0,0 +1,65 @@
+//! Checks the exit statuses of the commands (mostly solve), which the scripts
+//! calling them rely on to tell the outcomes apart.
+
+mod common;
+
+use std::path::{Path, PathBuf};
+
+use assert_cmd::Command;
+use tempfile::TempDir;
+
+/// Saves a random instance of `n` destinations in the given directory and
+/// returns its path
+fn random_instance(dir: &Path, n: usize) -> PathBuf {
+    let path = dir.join(format!("random-{n}.json"));
+    common::random_instance(n, n as u64).save(Some(path.to_str().unwrap())).unwrap();
+    path
+}
+
+fn solve(instance: &Path, flags: &[&str]) -> Command {
+    let mut command = Command::cargo_bin("tsptools").unwrap();
+    command.arg("solve").arg("-i").arg(instance).args(flags);
+    command
+}
+
+#[test]
+fn an_optimal_tour_exits_with_0() {
+    let dir = TempDir::new().unwrap();
+    let instance = random_instance(dir.path(), 6);
+    solve(&instance, &["-t", "60"]).assert().code(0);
+}
+
+#[test]
+fn a_run_stopped_by_its_timeout_exits_with_2() {
+    // a timeout of 0 removes the time limit: the shortest budget is one second,
+    // which is far from enough to prove a tour of 60 destinations optimal
+    let dir = TempDir::new().unwrap();
+    let instance = random_instance(dir.path(), 60);
+    solve(&instance, &["-t", "1"]).assert().code(2);
+}
+
+#[test]
+fn an_instance_without_admissible_legs_exits_with_4() {
+    let dir = TempDir::new().unwrap();
+    let instance = random_instance(dir.path(), 6);
+    solve(&instance, &["--max-leg", "0.5"]).assert().code(4);
+}
+
+#[test]
+fn a_malformed_instance_exits_with_5() {
+    let dir = TempDir::new().unwrap();
+    let instance = dir.path().join("malformed.json");
+    std::fs::write(&instance, r#"{"destinations": [{"longitude": 4.3"#).unwrap();
+    solve(&instance, &[]).assert().code(5);
+}
+
+#[test]
+fn an_unreachable_router_exits_with_5() {
+    // nothing listens on the discard port: the router cannot be reached
+    let dir = TempDir::new().unwrap();
+    let instance = random_instance(dir.path(), 6);
+    let mut command = Command::cargo_bin("tsptools").unwrap();
+    command.arg("refresh").arg("-i").arg(&instance).args(["-u", "http://127.0.0.1:9"]);
+    let output = command.assert().code(5).get_output().clone();
+    assert!(String::from_utf8_lossy(&output.stderr).contains("cannot fetch the travel matrices"));
+}
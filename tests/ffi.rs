@@ -0,0 +1,27 @@
+//! Links the C harness compiled by the build script against the static library
+//! and runs it, which checks the interface declared in `include/tsptools.h` from
+//! C. The native libraries needed by the standard library are those of Linux.
+#![cfg(all(feature = "ffi", target_os = "linux"))]
+
+use std::{path::Path, process::Command};
+
+#[test]
+fn the_c_interface_solves_an_instance() {
+    // the static library is built along with the tests, next to them
+    let tests = std::env::current_exe().unwrap().parent().unwrap().to_path_buf();
+    let library = tests.join("libtsptools.a");
+    assert!(library.exists(), "{} was not built", library.display());
+    let harness = Path::new(env!("CARGO_TARGET_TMPDIR")).join("tsptools-harness");
+
+    let linked = Command::new(env!("TSPTOOLS_CC"))
+        .arg(env!("TSPTOOLS_HARNESS_OBJECT"))
+        .arg(&library)
+        .args(["-lpthread", "-ldl", "-lm", "-lrt", "-lutil", "-o"])
+        .arg(&harness)
+        .status()
+        .unwrap();
+    assert!(linked.success(), "cannot link the C harness");
+
+    let run = Command::new(&harness).status().unwrap();
+    assert!(run.success(), "the C harness failed");
+}
@@ -0,0 +1,50 @@
+/* Solves a small instance through the C interface of include/tsptools.h and
+ * exits with a non zero status when a call does not behave as documented. */
+#include <stdio.h>
+
+#include "tsptools.h"
+
+#define N 5
+
+#define CHECK(condition) \
+    if (!(condition)) { fprintf(stderr, "%s:%d: %s does not hold\n", __FILE__, __LINE__, #condition); return 1; }
+
+int main(void) {
+    /* the ring 0 -> 1 -> 2 -> 3 -> 4 -> 0 is the only tour of length N */
+    double distances[N * N];
+    for (size_t i = 0; i < N; i++) {
+        for (size_t j = 0; j < N; j++) {
+            distances[i * N + j] = i == j ? 0.0 : j == (i + 1) % N ? 1.0 : 10.0;
+        }
+    }
+
+    TspInstance *instance = NULL;
+    CHECK(tsp_instance_new(NULL, N, NULL, &instance) == TSP_NULL_POINTER);
+    CHECK(tsp_instance_new(distances, N, NULL, &instance) == TSP_OK);
+    CHECK(instance != NULL);
+
+    TspOptions options;
+    CHECK(tsp_options_default(&options) == TSP_OK);
+    options.timeout = 0;
+    options.deterministic = 1;
+
+    size_t tour[N];
+    size_t tour_len = 0;
+    double value = 0.0;
+    int32_t is_exact = 0;
+    CHECK(tsp_solve(instance, &options, tour, 2, &tour_len, &value, &is_exact) == TSP_BUFFER_TOO_SMALL);
+    CHECK(tour_len == N);
+    CHECK(tsp_solve(instance, &options, tour, N, &tour_len, &value, &is_exact) == TSP_OK);
+    CHECK(tour_len == N);
+    CHECK(is_exact);
+    CHECK(value == (double) N);
+    for (size_t i = 0; i < N; i++) {
+        CHECK(tour[i] == (i + 1) % N);
+    }
+
+    options.width = 0;
+    CHECK(tsp_solve(instance, &options, tour, N, &tour_len, &value, &is_exact) == TSP_INVALID_OPTIONS);
+
+    tsp_instance_free(instance);
+    return 0;
+}
@@ -0,0 +1,30 @@
+//! Checks that the quiet mode only prints machine readable output on stdout
+
+use assert_cmd::Command;
+use tempfile::TempDir;
+
+const SMALL: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/small.json");
+
+#[test]
+fn a_quiet_visualisation_written_to_a_file_prints_a_json_summary() {
+    let dir = TempDir::new().unwrap();
+    let html = dir.path().join("small.html");
+
+    let mut command = Command::cargo_bin("tsptools").unwrap();
+    command.args(["-q", "visualize", "-i", SMALL, "-o"]).arg(&html);
+    let output = command.assert().code(0).get_output().clone();
+    let summary: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(summary["output"], html.to_str().unwrap());
+    assert_eq!(summary["solutions"], serde_json::json!([]));
+    assert!(std::fs::read_to_string(&html).unwrap().contains("<html"));
+}
+
+#[test]
+fn a_quiet_visualisation_on_stdout_only_prints_the_html() {
+    let mut command = Command::cargo_bin("tsptools").unwrap();
+    command.args(["-q", "visualize", "-i", SMALL]);
+    let output = command.assert().code(0).get_output().clone();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.trim_start().starts_with("<"), "{stdout}");
+    assert!(!stdout.contains("wrote the visualisation"));
+}
@@ -0,0 +1,23 @@
+//! Checks the VROOM problems written by the export against golden files
+
+use std::fs::read_to_string;
+
+use tsptools::instance::{Instance, vroom};
+
+#[test]
+fn a_small_instance_is_exported_as_its_golden_vroom_problem() {
+    let instance = Instance::load(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/small.json")).unwrap();
+    let exported = vroom::write(&instance, true).unwrap();
+    let golden = read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/small.vroom.json")).unwrap();
+    assert_eq!(exported.trim_end(), golden.trim_end());
+}
+
+#[test]
+fn a_job_without_index_in_the_custom_matrix_is_rejected() {
+    let problem = r#"{
+        "vehicles": [{"id": 1, "start_index": 0, "end_index": 0}],
+        "jobs": [{"id": 1, "location_index": 1}, {"id": 2, "location": [4.70, 50.88]}],
+        "matrix": [[0, 10, 20], [10, 0, 15], [20, 15, 0]]
+    }"#;
+    assert_eq!(vroom::read(problem).err().unwrap(), "job 2 has no location index in the custom matrices");
+}
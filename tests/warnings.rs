@@ -0,0 +1,22 @@
+//! Checks that the solve command warns about the settings it changes
+
+mod common;
+
+use assert_cmd::Command;
+use tempfile::TempDir;
+
+#[test]
+fn a_raised_width_is_warned_about_and_reported() {
+    let dir = TempDir::new().unwrap();
+    let instance = dir.path().join("random.json");
+    common::random_instance(8, 202).save(Some(instance.to_str().unwrap())).unwrap();
+
+    let mut command = Command::cargo_bin("tsptools").unwrap();
+    command.arg("solve").arg("-i").arg(&instance).args(["--width", "2", "--json"]);
+    let output = command.assert().code(0).get_output().clone();
+    let warning = "warning: the width 2 is smaller than the largest domain (7), it is raised to 7";
+    assert!(String::from_utf8_lossy(&output.stderr).lines().any(|line| line == warning));
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["settings"]["width"], 7);
+    assert_eq!(report["settings"]["adjustments"][0], "the width 2 is smaller than the largest domain (7), it is raised to 7");
+}